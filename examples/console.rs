@@ -1,8 +1,9 @@
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
-use libweb::console_log;
+use libweb::{console, console_log};
 
 fn main() {
+    console::set_panic_hook();
     let world = "world";
     console_log!("hello {}", world);
 }
@@ -10,6 +11,5 @@ fn main() {
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen(start)]
 pub fn run() {
-    console_error_panic_hook::set_once();
     main()
 }
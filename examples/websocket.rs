@@ -60,6 +60,7 @@ impl Handler for PongHandler {
 }
 
 fn main() -> Result<()> {
+    console::set_panic_hook();
     connect::<PongHandler>("ws://127.0.0.1:3012")?;
     Ok(())
 }
@@ -67,6 +68,5 @@ fn main() -> Result<()> {
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen(start)]
 pub fn run() {
-    console_error_panic_hook::set_once();
     main().unwrap();
 }
@@ -1,10 +1,13 @@
+// `ws::Error` is large, but that is out of our control.
+#![allow(clippy::result_large_err)]
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
-use libweb::console_log;
+use libweb::{console, console_log};
 
 #[cfg(not(target_arch = "wasm32"))]
 mod server {
     use super::*;
+    use libweb::websocket::{RateLimit, RateLimiter, Throttle};
     use ws::listen;
 
     pub fn main() {
@@ -15,13 +18,21 @@ mod server {
         if let Err(error) = listen("127.0.0.1:3012", |out| {
             console_log!("Server got connection");
 
+            // Allow each connection to send 10 messages per second
+            let limiter = RateLimiter::new(RateLimit::per_second(10, Throttle::Error));
+
             // The handler needs to take ownership of out, so we use move
             move |msg| {
-                // Handle messages received on this connection
-                console_log!("Server got message '{}'. ", msg);
+                // Close connections exceeding the rate limit
+                if !limiter.try_acquire() {
+                    out.close(ws::CloseCode::Policy)
+                } else {
+                    // Handle messages received on this connection
+                    console_log!("Server got message '{}'. ", msg);
 
-                // Use the out channel to send messages back
-                out.send(msg)
+                    // Use the out channel to send messages back
+                    out.send(msg)
+                }
             }
         }) {
             // Inform the user of failure
@@ -31,6 +42,7 @@ mod server {
 }
 
 fn main() {
+    console::set_panic_hook();
     #[cfg(not(target_arch = "wasm32"))]
     server::main();
     #[cfg(target_arch = "wasm32")]
@@ -40,6 +52,5 @@ fn main() {
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen(start)]
 pub fn run() {
-    console_error_panic_hook::set_once();
     main()
 }
@@ -0,0 +1,65 @@
+//! Clocks that work on both targets.
+use std::time::{Duration, SystemTime};
+
+/// Milliseconds elapsed on a monotonic clock.
+///
+/// Uses `performance.now` when available, which also exists in workers.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn now() -> f64 {
+    match performance() {
+        Some(performance) => performance.now(),
+        None => js_sys::Date::now(),
+    }
+}
+
+/// Returns `performance` from the global scope, which is the window or a
+/// worker.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn performance() -> Option<web_sys::Performance> {
+    use wasm_bindgen::JsCast;
+    js_sys::Reflect::get(&js_sys::global(), &"performance".into())
+        .ok()
+        .and_then(|performance| performance.dyn_into::<web_sys::Performance>().ok())
+}
+
+/// The time origin of `now` on desktop.
+#[cfg(not(target_arch = "wasm32"))]
+static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+
+/// Starts the clock on desktop if it wasn't used yet.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn init() {
+    START.get_or_init(std::time::Instant::now);
+}
+
+/// Milliseconds elapsed on a monotonic clock since it was first used.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn now() -> f64 {
+    millis(START.get_or_init(std::time::Instant::now).elapsed())
+}
+
+/// The current wall clock time.
+///
+/// `SystemTime::now` panics on `wasm32-unknown-unknown`.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn system_time() -> SystemTime {
+    std::time::UNIX_EPOCH + from_millis(js_sys::Date::now())
+}
+
+/// The current wall clock time.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn system_time() -> SystemTime {
+    SystemTime::now()
+}
+
+/// Converts a duration to fractional milliseconds.
+#[inline]
+pub(crate) fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1_000_000.0
+}
+
+/// Converts fractional milliseconds to a duration.
+#[inline]
+pub(crate) fn from_millis(millis: f64) -> Duration {
+    Duration::from_micros((millis.max(0.0) * 1000.0).round() as u64)
+}
@@ -0,0 +1,246 @@
+#[cfg(feature = "serde")]
+use crate::Result;
+use crate::console::Level;
+use std::fmt;
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::JsValue;
+
+/// An argument passed to the console without stringifying it first, so the
+/// browser's devtools can inspect it.
+#[derive(Debug, Clone)]
+pub enum Arg {
+    /// A string.
+    Str(String),
+    /// A number.
+    Number(f64),
+    /// A boolean.
+    Bool(bool),
+    /// A JavaScript value.
+    #[cfg(target_arch = "wasm32")]
+    Js(JsValue),
+    /// A serialized value. In the browser it is converted to a JavaScript
+    /// object, on desktop it is printed as JSON.
+    #[cfg(feature = "serde")]
+    Json(serde_json::Value),
+    /// A value formatted using `Debug`.
+    Debug(String),
+}
+
+impl Arg {
+    /// Creates an argument from a value implementing `Debug`.
+    pub fn debug<T: fmt::Debug + ?Sized>(value: &T) -> Self {
+        Arg::Debug(format!("{:?}", value))
+    }
+
+    /// Creates an argument from a value implementing `serde::Serialize`.
+    #[cfg(feature = "serde")]
+    pub fn json<T: serde::Serialize + ?Sized>(value: &T) -> Result<Self> {
+        Ok(Arg::Json(serde_json::to_value(value)?))
+    }
+
+    /// Formats the argument, pretty-printing serialized values.
+    pub(crate) fn pretty(&self) -> String {
+        match self {
+            #[cfg(feature = "serde")]
+            Arg::Json(value) => {
+                serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string())
+            }
+            arg => arg.to_string(),
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn to_js_value(&self) -> JsValue {
+        match self {
+            Arg::Str(string) => JsValue::from(string.as_str()),
+            Arg::Number(number) => JsValue::from(*number),
+            Arg::Bool(boolean) => JsValue::from(*boolean),
+            Arg::Js(value) => value.clone(),
+            #[cfg(feature = "serde")]
+            Arg::Json(value) => js_sys::JSON::parse(&value.to_string())
+                .unwrap_or_else(|_| JsValue::from(value.to_string())),
+            Arg::Debug(string) => JsValue::from(string.as_str()),
+        }
+    }
+}
+
+impl fmt::Display for Arg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Arg::Str(string) => write!(f, "{}", string),
+            Arg::Number(number) => write!(f, "{}", number),
+            Arg::Bool(boolean) => write!(f, "{}", boolean),
+            #[cfg(target_arch = "wasm32")]
+            Arg::Js(value) => write!(f, "{:?}", value),
+            #[cfg(feature = "serde")]
+            Arg::Json(value) => write!(f, "{}", value),
+            Arg::Debug(string) => write!(f, "{}", string),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Arg {
+    fn from(string: &'a str) -> Self {
+        Arg::Str(string.to_string())
+    }
+}
+
+impl From<String> for Arg {
+    fn from(string: String) -> Self {
+        Arg::Str(string)
+    }
+}
+
+impl From<bool> for Arg {
+    fn from(boolean: bool) -> Self {
+        Arg::Bool(boolean)
+    }
+}
+
+macro_rules! impl_from_number {
+    ($($ty: ty),*) => {
+        $(
+            impl From<$ty> for Arg {
+                fn from(number: $ty) -> Self {
+                    Arg::Number(number as f64)
+                }
+            }
+        )*
+    }
+}
+
+impl_from_number!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64);
+
+#[cfg(target_arch = "wasm32")]
+impl From<JsValue> for Arg {
+    fn from(value: JsValue) -> Self {
+        Arg::Js(value)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<serde_json::Value> for Arg {
+    fn from(value: serde_json::Value) -> Self {
+        Arg::Json(value)
+    }
+}
+
+/// Builds a message styled with CSS using `%c` directives.
+///
+/// ```
+/// use libweb::console::{self, Styled};
+///
+/// let args = Styled::new()
+///     .text("libweb", "color: white; background: purple")
+///     .text(" ready", "")
+///     .into_args();
+/// console::log_args(&args);
+/// ```
+///
+/// On desktop the styles are ignored.
+#[derive(Debug, Default, Clone)]
+pub struct Styled {
+    format: String,
+    styles: Vec<Arg>,
+}
+
+impl Styled {
+    /// Creates an empty styled message.
+    pub fn new() -> Self {
+        Styled::default()
+    }
+
+    /// Appends text styled with `css`.
+    pub fn text(mut self, text: &str, css: &str) -> Self {
+        self.format.push_str("%c");
+        self.format.push_str(text);
+        self.styles.push(Arg::from(css));
+        self
+    }
+
+    /// Returns the format string followed by the styles.
+    pub fn into_args(self) -> Vec<Arg> {
+        let mut args = vec![Arg::Str(self.format)];
+        args.extend(self.styles);
+        args
+    }
+}
+
+/// Logs the arguments at a level.
+pub fn print_args(level: Level, args: &[Arg]) {
+    #[cfg(target_arch = "wasm32")]
+    super::browser::print_args(level, args);
+    #[cfg(not(target_arch = "wasm32"))]
+    super::print(level, &join(args));
+}
+
+/// Logs the arguments.
+#[inline]
+pub fn log_args(args: &[Arg]) {
+    print_args(Level::Log, args);
+}
+
+/// Joins the arguments with spaces, removing `%c` directives and their
+/// styles.
+pub(crate) fn join(args: &[Arg]) -> String {
+    let mut args = args.iter();
+    let mut parts = Vec::new();
+    if let Some(first) = args.next() {
+        match first {
+            Arg::Str(format) if format.contains("%c") => {
+                parts.push(format.replace("%c", ""));
+                for _ in 0..format.matches("%c").count() {
+                    args.next();
+                }
+            }
+            arg => parts.push(arg.to_string()),
+        }
+    }
+    parts.extend(args.map(ToString::to_string));
+    parts.join(" ")
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join() {
+        let args = [Arg::from("count"), Arg::from(3), Arg::from(true), Arg::debug(&Some(1.5))];
+        assert_eq!(join(&args), "count 3 true Some(1.5)");
+        assert_eq!(join(&[]), "");
+    }
+
+    #[test]
+    fn test_styled() {
+        let mut args = Styled::new()
+            .text("libweb", "color: purple")
+            .text(" ready", "")
+            .into_args();
+        assert_eq!(args.len(), 3);
+        assert_eq!(args[0].to_string(), "%clibweb%c ready");
+        args.push(Arg::from(42));
+        assert_eq!(join(&args), "libweb ready 42");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json() {
+        let arg = Arg::json(&vec![("a", 1)]).unwrap();
+        assert_eq!(join(&[Arg::from("data"), arg]), "data [[\"a\",1]]");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_pretty() {
+        let arg = Arg::json(&vec![1, 2]).unwrap();
+        assert_eq!(arg.pretty(), "[\n  1,\n  2\n]");
+        assert_eq!(Arg::from("text").pretty(), "text");
+    }
+
+    #[test]
+    fn test_console_log_args() {
+        console_log_args!("hello", 1, 2.5, false);
+        console_log_args!();
+    }
+}
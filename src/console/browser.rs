@@ -1,36 +1,273 @@
-/// Outputs a message to the web console.
-#[macro_export]
-macro_rules! console_log {
-    ($($arg: tt)*) => (
-        let format = format!($($arg)*);
-        let value = wasm_bindgen::JsValue::from(format.as_str());
-        web_sys::console::log_1(&value);
-    )
-}
-
-/// Outputs an error message to the web console.
-#[macro_export]
-macro_rules! console_error {
-    ($($arg: tt)*) => (
-        let format = format!($($arg)*);
-        let value = wasm_bindgen::JsValue::from(format.as_str());
-        web_sys::console::error_1(&value);
-    )
+use crate::console::{args, sink, Arg, Level};
+#[cfg(feature = "serde")]
+use crate::{js_value_to_error, Result};
+use wasm_bindgen::prelude::*;
+use web_sys::console;
+
+#[wasm_bindgen]
+extern "C" {
+    type Error;
+
+    #[wasm_bindgen(constructor)]
+    fn new() -> Error;
+
+    #[wasm_bindgen(structural, method, getter)]
+    fn stack(error: &Error) -> String;
+}
+
+/// Returns the current JavaScript stack trace.
+pub(crate) fn stack() -> String {
+    Error::new().stack()
+}
+
+#[inline]
+pub(crate) fn print(level: Level, msg: &str) {
+    if sink::write(level, msg) {
+        return;
+    }
+    let value = JsValue::from(msg);
+    match level {
+        Level::Trace => console::trace_1(&value),
+        Level::Debug => console::debug_1(&value),
+        Level::Log => console::log_1(&value),
+        Level::Info => console::info_1(&value),
+        Level::Warn => console::warn_1(&value),
+        Level::Error => console::error_1(&value),
+    }
+}
+
+pub(crate) fn print_args(level: Level, args: &[Arg]) {
+    if sink::write(level, &args::join(args)) {
+        return;
+    }
+    let args = args.iter().map(Arg::to_js_value).collect::<js_sys::Array>();
+    match level {
+        Level::Trace => console::trace(&args),
+        Level::Debug => console::debug(&args),
+        Level::Log => console::log(&args),
+        Level::Info => console::info(&args),
+        Level::Warn => console::warn(&args),
+        Level::Error => console::error(&args),
+    }
+}
+
+#[inline]
+pub(crate) fn assert(msg: &str) {
+    let captured = match msg {
+        "" => sink::write(Level::Error, "Assertion failed"),
+        msg => sink::write(Level::Error, &format!("Assertion failed: {}", msg)),
+    };
+    if captured {
+        return;
+    }
+    if msg.is_empty() {
+        console::assert_with_condition_and_data_0(false);
+    } else {
+        console::assert_with_condition_and_data_1(false, &JsValue::from(msg));
+    }
+}
+
+#[inline]
+pub(crate) fn dir(arg: &Arg) {
+    if sink::write(Level::Log, &arg.pretty()) {
+        return;
+    }
+    console::dir_1(&arg.to_js_value());
+}
+
+#[cfg(test)]
+thread_local! {
+    /// The number of open groups, which the console doesn't expose.
+    static DEPTH: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// Returns the number of open groups.
+#[cfg(test)]
+pub(crate) fn depth() -> usize {
+    DEPTH.with(std::cell::Cell::get)
+}
+
+#[inline]
+pub(crate) fn group_start(label: &str, collapsed: bool) {
+    #[cfg(test)]
+    DEPTH.with(|depth| depth.set(depth.get() + 1));
+    let value = JsValue::from(label);
+    if collapsed {
+        console::group_collapsed_1(&value);
+    } else {
+        console::group_1(&value);
+    }
+}
+
+#[inline]
+pub(crate) fn group_end() {
+    #[cfg(test)]
+    DEPTH.with(|depth| depth.set(depth.get().saturating_sub(1)));
+    console::group_end();
+}
+
+#[inline]
+pub(crate) fn time(label: &str) {
+    console::time_with_label(label);
+}
+
+#[inline]
+pub(crate) fn time_log(label: &str) {
+    console::time_log_with_label_and_data_0(label);
+}
+
+#[inline]
+pub(crate) fn time_end(label: &str) {
+    console::time_end_with_label(label);
+}
+
+#[inline]
+pub(crate) fn count(label: &str) {
+    console::count_with_label(label);
+}
+
+#[inline]
+pub(crate) fn count_reset(label: &str) {
+    console::count_reset_with_label(label);
+}
+
+/// Logs the JSON encoded data as a table.
+#[cfg(feature = "serde")]
+pub(crate) fn table(json: &str, columns: Option<&[&str]>) -> Result<()> {
+    let data = js_sys::JSON::parse(json).map_err(js_value_to_error)?;
+    match columns {
+        Some(columns) => {
+            let columns = columns
+                .iter()
+                .map(|column| JsValue::from(*column))
+                .collect::<js_sys::Array>();
+            console::table_2(&data, &columns);
+        }
+        None => console::table_1(&data),
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use super::depth;
+    use crate::console::{Capture, Level};
     use wasm_bindgen_test::wasm_bindgen_test;
 
     #[wasm_bindgen_test]
     fn test_console_log() {
+        let capture = Capture::start();
         let world = "world";
         console_log!("hello\n{}", world);
+        assert!(capture.contains(Level::Log, "hello\nworld"));
     }
 
     #[wasm_bindgen_test]
     fn test_console_error() {
+        let capture = Capture::start();
         let world = "world";
         console_error!("hello\n{}", world);
+        assert!(capture.contains(Level::Error, "hello\nworld"));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_capture() {
+        let capture = Capture::start();
+        console_log_args!("count", 3);
+        console_assert!(false, "failed");
+        assert!(capture.contains(Level::Log, "count 3"));
+        assert!(capture
+            .lines()
+            .iter()
+            .any(|(level, msg)| *level == Level::Error && msg.starts_with("Assertion failed: failed")));
+        capture.clear();
+        assert!(capture.lines().is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_console_levels() {
+        let capture = Capture::start();
+        let world = "world";
+        console_debug!("hello\n{}", world);
+        console_info!("hello\n{}", world);
+        console_warn!("hello\n{}", world);
+        console_trace!("hello\n{}", world);
+        let hello = "hello\nworld".to_string();
+        assert_eq!(
+            capture.lines(),
+            vec![
+                (Level::Debug, hello.clone()),
+                (Level::Info, hello.clone()),
+                (Level::Warn, hello.clone()),
+                (Level::Trace, hello),
+            ],
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn test_console_group() {
+        let capture = Capture::start();
+        // Group labels are shown by the console and not captured.
+        console_group!("group {}", 1);
+        console_group_collapsed!();
+        assert_eq!(depth(), 2);
+        console_log!("indented");
+        console_group_end!();
+        console_group_end!();
+        assert_eq!(depth(), 0);
+        {
+            let _group = crate::console::Group::new("group");
+            assert_eq!(depth(), 1);
+        }
+        assert_eq!(depth(), 0);
+        assert_eq!(capture.lines(), vec![(Level::Log, "indented".to_string())]);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_console_assert() {
+        let capture = Capture::start();
+        console_assert!(1 + 1 == 2, "not logged");
+        console_assert!(1 + 1 == 3, "1 + 1 = {}", 1 + 1);
+        console_assert!(false);
+        assert_eq!(
+            capture.lines(),
+            vec![
+                (Level::Error, "Assertion failed: 1 + 1 = 2".to_string()),
+                (Level::Error, "Assertion failed".to_string()),
+            ],
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn test_console_dir() {
+        let capture = Capture::start();
+        crate::console::dir(&Some(vec![1, 2]));
+        crate::console::dir_arg(&wasm_bindgen::JsValue::from(js_sys::Object::new()).into());
+        let lines = capture.lines();
+        assert_eq!(lines.len(), 2, "{:?}", lines);
+        assert_eq!(lines[0], (Level::Log, format!("{:#?}", Some(vec![1, 2]))));
+        assert_eq!(lines[1].0, Level::Log);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_console_log_args() {
+        let object = js_sys::Object::new();
+        console_log_args!("hello", 1, 2.5, false, wasm_bindgen::JsValue::from(object));
+        let args = crate::console::Styled::new()
+            .text("libweb", "color: purple")
+            .into_args();
+        crate::console::log_args(&args);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_console_time() {
+        // Timers and counters are shown by the console and not captured.
+        let capture = Capture::start();
+        console_time!("test_console_time");
+        console_time_log!("test_console_time");
+        console_time_end!("test_console_time");
+        console_count!();
+        console_count_reset!();
+        assert!(capture.lines().is_empty(), "{:?}", capture.lines());
     }
 }
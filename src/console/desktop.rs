@@ -1,30 +1,400 @@
-/// Outputs an error message to stdout.
-#[macro_export]
-macro_rules! console_log {
-    ($($arg: tt)*) => (
-        println!($($arg)*);
-    )
+use crate::console::{sink, Level};
+use std::backtrace::Backtrace;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, IsTerminal};
+use std::panic::Location;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+const AUTO: u8 = 0;
+const COLORED: u8 = 1;
+const PLAIN: u8 = 2;
+
+static COLOR: AtomicU8 = AtomicU8::new(AUTO);
+
+thread_local! {
+    /// The number of open groups on this thread.
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+#[inline]
+pub(crate) fn set_colored(colored: bool) {
+    COLOR.store(if colored { COLORED } else { PLAIN }, Ordering::Relaxed);
+}
+
+fn colored(terminal: bool) -> bool {
+    match COLOR.load(Ordering::Relaxed) {
+        COLORED => true,
+        PLAIN => false,
+        _ => terminal && env::var_os("NO_COLOR").is_none(),
+    }
+}
+
+/// The ANSI color code of a level.
+fn color(level: Level) -> Option<&'static str> {
+    match level {
+        Level::Trace => Some("35"),
+        Level::Debug => Some("90"),
+        Level::Log | Level::Info => None,
+        Level::Warn => Some("33"),
+        Level::Error => Some("31"),
+    }
+}
+
+/// Writes the group label and indents subsequent messages. Terminals can't
+/// collapse groups, so collapsed groups are printed like normal groups.
+pub(crate) fn group_start(label: &str, _collapsed: bool) {
+    if !label.is_empty() {
+        print(Level::Log, label);
+    }
+    DEPTH.with(|depth| depth.set(depth.get() + 1));
+}
+
+pub(crate) fn group_end() {
+    DEPTH.with(|depth| depth.set(depth.get().saturating_sub(1)));
+}
+
+/// Returns the number of open groups on this thread.
+#[cfg(test)]
+pub(crate) fn depth() -> usize {
+    DEPTH.with(Cell::get)
+}
+
+/// Indents every line of the message by two spaces per open group.
+fn indent(msg: &str, depth: usize) -> String {
+    if depth == 0 {
+        return msg.to_string();
+    }
+    let prefix = "  ".repeat(depth);
+    msg.lines()
+        .map(|line| format!("{}{}", prefix, line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Writes the message to stdout or stderr depending on the level.
+pub(crate) fn print(level: Level, msg: &str) {
+    if sink::write(level, msg) {
+        return;
+    }
+    let msg = match level {
+        Level::Trace => format!("Trace: {}\n{}", msg, Backtrace::force_capture()),
+        _ => msg.to_string(),
+    };
+    match level {
+        Level::Debug | Level::Log | Level::Info => {
+            let msg = indent(&msg, DEPTH.with(Cell::get));
+            let colored = colored(io::stdout().is_terminal());
+            println!("{}", paint(level, &msg, colored));
+        }
+        Level::Trace | Level::Warn | Level::Error => eprint(level, &msg),
+    }
+}
+
+/// Writes the message to stderr.
+pub(crate) fn eprint(level: Level, msg: &str) {
+    if sink::write(level, msg) {
+        return;
+    }
+    let msg = &indent(msg, DEPTH.with(Cell::get));
+    let colored = colored(io::stderr().is_terminal());
+    eprintln!("{}", paint(level, msg, colored));
+}
+
+/// Console timers and counters, which are global like in the browser.
+#[derive(Default)]
+struct Labels {
+    timers: HashMap<String, Instant>,
+    counters: HashMap<String, u64>,
+}
+
+impl Labels {
+    fn time(&mut self, label: &str, now: Instant) -> Option<String> {
+        if self.timers.contains_key(label) {
+            return Some(format!("Timer '{}' already exists", label));
+        }
+        self.timers.insert(label.to_string(), now);
+        None
+    }
+
+    fn time_log(&self, label: &str, now: Instant) -> Result<String, String> {
+        match self.timers.get(label) {
+            Some(start) => Ok(format!("{}: {}", label, format_duration(now - *start))),
+            None => Err(format!("Timer '{}' does not exist", label)),
+        }
+    }
+
+    fn time_end(&mut self, label: &str, now: Instant) -> Result<String, String> {
+        match self.timers.remove(label) {
+            Some(start) => Ok(format!(
+                "{}: {} - timer ended",
+                label,
+                format_duration(now - start),
+            )),
+            None => Err(format!("Timer '{}' does not exist", label)),
+        }
+    }
+
+    fn count(&mut self, label: &str) -> String {
+        let count = self.counters.entry(label.to_string()).or_insert(0);
+        *count += 1;
+        format!("{}: {}", label, count)
+    }
+
+    fn count_reset(&mut self, label: &str) -> Option<String> {
+        match self.counters.get_mut(label) {
+            Some(count) => {
+                *count = 0;
+                None
+            }
+            None => Some(format!("Count for '{}' does not exist", label)),
+        }
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{} ms", duration.as_secs_f64() * 1000.0)
+}
+
+fn labels() -> std::sync::MutexGuard<'static, Labels> {
+    static LABELS: OnceLock<Mutex<Labels>> = OnceLock::new();
+    LABELS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+}
+
+pub(crate) fn time(label: &str) {
+    let warning = labels().time(label, Instant::now());
+    if let Some(warning) = warning {
+        print(Level::Warn, &warning);
+    }
+}
+
+pub(crate) fn time_log(label: &str) {
+    let result = labels().time_log(label, Instant::now());
+    match result {
+        Ok(msg) => print(Level::Log, &msg),
+        Err(warning) => print(Level::Warn, &warning),
+    }
+}
+
+pub(crate) fn time_end(label: &str) {
+    let result = labels().time_end(label, Instant::now());
+    match result {
+        Ok(msg) => print(Level::Log, &msg),
+        Err(warning) => print(Level::Warn, &warning),
+    }
+}
+
+pub(crate) fn count(label: &str) {
+    let msg = labels().count(label);
+    print(Level::Info, &msg);
+}
+
+pub(crate) fn count_reset(label: &str) {
+    let warning = labels().count_reset(label);
+    if let Some(warning) = warning {
+        print(Level::Warn, &warning);
+    }
+}
+
+pub(crate) fn assert(msg: &str, location: &Location) {
+    print(Level::Error, &format_assertion(msg, location));
+}
+
+fn format_assertion(msg: &str, location: &Location) -> String {
+    if msg.is_empty() {
+        format!("Assertion failed at {}:{}", location.file(), location.line())
+    } else {
+        format!("Assertion failed: {} at {}:{}", msg, location.file(), location.line())
+    }
 }
 
-/// Outputs an error message to stderr.
-#[macro_export]
-macro_rules! console_error {
-    ($($arg: tt)*) => (
-        eprintln!($($arg)*)
-    )
+fn paint(level: Level, msg: &str, colored: bool) -> String {
+    match color(level) {
+        Some(color) if colored => format!("\x1b[{}m{}\x1b[0m", color, msg),
+        _ => msg.to_string(),
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::console::Capture;
+
     #[test]
     fn test_console_log() {
+        let capture = Capture::start();
         let world = "world";
         console_log!("hello\n{}", world);
+        assert!(capture.contains(Level::Log, "hello\nworld"));
     }
 
     #[test]
     fn test_console_error() {
+        let capture = Capture::start();
         let world = "world";
         console_error!("hello\n{}", world);
+        assert!(capture.contains(Level::Error, "hello\nworld"));
+    }
+
+    #[test]
+    fn test_capture() {
+        let capture = Capture::start();
+        console_log_args!("count", 3);
+        console_assert!(false, "failed");
+        assert!(capture.contains(Level::Log, "count 3"));
+        assert!(capture
+            .lines()
+            .iter()
+            .any(|(level, msg)| *level == Level::Error && msg.starts_with("Assertion failed: failed")));
+        capture.clear();
+        assert!(capture.lines().is_empty());
+    }
+
+    #[test]
+    fn test_console_levels() {
+        let capture = Capture::start();
+        let world = "world";
+        console_debug!("hello\n{}", world);
+        console_info!("hello\n{}", world);
+        console_warn!("hello\n{}", world);
+        console_trace!("hello\n{}", world);
+        for level in &[Level::Debug, Level::Info, Level::Warn, Level::Trace] {
+            assert!(capture.contains(*level, "hello\nworld"), "{:?}", level);
+        }
+    }
+
+    #[test]
+    fn test_console_assert() {
+        let location = Location::caller();
+        assert_eq!(
+            format_assertion("1 + 1 = 3", location),
+            format!("Assertion failed: 1 + 1 = 3 at {}:{}", file!(), location.line()),
+        );
+        assert_eq!(
+            format_assertion("", location),
+            format!("Assertion failed at {}:{}", file!(), location.line()),
+        );
+        console_assert!(1 + 1 == 2, "not logged");
+        console_assert!(1 + 1 == 3, "1 + 1 = {}", 1 + 1);
+        console_assert!(false);
+    }
+
+    #[test]
+    fn test_console_dir() {
+        let capture = Capture::start();
+        crate::console::dir(&Some(vec![1, 2]));
+        crate::console::dir_arg(&"text".into());
+        assert!(capture.contains(Level::Log, "Some(\n    [\n        1,\n        2,\n    ],\n)"));
+        assert!(capture.contains(Level::Log, "text"));
+    }
+
+    #[test]
+    fn test_console_group() {
+        console_group!("group {}", 1);
+        console_log!("indented");
+        console_group_collapsed!();
+        assert_eq!(depth(), 2);
+        console_group_end!();
+        console_group_end!();
+        assert_eq!(depth(), 0);
+        console_group_end!();
+        assert_eq!(depth(), 0);
+    }
+
+    #[test]
+    fn test_group_guard() {
+        {
+            let _group = crate::console::Group::new("group");
+            let _collapsed = crate::console::Group::collapsed("collapsed");
+            assert_eq!(depth(), 2);
+        }
+        assert_eq!(depth(), 0);
+    }
+
+    #[test]
+    fn test_timers() {
+        let start = Instant::now();
+        let mut labels = Labels::default();
+        assert_eq!(labels.time("load", start), None);
+        assert_eq!(
+            labels.time("load", start),
+            Some("Timer 'load' already exists".into()),
+        );
+        let now = start + Duration::from_micros(1500);
+        assert_eq!(labels.time_log("load", now), Ok("load: 1.5 ms".into()));
+        assert_eq!(
+            labels.time_end("load", now),
+            Ok("load: 1.5 ms - timer ended".into()),
+        );
+        assert_eq!(
+            labels.time_end("load", now),
+            Err("Timer 'load' does not exist".into()),
+        );
+        assert_eq!(
+            labels.time_log("load", now),
+            Err("Timer 'load' does not exist".into()),
+        );
+    }
+
+    #[test]
+    fn test_counters() {
+        let mut labels = Labels::default();
+        assert_eq!(labels.count("default"), "default: 1");
+        assert_eq!(labels.count("default"), "default: 2");
+        assert_eq!(labels.count("other"), "other: 1");
+        assert_eq!(labels.count_reset("default"), None);
+        assert_eq!(labels.count("default"), "default: 1");
+        assert_eq!(
+            labels.count_reset("missing"),
+            Some("Count for 'missing' does not exist".into()),
+        );
+    }
+
+    #[test]
+    fn test_console_time() {
+        let capture = Capture::start();
+        console_time!("test_console_time");
+        console_time!("test_console_time");
+        console_time_log!("test_console_time");
+        console_time_end!("test_console_time");
+        console_time_end!("test_console_time");
+        console_count_reset!();
+        console_count!();
+        console_count!();
+        console_count_reset!("test_console_time");
+
+        let lines = capture.lines();
+        let matches = |level: Level, prefix: &str, suffix: &str| {
+            lines
+                .iter()
+                .filter(|(l, msg)| *l == level && msg.starts_with(prefix) && msg.ends_with(suffix))
+                .count()
+        };
+        assert_eq!(matches(Level::Log, "test_console_time: ", " ms"), 1, "{:?}", lines);
+        assert_eq!(matches(Level::Log, "test_console_time: ", " ms - timer ended"), 1);
+        assert!(capture.contains(Level::Warn, "Timer 'test_console_time' already exists"));
+        assert!(capture.contains(Level::Warn, "Timer 'test_console_time' does not exist"));
+        assert!(capture.contains(Level::Info, "default: 1"));
+        assert!(capture.contains(Level::Info, "default: 2"));
+        assert!(capture.contains(Level::Warn, "Count for 'test_console_time' does not exist"));
+    }
+
+    #[test]
+    fn test_indent() {
+        assert_eq!(indent("hello\nworld", 0), "hello\nworld");
+        assert_eq!(indent("hello\nworld", 2), "    hello\n    world");
+    }
+
+    #[test]
+    fn test_paint() {
+        assert_eq!(paint(Level::Warn, "warning", true), "\x1b[33mwarning\x1b[0m");
+        assert_eq!(paint(Level::Info, "info", true), "info");
+        assert_eq!(paint(Level::Error, "error", false), "error");
     }
 }
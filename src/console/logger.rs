@@ -0,0 +1,219 @@
+use crate::Result;
+use crate::console::Level;
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// A `log` backend writing to the console.
+///
+/// In the browser records are written using the `console.*` function of
+/// their level, on desktop they are written to stderr.
+///
+/// Records can be filtered per module using the syntax of `env_logger`'s
+/// `RUST_LOG` variable, for example `warn,libweb::websocket=debug`.
+#[derive(Debug)]
+pub struct Logger {
+    directives: Vec<Directive>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+struct Directive {
+    target: Option<String>,
+    level: LevelFilter,
+}
+
+impl Logger {
+    /// Creates a logger that logs all records up to `level`.
+    pub fn new(level: LevelFilter) -> Self {
+        Logger {
+            directives: vec![Directive { target: None, level }],
+        }
+    }
+
+    /// Creates a logger from a comma separated list of filter directives
+    /// like `warn,libweb::websocket=debug`.
+    ///
+    /// Invalid directives are ignored. Records whose target doesn't match any
+    /// directive are logged up to the `error` level.
+    pub fn parse(filters: &str) -> Self {
+        let mut directives = Vec::new();
+        for directive in filters.split(',').map(str::trim) {
+            if directive.is_empty() {
+                continue;
+            }
+            let mut parts = directive.splitn(2, '=');
+            let (target, level) = match (parts.next(), parts.next()) {
+                (Some(target), Some(level)) => match level.trim().parse() {
+                    Ok(level) => (Some(target.trim().to_string()), level),
+                    Err(_) => continue,
+                },
+                (Some(level_or_target), None) => match level_or_target.parse() {
+                    Ok(level) => (None, level),
+                    Err(_) => (Some(level_or_target.to_string()), LevelFilter::Trace),
+                },
+                _ => continue,
+            };
+            directives.push(Directive { target, level });
+        }
+        if !directives.iter().any(|directive| directive.target.is_none()) {
+            directives.push(Directive {
+                target: None,
+                level: LevelFilter::Error,
+            });
+        }
+        Logger { directives }
+    }
+
+    /// Installs a logger that logs all records up to `level`.
+    ///
+    /// On desktop the `RUST_LOG` environment variable overrides `level`.
+    pub fn init(level: LevelFilter) -> Result<()> {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if let Ok(filters) = std::env::var("RUST_LOG") {
+                return Logger::parse(&filters).install();
+            }
+        }
+        Logger::new(level).install()
+    }
+
+    /// Installs the logger as the global logger of the `log` crate.
+    pub fn install(self) -> Result<()> {
+        log::set_max_level(self.max_level());
+        log::set_boxed_logger(Box::new(self))?;
+        Ok(())
+    }
+
+    /// Returns the level filter for `target`.
+    ///
+    /// The directive with the longest module path matching `target` is used.
+    fn level(&self, target: &str) -> LevelFilter {
+        self.directives
+            .iter()
+            .filter(|directive| match &directive.target {
+                Some(name) => {
+                    target == name
+                        || (target.starts_with(name.as_str())
+                            && target[name.len()..].starts_with("::"))
+                }
+                None => true,
+            })
+            .max_by_key(|directive| directive.target.as_ref().map(String::len))
+            .map(|directive| directive.level)
+            .unwrap_or(LevelFilter::Off)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.directives
+            .iter()
+            .map(|directive| directive.level)
+            .max()
+            .unwrap_or(LevelFilter::Off)
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let level = match record.level() {
+            log::Level::Error => Level::Error,
+            log::Level::Warn => Level::Warn,
+            log::Level::Info => Level::Info,
+            // `console.trace` prints a stack trace, so trace records are
+            // logged as debug messages.
+            log::Level::Debug | log::Level::Trace => Level::Debug,
+        };
+        let msg = format!("[{:<5} {}] {}", record.level(), record.target(), record.args());
+        #[cfg(target_arch = "wasm32")]
+        super::browser::print(level, &msg);
+        #[cfg(not(target_arch = "wasm32"))]
+        super::desktop::eprint(level, &msg);
+    }
+
+    fn flush(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::Capture;
+
+    #[test]
+    fn test_parse() {
+        let logger = Logger::parse("info, libweb::websocket=debug,hyper,foo=bar");
+        assert_eq!(
+            logger.directives,
+            vec![
+                Directive { target: None, level: LevelFilter::Info },
+                Directive {
+                    target: Some("libweb::websocket".into()),
+                    level: LevelFilter::Debug,
+                },
+                Directive { target: Some("hyper".into()), level: LevelFilter::Trace },
+            ],
+        );
+        assert_eq!(logger.max_level(), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn test_default_level() {
+        let logger = Logger::parse("libweb=info");
+        assert_eq!(logger.level("libweb"), LevelFilter::Info);
+        assert_eq!(logger.level("other"), LevelFilter::Error);
+    }
+
+    #[test]
+    fn test_level() {
+        let logger = Logger::parse("warn,libweb=info,libweb::websocket=debug");
+        assert_eq!(logger.level("libweb"), LevelFilter::Info);
+        assert_eq!(logger.level("libweb::console"), LevelFilter::Info);
+        assert_eq!(logger.level("libweb::websocket::desktop"), LevelFilter::Debug);
+        assert_eq!(logger.level("libwebsocket"), LevelFilter::Warn);
+        assert_eq!(logger.level("ws"), LevelFilter::Warn);
+    }
+
+    fn log(logger: &Logger, level: log::Level, target: &str, msg: &str) {
+        // The `log!` macros check the global max level, which is only set by
+        // `install`, so the records are passed to the logger directly.
+        logger.log(
+            &Record::builder()
+                .level(level)
+                .target(target)
+                .args(format_args!("{}", msg))
+                .build(),
+        );
+    }
+
+    #[test]
+    fn test_log() {
+        let logger = Logger::parse("info,libweb::quiet=warn,libweb::verbose=trace");
+        let capture = Capture::start();
+        log(&logger, log::Level::Error, "libweb", "error");
+        log(&logger, log::Level::Warn, "libweb", "warn");
+        log(&logger, log::Level::Info, "libweb", "info");
+        log(&logger, log::Level::Debug, "libweb", "debug");
+        log(&logger, log::Level::Info, "libweb::quiet", "quiet info");
+        log(&logger, log::Level::Warn, "libweb::quiet", "quiet warn");
+        log(&logger, log::Level::Trace, "libweb::verbose", "trace");
+        // Messages logged by other threads are captured too.
+        let lines: Vec<_> = capture
+            .lines()
+            .into_iter()
+            .filter(|(_, msg)| msg.starts_with('[') && msg.contains(" libweb"))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                (Level::Error, "[ERROR libweb] error".to_string()),
+                (Level::Warn, "[WARN  libweb] warn".to_string()),
+                (Level::Info, "[INFO  libweb] info".to_string()),
+                (Level::Warn, "[WARN  libweb::quiet] quiet warn".to_string()),
+                (Level::Debug, "[TRACE libweb::verbose] trace".to_string()),
+            ],
+        );
+    }
+}
@@ -1,5 +1,431 @@
 //! The console API
+
+/// Outputs a message to the console.
+#[macro_export]
+macro_rules! console_log {
+    ($($arg: tt)*) => (
+        $crate::console::log(&format!($($arg)*))
+    )
+}
+
+/// Outputs a debug message to the console.
+#[macro_export]
+macro_rules! console_debug {
+    ($($arg: tt)*) => (
+        $crate::console::debug(&format!($($arg)*))
+    )
+}
+
+/// Outputs an informational message to the console.
+#[macro_export]
+macro_rules! console_info {
+    ($($arg: tt)*) => (
+        $crate::console::info(&format!($($arg)*))
+    )
+}
+
+/// Outputs a warning message to the console.
+#[macro_export]
+macro_rules! console_warn {
+    ($($arg: tt)*) => (
+        $crate::console::warn(&format!($($arg)*))
+    )
+}
+
+/// Outputs an error message to the console.
+#[macro_export]
+macro_rules! console_error {
+    ($($arg: tt)*) => (
+        $crate::console::error(&format!($($arg)*))
+    )
+}
+
+/// Outputs a message and a stack trace to the console.
+#[macro_export]
+macro_rules! console_trace {
+    ($($arg: tt)*) => (
+        $crate::console::trace(&format!($($arg)*))
+    )
+}
+
+/// Starts a new group in the console. Subsequent messages are indented until
+/// `console_group_end!` is called.
+#[macro_export]
+macro_rules! console_group {
+    () => (
+        $crate::console::group("")
+    );
+    ($($arg: tt)*) => (
+        $crate::console::group(&format!($($arg)*))
+    )
+}
+
+/// Starts a new collapsed group in the console. Subsequent messages are
+/// indented until `console_group_end!` is called.
+#[macro_export]
+macro_rules! console_group_collapsed {
+    () => (
+        $crate::console::group_collapsed("")
+    );
+    ($($arg: tt)*) => (
+        $crate::console::group_collapsed(&format!($($arg)*))
+    )
+}
+
+/// Ends the current group in the console.
+#[macro_export]
+macro_rules! console_group_end {
+    () => (
+        $crate::console::group_end()
+    )
+}
+
+/// Starts a timer, the label defaults to `"default"`.
+#[macro_export]
+macro_rules! console_time {
+    () => (
+        $crate::console::time("default")
+    );
+    ($label: expr) => (
+        $crate::console::time($label)
+    )
+}
+
+/// Logs the elapsed time of a timer, the label defaults to `"default"`.
+#[macro_export]
+macro_rules! console_time_log {
+    () => (
+        $crate::console::time_log("default")
+    );
+    ($label: expr) => (
+        $crate::console::time_log($label)
+    )
+}
+
+/// Logs the elapsed time of a timer and stops it, the label defaults to
+/// `"default"`.
+#[macro_export]
+macro_rules! console_time_end {
+    () => (
+        $crate::console::time_end("default")
+    );
+    ($label: expr) => (
+        $crate::console::time_end($label)
+    )
+}
+
+/// Logs how often it was called with a label, the label defaults to
+/// `"default"`.
+#[macro_export]
+macro_rules! console_count {
+    () => (
+        $crate::console::count("default")
+    );
+    ($label: expr) => (
+        $crate::console::count($label)
+    )
+}
+
+/// Resets a counter, the label defaults to `"default"`.
+#[macro_export]
+macro_rules! console_count_reset {
+    () => (
+        $crate::console::count_reset("default")
+    );
+    ($label: expr) => (
+        $crate::console::count_reset($label)
+    )
+}
+
+/// Outputs multiple values to the console as separate arguments, so they can
+/// be inspected in the browser's devtools.
+///
+/// Each argument is converted using `console::Arg::from`.
+#[macro_export]
+macro_rules! console_log_args {
+    ($($arg: expr),* $(,)*) => (
+        $crate::console::log_args(&[$($crate::console::Arg::from($arg)),*])
+    )
+}
+
+/// Outputs an error message to the console if the condition is false.
+///
+/// Unlike `assert!` it doesn't panic. The message is only formatted when the
+/// assertion fails.
+#[macro_export]
+macro_rules! console_assert {
+    ($cond: expr $(,)*) => (
+        $crate::console::assert($cond, "")
+    );
+    ($cond: expr, $($arg: tt)+) => (
+        if !($cond) {
+            $crate::console::assert(false, &format!($($arg)+))
+        }
+    )
+}
+
+mod args;
 #[cfg(target_arch = "wasm32")]
 mod browser;
 #[cfg(not(target_arch = "wasm32"))]
 mod desktop;
+mod logger;
+mod panic;
+mod sink;
+#[cfg(feature = "serde")]
+mod table;
+#[cfg(feature = "tracing")]
+mod tracing_layer;
+
+pub use self::args::{log_args, print_args, Arg, Styled};
+pub use self::logger::Logger;
+pub use self::panic::set_panic_hook;
+pub use self::sink::{set_sink, Capture, Sink};
+#[cfg(feature = "serde")]
+pub use self::table::{table, table_with_columns};
+#[cfg(feature = "tracing")]
+pub use self::tracing_layer::ConsoleLayer;
+
+use std::fmt;
+use std::marker::PhantomData;
+
+/// The level of a console message.
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Clone, Copy)]
+pub enum Level {
+    /// A message and a stack trace.
+    Trace,
+    /// A debug message.
+    Debug,
+    /// A plain message.
+    Log,
+    /// An informational message.
+    Info,
+    /// A warning.
+    Warn,
+    /// An error.
+    Error,
+}
+
+/// Outputs a message to the console.
+///
+/// On desktop messages are written to stdout.
+#[inline]
+pub fn log(msg: &str) {
+    print(Level::Log, msg);
+}
+
+/// Outputs a debug message to the console.
+///
+/// On desktop messages are written to stdout.
+#[inline]
+pub fn debug(msg: &str) {
+    print(Level::Debug, msg);
+}
+
+/// Outputs an informational message to the console.
+///
+/// On desktop messages are written to stdout.
+#[inline]
+pub fn info(msg: &str) {
+    print(Level::Info, msg);
+}
+
+/// Outputs a warning message to the console.
+///
+/// On desktop messages are written to stderr.
+#[inline]
+pub fn warn(msg: &str) {
+    print(Level::Warn, msg);
+}
+
+/// Outputs an error message to the console.
+///
+/// On desktop messages are written to stderr.
+#[inline]
+pub fn error(msg: &str) {
+    print(Level::Error, msg);
+}
+
+/// Outputs a message and a stack trace to the console.
+///
+/// On desktop the message and a Rust backtrace are written to stderr.
+#[inline]
+pub fn trace(msg: &str) {
+    print(Level::Trace, msg);
+}
+
+/// Enables or disables colored output. By default colors are used when
+/// writing to a terminal and the `NO_COLOR` environment variable is not set.
+///
+/// Has no effect in the browser.
+#[inline]
+pub fn set_colored(colored: bool) {
+    #[cfg(not(target_arch = "wasm32"))]
+    desktop::set_colored(colored);
+    #[cfg(target_arch = "wasm32")]
+    let _ = colored;
+}
+
+/// Starts a new group. Subsequent messages are indented until `group_end` is
+/// called.
+///
+/// On desktop groups are tracked per thread.
+#[inline]
+pub fn group(label: &str) {
+    #[cfg(target_arch = "wasm32")]
+    browser::group_start(label, false);
+    #[cfg(not(target_arch = "wasm32"))]
+    desktop::group_start(label, false);
+}
+
+/// Starts a new group that is collapsed in the browser's devtools.
+/// Subsequent messages are indented until `group_end` is called.
+///
+/// On desktop collapsed groups are shown like normal groups.
+#[inline]
+pub fn group_collapsed(label: &str) {
+    #[cfg(target_arch = "wasm32")]
+    browser::group_start(label, true);
+    #[cfg(not(target_arch = "wasm32"))]
+    desktop::group_start(label, true);
+}
+
+/// Ends the current group.
+#[inline]
+pub fn group_end() {
+    #[cfg(target_arch = "wasm32")]
+    browser::group_end();
+    #[cfg(not(target_arch = "wasm32"))]
+    desktop::group_end();
+}
+
+/// Outputs an error message to the console if the condition is false.
+///
+/// On desktop the message is written to stderr with the file and line of the
+/// caller.
+#[inline]
+#[track_caller]
+pub fn assert(condition: bool, msg: &str) {
+    if condition {
+        return;
+    }
+    #[cfg(target_arch = "wasm32")]
+    browser::assert(msg);
+    #[cfg(not(target_arch = "wasm32"))]
+    desktop::assert(msg, std::panic::Location::caller());
+}
+
+/// Outputs the structure of a value to the console.
+///
+/// On desktop the value is pretty-printed using `{:#?}`. Use `dir_arg` to
+/// show JavaScript and serialized values as inspectable objects in the
+/// browser.
+#[inline]
+pub fn dir<T: fmt::Debug + ?Sized>(value: &T) {
+    #[cfg(target_arch = "wasm32")]
+    browser::dir(&Arg::Debug(format!("{:#?}", value)));
+    #[cfg(not(target_arch = "wasm32"))]
+    desktop::print(Level::Log, &format!("{:#?}", value));
+}
+
+/// Outputs the structure of an argument to the console.
+///
+/// On desktop serialized values are pretty-printed as JSON.
+#[inline]
+pub fn dir_arg(arg: &Arg) {
+    #[cfg(target_arch = "wasm32")]
+    browser::dir(arg);
+    #[cfg(not(target_arch = "wasm32"))]
+    desktop::print(Level::Log, &arg.pretty());
+}
+
+/// Starts a timer with a label.
+///
+/// Timers are global, on desktop a warning is logged if the timer exists.
+#[inline]
+pub fn time(label: &str) {
+    #[cfg(target_arch = "wasm32")]
+    browser::time(label);
+    #[cfg(not(target_arch = "wasm32"))]
+    desktop::time(label);
+}
+
+/// Logs the elapsed time of a timer like `label: 1.234 ms`.
+#[inline]
+pub fn time_log(label: &str) {
+    #[cfg(target_arch = "wasm32")]
+    browser::time_log(label);
+    #[cfg(not(target_arch = "wasm32"))]
+    desktop::time_log(label);
+}
+
+/// Logs the elapsed time of a timer like `label: 1.234 ms - timer ended` and
+/// stops it.
+#[inline]
+pub fn time_end(label: &str) {
+    #[cfg(target_arch = "wasm32")]
+    browser::time_end(label);
+    #[cfg(not(target_arch = "wasm32"))]
+    desktop::time_end(label);
+}
+
+/// Logs how often `count` was called with a label like `label: 3`.
+#[inline]
+pub fn count(label: &str) {
+    #[cfg(target_arch = "wasm32")]
+    browser::count(label);
+    #[cfg(not(target_arch = "wasm32"))]
+    desktop::count(label);
+}
+
+/// Resets the counter of a label.
+#[inline]
+pub fn count_reset(label: &str) {
+    #[cfg(target_arch = "wasm32")]
+    browser::count_reset(label);
+    #[cfg(not(target_arch = "wasm32"))]
+    desktop::count_reset(label);
+}
+
+/// A guard that ends its group when dropped.
+///
+/// ```
+/// use libweb::console::{self, Group};
+///
+/// let _group = Group::new("request");
+/// console::log("indented");
+/// ```
+#[derive(Debug)]
+#[must_use = "the group ends when the guard is dropped"]
+pub struct Group {
+    // Groups are tracked per thread on desktop.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Group {
+    /// Starts a new group.
+    pub fn new(label: &str) -> Self {
+        group(label);
+        Group { _not_send: PhantomData }
+    }
+
+    /// Starts a new collapsed group.
+    pub fn collapsed(label: &str) -> Self {
+        group_collapsed(label);
+        Group { _not_send: PhantomData }
+    }
+}
+
+impl Drop for Group {
+    fn drop(&mut self) {
+        group_end();
+    }
+}
+
+#[inline]
+pub(crate) fn print(level: Level, msg: &str) {
+    #[cfg(target_arch = "wasm32")]
+    browser::print(level, msg);
+    #[cfg(not(target_arch = "wasm32"))]
+    desktop::print(level, msg);
+}
@@ -0,0 +1,71 @@
+use crate::console::{self, Level};
+use std::any::Any;
+use std::panic::{self, Location};
+use std::sync::Once;
+
+/// Installs a panic hook logging panics to the console as errors.
+///
+/// The message contains the panic message, its location and a stack trace.
+/// In the browser the stack trace is the JavaScript stack, on desktop it's
+/// the Rust backtrace. Calling it more than once has no effect.
+pub fn set_panic_hook() {
+    static SET: Once = Once::new();
+    SET.call_once(|| {
+        panic::set_hook(Box::new(|info| {
+            let msg = format_panic(info.payload(), info.location());
+            console::print(Level::Error, &format!("{}\n\nStack:\n\n{}", msg, stack()));
+        }));
+    });
+}
+
+/// Formats a panic like `panicked at 'message', src/main.rs:1:2`.
+fn format_panic(payload: &(dyn Any + Send), location: Option<&Location>) -> String {
+    let msg = match payload.downcast_ref::<&str>() {
+        Some(msg) => *msg,
+        None => match payload.downcast_ref::<String>() {
+            Some(msg) => msg.as_str(),
+            None => "Box<dyn Any>",
+        },
+    };
+    match location {
+        Some(location) => format!(
+            "panicked at '{}', {}:{}:{}",
+            msg,
+            location.file(),
+            location.line(),
+            location.column()
+        ),
+        None => format!("panicked at '{}'", msg),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn stack() -> String {
+    super::browser::stack()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn stack() -> String {
+    std::backtrace::Backtrace::force_capture().to_string()
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_panic() {
+        let location = Location::caller();
+        assert_eq!(
+            format_panic(&"boom", Some(location)),
+            format!("panicked at 'boom', {}:{}:{}", file!(), location.line(), location.column()),
+        );
+        assert_eq!(format_panic(&"boom".to_string(), None), "panicked at 'boom'");
+        assert_eq!(format_panic(&1, None), "panicked at 'Box<dyn Any>'");
+    }
+
+    #[test]
+    fn test_stack() {
+        assert!(!stack().is_empty());
+    }
+}
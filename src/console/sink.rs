@@ -0,0 +1,119 @@
+use crate::console::Level;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+
+/// A destination for console messages replacing the default output.
+///
+/// Timers, counters and groups in the browser are handled by the console
+/// itself and don't go through the sink.
+pub trait Sink: Send + Sync {
+    /// Writes a message.
+    fn write(&self, level: Level, msg: &str);
+}
+
+impl<F> Sink for F
+where
+    F: Fn(Level, &str) + Send + Sync,
+{
+    fn write(&self, level: Level, msg: &str) {
+        self(level, msg)
+    }
+}
+
+static SINK: RwLock<Option<Arc<dyn Sink>>> = RwLock::new(None);
+
+/// Replaces the global sink, returning the previous one. `None` restores the
+/// default output.
+pub fn set_sink(sink: Option<Arc<dyn Sink>>) -> Option<Arc<dyn Sink>> {
+    let mut current = SINK.write().unwrap_or_else(|err| err.into_inner());
+    std::mem::replace(&mut *current, sink)
+}
+
+/// Writes the message to the global sink. Returns `false` if there's none.
+pub(crate) fn write(level: Level, msg: &str) -> bool {
+    let sink = SINK.read().unwrap_or_else(|err| err.into_inner()).clone();
+    match sink {
+        Some(sink) => {
+            sink.write(level, msg);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Captures console messages until it's dropped, for example to assert on
+/// them in tests.
+///
+/// ```
+/// #[macro_use]
+/// extern crate libweb;
+///
+/// use libweb::console::{Capture, Level};
+///
+/// # fn main() {
+/// let capture = Capture::start();
+/// console_log!("hello {}", 1);
+/// assert!(capture.contains(Level::Log, "hello 1"));
+/// # }
+/// ```
+///
+/// Captures are serialized, so a capture started while another one is active
+/// blocks until the other one is dropped. Messages from other threads are
+/// captured too.
+#[must_use = "messages are only captured until the capture is dropped"]
+pub struct Capture {
+    lines: Arc<Mutex<Vec<(Level, String)>>>,
+    previous: Option<Arc<dyn Sink>>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl Capture {
+    /// Starts capturing messages.
+    pub fn start() -> Self {
+        static LOCK: Mutex<()> = Mutex::new(());
+        let lock = LOCK.lock().unwrap_or_else(|err| err.into_inner());
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let buffer = lines.clone();
+        let previous = set_sink(Some(Arc::new(move |level, msg: &str| {
+            lock_lines(&buffer).push((level, msg.to_string()));
+        })));
+        Capture {
+            lines,
+            previous,
+            _lock: lock,
+        }
+    }
+
+    /// Returns the captured messages.
+    pub fn lines(&self) -> Vec<(Level, String)> {
+        lock_lines(&self.lines).clone()
+    }
+
+    /// Returns whether a message was captured at a level.
+    pub fn contains(&self, level: Level, msg: &str) -> bool {
+        lock_lines(&self.lines)
+            .iter()
+            .any(|line| line.0 == level && line.1 == msg)
+    }
+
+    /// Removes the captured messages.
+    pub fn clear(&self) {
+        lock_lines(&self.lines).clear();
+    }
+}
+
+impl fmt::Debug for Capture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Capture").field("lines", &self.lines()).finish()
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        set_sink(self.previous.take());
+    }
+}
+
+fn lock_lines(lines: &Mutex<Vec<(Level, String)>>) -> MutexGuard<'_, Vec<(Level, String)>> {
+    lines.lock().unwrap_or_else(|err| err.into_inner())
+}
@@ -0,0 +1,217 @@
+use crate::Result;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Logs tabular data. Arrays and objects are shown as a table with a row per
+/// element, other values are logged as is.
+///
+/// On desktop the table is rendered as an aligned ASCII table with an index
+/// column.
+pub fn table<T: Serialize + ?Sized>(data: &T) -> Result<()> {
+    print(serde_json::to_value(data)?, None)
+}
+
+/// Logs tabular data showing only the selected columns.
+pub fn table_with_columns<T: Serialize + ?Sized>(data: &T, columns: &[&str]) -> Result<()> {
+    print(serde_json::to_value(data)?, Some(columns))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn print(data: Value, columns: Option<&[&str]>) -> Result<()> {
+    super::browser::table(&data.to_string(), columns)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn print(data: Value, columns: Option<&[&str]>) -> Result<()> {
+    match render(&data, columns) {
+        Some(table) => super::log(&table),
+        None => super::log(&format_cell(&data)),
+    }
+    Ok(())
+}
+
+/// The name of the column containing primitive rows.
+#[cfg(not(target_arch = "wasm32"))]
+const VALUE: &str = "Value";
+
+/// Renders arrays and objects as an ASCII table. Returns `None` for other
+/// values.
+#[cfg(not(target_arch = "wasm32"))]
+fn render(data: &Value, columns: Option<&[&str]>) -> Option<String> {
+    let rows: Vec<(String, &Value)> = match data {
+        Value::Array(items) => items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| (i.to_string(), item))
+            .collect(),
+        Value::Object(map) => map.iter().map(|(key, value)| (key.clone(), value)).collect(),
+        _ => return None,
+    };
+
+    let mut header = vec!["(index)".to_string()];
+    match columns {
+        Some(columns) => header.extend(columns.iter().map(|column| column.to_string())),
+        None => {
+            let mut has_values = false;
+            for (_, row) in &rows {
+                match row {
+                    Value::Object(map) => {
+                        for key in map.keys() {
+                            if !header[1..].contains(key) {
+                                header.push(key.clone());
+                            }
+                        }
+                    }
+                    Value::Array(items) => {
+                        for i in 0..items.len() {
+                            let key = i.to_string();
+                            if !header[1..].contains(&key) {
+                                header.push(key);
+                            }
+                        }
+                    }
+                    _ => has_values = true,
+                }
+            }
+            if has_values {
+                header.push(VALUE.to_string());
+            }
+        }
+    }
+
+    let mut table = vec![header];
+    for (index, row) in rows {
+        let mut cells = vec![index];
+        for column in &table[0][1..] {
+            let cell = match row {
+                Value::Object(map) => map.get(column),
+                Value::Array(items) => column.parse::<usize>().ok().and_then(|i| items.get(i)),
+                value if column == VALUE && columns.is_none() => Some(value),
+                _ => None,
+            };
+            cells.push(cell.map(format_cell).unwrap_or_default());
+        }
+        table.push(cells);
+    }
+
+    let widths: Vec<usize> = (0..table[0].len())
+        .map(|i| table.iter().map(|row| row[i].chars().count()).max().unwrap_or(0))
+        .collect();
+    let separator = widths
+        .iter()
+        .map(|width| "-".repeat(width + 2))
+        .collect::<Vec<_>>()
+        .join("+");
+    let separator = format!("+{}+", separator);
+    let mut lines = vec![separator.clone()];
+    for (i, row) in table.iter().enumerate() {
+        let cells = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| {
+                let padding = width - cell.chars().count();
+                format!(" {}{} ", cell, " ".repeat(padding))
+            })
+            .collect::<Vec<_>>()
+            .join("|");
+        lines.push(format!("|{}|", cells));
+        if i == 0 {
+            lines.push(separator.clone());
+        }
+    }
+    lines.push(separator);
+    Some(lines.join("\n"))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn format_cell(value: &Value) -> String {
+    match value {
+        Value::String(string) => string.clone(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::console::{Capture, Level};
+    use serde_json::json;
+
+    #[test]
+    fn test_array_of_objects() {
+        let data = json!([
+            { "name": "alice", "age": 30 },
+            { "name": "bob", "admin": true },
+        ]);
+        let expected = "\
++---------+-------+-----+-------+
+| (index) | name  | age | admin |
++---------+-------+-----+-------+
+| 0       | alice | 30  |       |
+| 1       | bob   |     | true  |
++---------+-------+-----+-------+";
+        assert_eq!(render(&data, None).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_columns() {
+        let data = json!({
+            "first": { "name": "alice", "age": 30 },
+            "second": { "name": "bob", "age": 25 },
+        });
+        let expected = "\
++---------+-----+
+| (index) | age |
++---------+-----+
+| first   | 30  |
+| second  | 25  |
++---------+-----+";
+        assert_eq!(render(&data, Some(&["age"])).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_primitives() {
+        let data = json!(["a", [1, 2], null]);
+        let expected = "\
++---------+---+---+-------+
+| (index) | 0 | 1 | Value |
++---------+---+---+-------+
+| 0       |   |   | a     |
+| 1       | 1 | 2 |       |
+| 2       |   |   | null  |
++---------+---+---+-------+";
+        assert_eq!(render(&data, None).unwrap(), expected);
+        assert_eq!(render(&json!(42), None), None);
+    }
+
+    #[test]
+    fn test_table() {
+        let capture = Capture::start();
+        table(&vec![(1, "one"), (2, "two")]).unwrap();
+        table_with_columns(&json!({"a": {"x": 1, "y": 2}}), &["y"]).unwrap();
+        table(&"not a table").unwrap();
+        let tuples = "\
++---------+---+-----+
+| (index) | 0 | 1   |
++---------+---+-----+
+| 0       | 1 | one |
+| 1       | 2 | two |
++---------+---+-----+";
+        let columns = "\
++---------+---+
+| (index) | y |
++---------+---+
+| a       | 2 |
++---------+---+";
+        // Other tests may log concurrently.
+        let lines = capture.lines();
+        let position = |msg: &str| {
+            lines
+                .iter()
+                .position(|line| *line == (Level::Log, msg.to_string()))
+                .unwrap_or_else(|| panic!("missing {:?} in {:?}", msg, lines))
+        };
+        assert!(position(tuples) < position(columns));
+        assert!(position(columns) < position("not a table"));
+    }
+}
@@ -0,0 +1,280 @@
+use crate::Result;
+use crate::clock::now;
+use crate::console::{self, Level};
+use failure::format_err;
+use std::fmt::{self, Write};
+use tracing_core::field::{Field, Visit};
+use tracing_core::span::{Attributes, Id, Record};
+use tracing_core::{Dispatch, Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::{LookupSpan, Registry};
+
+/// A `tracing` layer writing to the console.
+///
+/// Entered spans are shown as console groups labeled with the span's name and
+/// fields, and the time spent in them is logged when they are closed. Events
+/// are logged using the console function of their level. On desktop groups
+/// are shown by indenting the output.
+///
+/// The label is only shown the first time a span is entered, spans entered
+/// again like the ones of futures polled several times only indent the
+/// output. Browsers can't open a group without a label, so there events of a
+/// span entered again are logged outside of its group.
+#[derive(Debug, Default)]
+pub struct ConsoleLayer;
+
+impl ConsoleLayer {
+    /// Creates a new console layer.
+    pub fn new() -> Self {
+        ConsoleLayer
+    }
+
+    /// Installs a subscriber consisting of a registry and a console layer as
+    /// the global default subscriber.
+    pub fn init() -> Result<()> {
+        let subscriber = Registry::default().with(ConsoleLayer::new());
+        tracing_core::dispatcher::set_global_default(Dispatch::new(subscriber))
+            .map_err(|err| format_err!("{}", err))
+    }
+}
+
+/// The formatted fields of a span.
+struct Fields(String);
+
+/// Marks a span whose label was shown.
+struct Shown;
+
+/// The time the span was entered and whether a group was opened for it.
+struct Entered {
+    start: f64,
+    group: bool,
+}
+
+/// The time spent in the span so far.
+struct Busy(f64);
+
+impl<S> Layer<S> for ConsoleLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes, id: &Id, ctx: Context<S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut visitor = FieldVisitor::default();
+            attrs.record(&mut visitor);
+            span.extensions_mut().insert(Fields(visitor.fields));
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record, ctx: Context<S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut extensions = span.extensions_mut();
+            if let Some(fields) = extensions.get_mut::<Fields>() {
+                let mut visitor = FieldVisitor {
+                    message: String::new(),
+                    fields: std::mem::take(&mut fields.0),
+                };
+                values.record(&mut visitor);
+                fields.0 = visitor.fields;
+            }
+        }
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut extensions = span.extensions_mut();
+            let group = if extensions.get_mut::<Shown>().is_some() {
+                reopen_group()
+            } else {
+                let label = match extensions.get_mut::<Fields>() {
+                    Some(fields) if !fields.0.is_empty() => {
+                        format!("{} {}", span.name(), fields.0)
+                    }
+                    _ => span.name().to_string(),
+                };
+                console::group(&label);
+                extensions.insert(Shown);
+                true
+            };
+            extensions.replace(Entered { start: now(), group });
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut extensions = span.extensions_mut();
+            if let Some(Entered { start, group }) = extensions.remove::<Entered>() {
+                let busy = now() - start;
+                match extensions.get_mut::<Busy>() {
+                    Some(total) => total.0 += busy,
+                    None => extensions.insert(Busy(busy)),
+                }
+                if group {
+                    console::group_end();
+                }
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<S>) {
+        if let Some(span) = ctx.span(&id) {
+            if let Some(Busy(busy)) = span.extensions().get::<Busy>() {
+                console::log(&format!("{}: {:.3}ms", span.name(), busy));
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event, _ctx: Context<S>) {
+        let metadata = event.metadata();
+        let level = match *metadata.level() {
+            tracing_core::Level::ERROR => Level::Error,
+            tracing_core::Level::WARN => Level::Warn,
+            tracing_core::Level::INFO => Level::Info,
+            // `console.trace` prints a stack trace, so trace events are
+            // logged as debug messages.
+            _ => Level::Debug,
+        };
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        console::print(level, &visitor.format(metadata.target()));
+    }
+}
+
+/// Opens a group without a label for a span entered again. Returns false if
+/// that's not possible.
+#[cfg(target_arch = "wasm32")]
+fn reopen_group() -> bool {
+    false
+}
+
+/// Opens a group without a label for a span entered again. Returns false if
+/// that's not possible.
+#[cfg(not(target_arch = "wasm32"))]
+fn reopen_group() -> bool {
+    console::desktop::group_start("", false);
+    true
+}
+
+/// Formats the `message` field and the other fields as `name=value`.
+#[derive(Default)]
+struct FieldVisitor {
+    message: String,
+    fields: String,
+}
+
+impl FieldVisitor {
+    fn format(&self, target: &str) -> String {
+        let mut msg = format!("{}:", target);
+        for part in [&self.message, &self.fields] {
+            if !part.is_empty() {
+                msg.push(' ');
+                msg.push_str(part);
+            }
+        }
+        msg
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            self.record_debug(field, &value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            if !self.fields.is_empty() {
+                self.fields.push(' ');
+            }
+            let _ = write!(self.fields, "{}={:?}", field.name(), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(not(target_arch = "wasm32"))]
+    fn test_console_layer() {
+        use crate::console::{desktop, Capture};
+        use tracing::{info, info_span, warn};
+
+        let capture = Capture::start();
+        let subscriber = Registry::default().with(ConsoleLayer::new());
+        tracing::subscriber::with_default(subscriber, || {
+            let span = info_span!("request", id = 1);
+            let _guard = span.enter();
+            assert_eq!(desktop::depth(), 1);
+            {
+                let _guard = info_span!("query").entered();
+                assert_eq!(desktop::depth(), 2);
+                info!(user = "alice", "hello {}", "world");
+            }
+            assert_eq!(desktop::depth(), 1);
+            warn!("goodbye");
+        });
+        assert_eq!(desktop::depth(), 0);
+
+        // Other tests may log concurrently, so only the order of this test's
+        // messages is checked.
+        let target = module_path!();
+        let lines = capture.lines();
+        let position = |level: Level, matches: &dyn Fn(&str) -> bool| {
+            lines
+                .iter()
+                .position(|(l, msg)| *l == level && matches(msg))
+                .unwrap_or_else(|| panic!("missing {:?} message in {:?}", level, lines))
+        };
+        let hello = format!("{}: hello world user=\"alice\"", target);
+        let goodbye = format!("{}: goodbye", target);
+        let positions = [
+            position(Level::Log, &|msg| msg == "request id=1"),
+            position(Level::Log, &|msg| msg == "query"),
+            position(Level::Info, &|msg| msg == hello),
+            position(Level::Log, &|msg| msg.starts_with("query: ") && msg.ends_with("ms")),
+            position(Level::Warn, &|msg| msg == goodbye),
+            position(Level::Log, &|msg| msg.starts_with("request: ") && msg.ends_with("ms")),
+        ];
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", lines);
+    }
+
+    #[test]
+    #[cfg(not(target_arch = "wasm32"))]
+    fn test_reentered_span() {
+        use crate::console::{desktop, Capture};
+        use tracing::info_span;
+
+        let capture = Capture::start();
+        let subscriber = Registry::default().with(ConsoleLayer::new());
+        tracing::subscriber::with_default(subscriber, || {
+            let span = info_span!("poll", task = 7);
+            for _ in 0..3 {
+                let _guard = span.enter();
+                assert_eq!(desktop::depth(), 1);
+            }
+            assert_eq!(desktop::depth(), 0);
+        });
+        let lines = capture.lines();
+        let count = |matches: &dyn Fn(&str) -> bool| {
+            lines.iter().filter(|(level, msg)| *level == Level::Log && matches(msg)).count()
+        };
+        assert_eq!(count(&|msg| msg == "poll task=7"), 1, "{:?}", lines);
+        assert_eq!(count(&|msg| msg.starts_with("poll: ") && msg.ends_with("ms")), 1, "{:?}", lines);
+    }
+
+    #[test]
+    fn test_format() {
+        let visitor = FieldVisitor {
+            message: "hello world".into(),
+            fields: "user=\"alice\" id=1".into(),
+        };
+        assert_eq!(visitor.format("app"), "app: hello world user=\"alice\" id=1");
+        assert_eq!(FieldVisitor::default().format("app"), "app:");
+    }
+}
@@ -0,0 +1,41 @@
+use crate::{js_value_to_error, Result};
+use failure::format_err;
+use wasm_bindgen::JsCast;
+
+/// Returns `crypto` from the global scope, which is the window or a worker.
+pub(crate) fn crypto() -> Result<web_sys::Crypto> {
+    js_sys::Reflect::get(&js_sys::global(), &"crypto".into())
+        .ok()
+        .and_then(|crypto| crypto.dyn_into::<web_sys::Crypto>().ok())
+        .ok_or_else(|| format_err!("The Web Crypto API is not available"))
+}
+
+pub(crate) fn get_random_values(buf: &mut [u8]) -> Result<()> {
+    crypto()?
+        .get_random_values_with_u8_array(buf)
+        .map_err(js_value_to_error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crypto::{get_random_values, random_uuid, MAX_RANDOM_BYTES};
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn test_get_random_values() {
+        let mut buf = vec![0; MAX_RANDOM_BYTES];
+        get_random_values(&mut buf).unwrap();
+        assert!(buf.iter().any(|byte| *byte != 0));
+        assert!(get_random_values(&mut [0; MAX_RANDOM_BYTES + 1]).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_random_uuid() {
+        let uuid = random_uuid().unwrap();
+        assert_eq!(uuid.len(), 36);
+        assert_ne!(uuid, random_uuid().unwrap());
+    }
+}
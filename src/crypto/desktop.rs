@@ -0,0 +1,6 @@
+use crate::Result;
+use failure::format_err;
+
+pub(crate) fn get_random_values(buf: &mut [u8]) -> Result<()> {
+    getrandom::getrandom(buf).map_err(|err| format_err!("Failed to get random values: {}", err))
+}
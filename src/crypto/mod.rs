@@ -0,0 +1,94 @@
+//! The Web Crypto API
+//!
+//! On desktop random values come from the random number generator of the
+//! operating system.
+use crate::Result;
+use failure::bail;
+
+#[cfg(target_arch = "wasm32")]
+mod browser;
+#[cfg(not(target_arch = "wasm32"))]
+mod desktop;
+pub mod subtle;
+
+#[cfg(target_arch = "wasm32")]
+use self::browser as backend;
+#[cfg(not(target_arch = "wasm32"))]
+use self::desktop as backend;
+
+/// The maximum number of bytes filled by a call to `get_random_values`.
+pub const MAX_RANDOM_BYTES: usize = 65536;
+
+/// Fills a buffer with cryptographically secure random bytes.
+///
+/// Fails if the buffer is longer than `MAX_RANDOM_BYTES` on both targets,
+/// like `crypto.getRandomValues` in the browser.
+///
+/// ```
+/// let mut key = [0u8; 32];
+/// libweb::crypto::get_random_values(&mut key).unwrap();
+/// ```
+pub fn get_random_values(buf: &mut [u8]) -> Result<()> {
+    if buf.len() > MAX_RANDOM_BYTES {
+        bail!(
+            "Can't get {} random bytes, the maximum is {}",
+            buf.len(),
+            MAX_RANDOM_BYTES
+        );
+    }
+    backend::get_random_values(buf)
+}
+
+/// Returns a random version 4 UUID like
+/// `"36b8f84d-df4e-4d49-b662-bcde71a8764f"`.
+pub fn random_uuid() -> Result<String> {
+    let mut bytes = [0; 16];
+    get_random_values(&mut bytes)?;
+    Ok(format_uuid(bytes))
+}
+
+/// Formats random bytes as a version 4 UUID, setting the version and variant
+/// bits.
+fn format_uuid(mut bytes: [u8; 16]) -> String {
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let mut uuid = String::with_capacity(36);
+    for (i, byte) in bytes.iter().enumerate() {
+        if let 4 | 6 | 8 | 10 = i {
+            uuid.push('-');
+        }
+        uuid.push_str(&format!("{:02x}", byte));
+    }
+    uuid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_uuid() {
+        assert_eq!(format_uuid([0; 16]), "00000000-0000-4000-8000-000000000000");
+        assert_eq!(
+            format_uuid([0xff; 16]),
+            "ffffffff-ffff-4fff-bfff-ffffffffffff"
+        );
+    }
+
+    #[test]
+    fn test_random_uuid() {
+        let uuid = random_uuid().unwrap();
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+        assert_ne!(uuid, random_uuid().unwrap());
+    }
+
+    #[test]
+    fn test_get_random_values() {
+        let mut buf = vec![0; MAX_RANDOM_BYTES];
+        get_random_values(&mut buf).unwrap();
+        assert!(buf.iter().any(|byte| *byte != 0));
+        assert!(get_random_values(&mut [0; MAX_RANDOM_BYTES + 1]).is_err());
+        get_random_values(&mut []).unwrap();
+    }
+}
@@ -0,0 +1,391 @@
+use crate::crypto::browser::crypto;
+use crate::crypto::subtle::{Algorithm, Hash, Jwk, KeyType, AES_GCM_TAG_LEN};
+use crate::{js_value_to_error, Result};
+use failure::{bail, format_err};
+use js_sys::{Object, Promise, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::SubtleCrypto;
+
+pub(crate) async fn digest(hash: Hash, data: &[u8]) -> Result<Vec<u8>> {
+    let promise = subtle()?.digest_with_str_and_buffer_source(hash.name(), &buffer(data));
+    Ok(bytes(&call(promise).await?))
+}
+
+pub(crate) async fn hmac_sign(hash: Hash, key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let algorithm = hmac(hash)?;
+    let key = import_key(key, &algorithm, "sign").await?;
+    let promise = subtle()?.sign_with_object_and_buffer_source(&algorithm, &key, &buffer(data));
+    Ok(bytes(&call(promise).await?))
+}
+
+pub(crate) async fn hmac_verify(
+    hash: Hash,
+    key: &[u8],
+    signature: &[u8],
+    data: &[u8],
+) -> Result<bool> {
+    let algorithm = hmac(hash)?;
+    let key = import_key(key, &algorithm, "verify").await?;
+    let promise = subtle()?.verify_with_object_and_buffer_source_and_buffer_source(
+        &algorithm,
+        &key,
+        &buffer(signature),
+        &buffer(data),
+    );
+    Ok(call(promise).await?.as_bool().unwrap_or(false))
+}
+
+pub(crate) async fn aes_gcm_encrypt(
+    key: &[u8],
+    iv: &[u8],
+    data: &[u8],
+    additional_data: &[u8],
+) -> Result<Vec<u8>> {
+    let key = import_key(key, &algorithm("AES-GCM", &[])?, "encrypt").await?;
+    let algorithm = aes_gcm(iv, additional_data)?;
+    let promise = subtle()?.encrypt_with_object_and_buffer_source(&algorithm, &key, &buffer(data));
+    Ok(bytes(&call(promise).await?))
+}
+
+pub(crate) async fn aes_gcm_decrypt(
+    key: &[u8],
+    iv: &[u8],
+    data: &[u8],
+    additional_data: &[u8],
+) -> Result<Vec<u8>> {
+    let key = import_key(key, &algorithm("AES-GCM", &[])?, "decrypt").await?;
+    let algorithm = aes_gcm(iv, additional_data)?;
+    let promise = subtle()?.decrypt_with_object_and_buffer_source(&algorithm, &key, &buffer(data));
+    let decrypted = call(promise)
+        .await
+        .map_err(|_| format_err!("AES-GCM decryption failed"))?;
+    Ok(bytes(&decrypted))
+}
+
+pub(crate) async fn pbkdf2(
+    hash: Hash,
+    password: &[u8],
+    salt: &[u8],
+    iterations: u32,
+    len: usize,
+) -> Result<Vec<u8>> {
+    let key = import_key(password, &algorithm("PBKDF2", &[])?, "deriveBits").await?;
+    let algorithm = algorithm(
+        "PBKDF2",
+        &[
+            ("hash", hash.name().into()),
+            ("salt", buffer(salt).into()),
+            ("iterations", iterations.into()),
+        ],
+    )?;
+    derive_bits_with(&algorithm, &key, len).await
+}
+
+pub(crate) async fn hkdf(
+    hash: Hash,
+    key: &[u8],
+    salt: &[u8],
+    info: &[u8],
+    len: usize,
+) -> Result<Vec<u8>> {
+    let key = import_key(key, &algorithm("HKDF", &[])?, "deriveBits").await?;
+    let algorithm = algorithm(
+        "HKDF",
+        &[
+            ("hash", hash.name().into()),
+            ("salt", buffer(salt).into()),
+            ("info", buffer(info).into()),
+        ],
+    )?;
+    derive_bits_with(&algorithm, &key, len).await
+}
+
+fn subtle() -> Result<SubtleCrypto> {
+    Ok(crypto()?.subtle())
+}
+
+async fn call(promise: std::result::Result<Promise, JsValue>) -> Result<JsValue> {
+    let promise = promise.map_err(js_value_to_error)?;
+    JsFuture::from(promise).await.map_err(js_value_to_error)
+}
+
+/// Creates an algorithm object like `{ name: "HMAC", hash: "SHA-256" }`.
+fn algorithm(name: &str, params: &[(&str, JsValue)]) -> Result<Object> {
+    let algorithm = Object::new();
+    js_sys::Reflect::set(&algorithm, &"name".into(), &name.into()).map_err(js_value_to_error)?;
+    for (key, value) in params {
+        js_sys::Reflect::set(&algorithm, &(*key).into(), value).map_err(js_value_to_error)?;
+    }
+    Ok(algorithm)
+}
+
+fn hmac(hash: Hash) -> Result<Object> {
+    algorithm("HMAC", &[("hash", hash.name().into())])
+}
+
+fn aes_gcm(iv: &[u8], additional_data: &[u8]) -> Result<Object> {
+    algorithm(
+        "AES-GCM",
+        &[
+            ("iv", buffer(iv).into()),
+            ("additionalData", buffer(additional_data).into()),
+            ("tagLength", ((AES_GCM_TAG_LEN * 8) as u32).into()),
+        ],
+    )
+}
+
+/// Imports a raw, non-extractable key for a single usage.
+async fn import_key(key: &[u8], algorithm: &Object, usage: &str) -> Result<web_sys::CryptoKey> {
+    let usages = js_sys::Array::of1(&usage.into());
+    let promise = subtle()?.import_key_with_object("raw", &buffer(key), algorithm, false, &usages);
+    call(promise).await?.dyn_into().map_err(js_value_to_error)
+}
+
+async fn derive_bits_with(
+    algorithm: &Object,
+    key: &web_sys::CryptoKey,
+    len: usize,
+) -> Result<Vec<u8>> {
+    let promise = subtle()?.derive_bits_with_object(algorithm, key, (len * 8) as u32);
+    Ok(bytes(&call(promise).await?))
+}
+
+pub(crate) struct CryptoKey {
+    key: web_sys::CryptoKey,
+    algorithm: Algorithm,
+    key_type: KeyType,
+}
+
+impl CryptoKey {
+    pub(crate) async fn import_raw(algorithm: Algorithm, data: &[u8]) -> Result<Self> {
+        CryptoKey::import("raw", &buffer(data), algorithm, KeyType::Public, true).await
+    }
+
+    pub(crate) async fn import_spki(algorithm: Algorithm, der: &[u8]) -> Result<Self> {
+        CryptoKey::import("spki", &buffer(der), algorithm, KeyType::Public, true).await
+    }
+
+    pub(crate) async fn import_pkcs8(
+        algorithm: Algorithm,
+        der: &[u8],
+        extractable: bool,
+    ) -> Result<Self> {
+        let key_type = KeyType::Private;
+        CryptoKey::import("pkcs8", &buffer(der), algorithm, key_type, extractable).await
+    }
+
+    pub(crate) async fn import_jwk(algorithm: Algorithm, jwk: &Jwk, extractable: bool) -> Result<Self> {
+        let (key_type, extractable) = match jwk.d {
+            Some(_) => (KeyType::Private, extractable),
+            None => (KeyType::Public, true),
+        };
+        CryptoKey::import("jwk", &jwk_to_js(jwk)?, algorithm, key_type, extractable).await
+    }
+
+    async fn import(
+        format: &str,
+        data: &Object,
+        algorithm: Algorithm,
+        key_type: KeyType,
+        extractable: bool,
+    ) -> Result<Self> {
+        let promise = subtle()?.import_key_with_object(
+            format,
+            data,
+            &key_algorithm(algorithm)?,
+            extractable,
+            &usages(algorithm, key_type),
+        );
+        let key = call(promise).await?.dyn_into().map_err(js_value_to_error)?;
+        Ok(CryptoKey {
+            key,
+            algorithm,
+            key_type,
+        })
+    }
+
+    pub(crate) fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub(crate) fn key_type(&self) -> KeyType {
+        self.key_type
+    }
+
+    pub(crate) fn extractable(&self) -> bool {
+        self.key.extractable()
+    }
+
+    pub(crate) async fn export_raw(&self) -> Result<Vec<u8>> {
+        Ok(bytes(&self.export("raw").await?))
+    }
+
+    pub(crate) async fn export_spki(&self) -> Result<Vec<u8>> {
+        Ok(bytes(&self.export("spki").await?))
+    }
+
+    pub(crate) async fn export_pkcs8(&self) -> Result<Vec<u8>> {
+        Ok(bytes(&self.export("pkcs8").await?))
+    }
+
+    pub(crate) async fn export_jwk(&self) -> Result<Jwk> {
+        jwk_from_js(&self.export("jwk").await?)
+    }
+
+    async fn export(&self, format: &str) -> Result<JsValue> {
+        call(subtle()?.export_key(format, &self.key)).await
+    }
+}
+
+pub(crate) async fn generate_key_pair(
+    algorithm: Algorithm,
+    extractable: bool,
+) -> Result<(CryptoKey, CryptoKey)> {
+    let params = match algorithm {
+        Algorithm::RsaOaep(hash) => self::algorithm(
+            "RSA-OAEP",
+            &[
+                ("modulusLength", 2048.into()),
+                ("publicExponent", Uint8Array::from(&[1u8, 0, 1][..]).into()),
+                ("hash", hash.name().into()),
+            ],
+        )?,
+        algorithm => key_algorithm(algorithm)?,
+    };
+    // Key pairs are generated with the usages of both keys.
+    let usages = js_sys::Array::new();
+    for key_type in &[KeyType::Public, KeyType::Private] {
+        for usage in algorithm.usages(*key_type) {
+            usages.push(&(*usage).into());
+        }
+    }
+    let pair = call(subtle()?.generate_key_with_object(&params, extractable, &usages)).await?;
+    let key = |name: &str, key_type| -> Result<CryptoKey> {
+        let key = js_sys::Reflect::get(&pair, &name.into()).map_err(js_value_to_error)?;
+        Ok(CryptoKey {
+            key: key.dyn_into().map_err(js_value_to_error)?,
+            algorithm,
+            key_type,
+        })
+    };
+    Ok((key("publicKey", KeyType::Public)?, key("privateKey", KeyType::Private)?))
+}
+
+pub(crate) async fn sign(key: &CryptoKey, data: &[u8]) -> Result<Vec<u8>> {
+    let algorithm = operation_algorithm(key.algorithm)?;
+    let promise = subtle()?.sign_with_object_and_buffer_source(&algorithm, &key.key, &buffer(data));
+    Ok(bytes(&call(promise).await?))
+}
+
+pub(crate) async fn verify(key: &CryptoKey, signature: &[u8], data: &[u8]) -> Result<bool> {
+    let algorithm = operation_algorithm(key.algorithm)?;
+    let promise = subtle()?.verify_with_object_and_buffer_source_and_buffer_source(
+        &algorithm,
+        &key.key,
+        &buffer(signature),
+        &buffer(data),
+    );
+    Ok(call(promise).await?.as_bool().unwrap_or(false))
+}
+
+pub(crate) async fn encrypt(key: &CryptoKey, data: &[u8]) -> Result<Vec<u8>> {
+    let algorithm = operation_algorithm(key.algorithm)?;
+    let promise = subtle()?.encrypt_with_object_and_buffer_source(&algorithm, &key.key, &buffer(data));
+    Ok(bytes(&call(promise).await?))
+}
+
+pub(crate) async fn decrypt(key: &CryptoKey, data: &[u8]) -> Result<Vec<u8>> {
+    let algorithm = operation_algorithm(key.algorithm)?;
+    let promise = subtle()?.decrypt_with_object_and_buffer_source(&algorithm, &key.key, &buffer(data));
+    let decrypted = call(promise)
+        .await
+        .map_err(|_| format_err!("RSA-OAEP decryption failed"))?;
+    Ok(bytes(&decrypted))
+}
+
+pub(crate) async fn derive_bits(
+    private_key: &CryptoKey,
+    public_key: &CryptoKey,
+    len: usize,
+) -> Result<Vec<u8>> {
+    let algorithm = algorithm("ECDH", &[("public", public_key.key.clone().into())])?;
+    derive_bits_with(&algorithm, &private_key.key, len).await
+}
+
+/// Returns the algorithm used to import keys.
+fn key_algorithm(algorithm: Algorithm) -> Result<Object> {
+    match algorithm {
+        Algorithm::EcdsaP256 => self::algorithm("ECDSA", &[("namedCurve", "P-256".into())]),
+        Algorithm::Ed25519 => self::algorithm("Ed25519", &[]),
+        Algorithm::EcdhP256 => self::algorithm("ECDH", &[("namedCurve", "P-256".into())]),
+        Algorithm::RsaOaep(hash) => self::algorithm("RSA-OAEP", &[("hash", hash.name().into())]),
+    }
+}
+
+/// Returns the algorithm used to sign, verify, encrypt and decrypt.
+fn operation_algorithm(algorithm: Algorithm) -> Result<Object> {
+    match algorithm {
+        Algorithm::EcdsaP256 => self::algorithm("ECDSA", &[("hash", "SHA-256".into())]),
+        Algorithm::Ed25519 => self::algorithm("Ed25519", &[]),
+        Algorithm::RsaOaep(_) => self::algorithm("RSA-OAEP", &[]),
+        Algorithm::EcdhP256 => bail!("ECDH keys can only derive bits"),
+    }
+}
+
+fn usages(algorithm: Algorithm, key_type: KeyType) -> js_sys::Array {
+    algorithm
+        .usages(key_type)
+        .iter()
+        .map(|usage| JsValue::from_str(usage))
+        .collect()
+}
+
+fn jwk_to_js(jwk: &Jwk) -> Result<Object> {
+    let object = Object::new();
+    let set = |name: &str, value: &JsValue| {
+        js_sys::Reflect::set(&object, &name.into(), value).map_err(js_value_to_error)
+    };
+    set("kty", &jwk.kty.as_str().into())?;
+    for name in &Jwk::MEMBERS {
+        if let Some(Some(value)) = jwk.member(name) {
+            set(name, &value.as_str().into())?;
+        }
+    }
+    if !jwk.key_ops.is_empty() {
+        let key_ops = jwk.key_ops.iter().map(|op| JsValue::from_str(op));
+        set("key_ops", &key_ops.collect::<js_sys::Array>())?;
+    }
+    if let Some(ext) = jwk.ext {
+        set("ext", &ext.into())?;
+    }
+    Ok(object)
+}
+
+fn jwk_from_js(value: &JsValue) -> Result<Jwk> {
+    let get = |name: &str| js_sys::Reflect::get(value, &name.into()).map_err(js_value_to_error);
+    let mut jwk = Jwk {
+        kty: get("kty")?.as_string().unwrap_or_default(),
+        ext: get("ext")?.as_bool(),
+        ..Jwk::default()
+    };
+    for name in &Jwk::MEMBERS {
+        let member = get(name)?.as_string();
+        if let Some(slot) = jwk.member_mut(name) {
+            *slot = member;
+        }
+    }
+    if let Some(key_ops) = get("key_ops")?.dyn_ref::<js_sys::Array>() {
+        jwk.key_ops = key_ops.iter().filter_map(|op| op.as_string()).collect();
+    }
+    Ok(jwk)
+}
+
+fn buffer(data: &[u8]) -> Object {
+    Uint8Array::from(data).into()
+}
+
+fn bytes(buffer: &JsValue) -> Vec<u8> {
+    Uint8Array::new(buffer).to_vec()
+}
@@ -0,0 +1,524 @@
+use crate::crypto::subtle::{Algorithm, Hash, Jwk, KeyType, AES_GCM_IV_LEN};
+use crate::Result;
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::aead::consts::U12;
+use aes_gcm::{Aes128Gcm, Aes256Gcm, KeyInit, Nonce};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use failure::{bail, format_err};
+use futures_channel::oneshot;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use p256::ecdsa::signature::{Signer, Verifier};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey};
+use rand_core::OsRng;
+use rsa::traits::{PrivateKeyParts, PublicKeyParts};
+use rsa::{BigUint, Oaep, RsaPrivateKey, RsaPublicKey};
+use sha2::Digest;
+use std::thread;
+
+/// Evaluates an expression with a type alias for the hash function.
+macro_rules! with_hash {
+    ($hash: expr, $digest: ident => $body: expr) => {
+        match $hash {
+            Hash::Sha1 => {
+                type $digest = sha1::Sha1;
+                $body
+            }
+            Hash::Sha256 => {
+                type $digest = sha2::Sha256;
+                $body
+            }
+            Hash::Sha384 => {
+                type $digest = sha2::Sha384;
+                $body
+            }
+            Hash::Sha512 => {
+                type $digest = sha2::Sha512;
+                $body
+            }
+        }
+    };
+}
+
+pub(crate) async fn digest(hash: Hash, data: &[u8]) -> Result<Vec<u8>> {
+    Ok(with_hash!(hash, D => D::digest(data).to_vec()))
+}
+
+pub(crate) async fn hmac_sign(hash: Hash, key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    with_hash!(hash, D => {
+        let mut mac = <Hmac<D> as Mac>::new_from_slice(key)?;
+        mac.update(data);
+        Ok(mac.finalize().into_bytes().to_vec())
+    })
+}
+
+pub(crate) async fn hmac_verify(
+    hash: Hash,
+    key: &[u8],
+    signature: &[u8],
+    data: &[u8],
+) -> Result<bool> {
+    with_hash!(hash, D => {
+        let mut mac = <Hmac<D> as Mac>::new_from_slice(key)?;
+        mac.update(data);
+        Ok(mac.verify_slice(signature).is_ok())
+    })
+}
+
+pub(crate) async fn aes_gcm_encrypt(
+    key: &[u8],
+    iv: &[u8],
+    data: &[u8],
+    additional_data: &[u8],
+) -> Result<Vec<u8>> {
+    let payload = Payload {
+        msg: data,
+        aad: additional_data,
+    };
+    let nonce = &nonce(iv)?;
+    let encrypted = match key.len() {
+        16 => Aes128Gcm::new_from_slice(key)?.encrypt(nonce, payload),
+        _ => Aes256Gcm::new_from_slice(key)?.encrypt(nonce, payload),
+    };
+    encrypted.map_err(|_| format_err!("AES-GCM encryption failed"))
+}
+
+pub(crate) async fn aes_gcm_decrypt(
+    key: &[u8],
+    iv: &[u8],
+    data: &[u8],
+    additional_data: &[u8],
+) -> Result<Vec<u8>> {
+    let payload = Payload {
+        msg: data,
+        aad: additional_data,
+    };
+    let nonce = &nonce(iv)?;
+    let decrypted = match key.len() {
+        16 => Aes128Gcm::new_from_slice(key)?.decrypt(nonce, payload),
+        _ => Aes256Gcm::new_from_slice(key)?.decrypt(nonce, payload),
+    };
+    decrypted.map_err(|_| format_err!("AES-GCM decryption failed"))
+}
+
+fn nonce(iv: &[u8]) -> Result<Nonce<U12>> {
+    let mut nonce = [0; AES_GCM_IV_LEN];
+    if iv.len() != nonce.len() {
+        bail!("The IV must be {} bytes, not {}", AES_GCM_IV_LEN, iv.len());
+    }
+    nonce.copy_from_slice(iv);
+    Ok(Nonce::from(nonce))
+}
+
+pub(crate) async fn pbkdf2(
+    hash: Hash,
+    password: &[u8],
+    salt: &[u8],
+    iterations: u32,
+    len: usize,
+) -> Result<Vec<u8>> {
+    let (password, salt) = (password.to_vec(), salt.to_vec());
+    spawn_blocking(move || {
+        let mut key = vec![0; len];
+        with_hash!(hash, D => pbkdf2::pbkdf2_hmac::<D>(&password, &salt, iterations, &mut key));
+        Ok(key)
+    })
+    .await
+}
+
+/// Runs slow computations on a separate thread, so they don't block the
+/// executor polling the future.
+async fn spawn_blocking<T, F>(f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    let (sender, receiver) = oneshot::channel();
+    thread::Builder::new()
+        .name("libweb-crypto".to_string())
+        .spawn(move || {
+            let _ = sender.send(f());
+        })?;
+    receiver
+        .await
+        .map_err(|_| format_err!("crypto thread panicked"))?
+}
+
+pub(crate) async fn hkdf(
+    hash: Hash,
+    key: &[u8],
+    salt: &[u8],
+    info: &[u8],
+    len: usize,
+) -> Result<Vec<u8>> {
+    let mut derived = vec![0; len];
+    with_hash!(hash, D => Hkdf::<D>::new(Some(salt), key).expand(info, &mut derived))
+        .map_err(|err| format_err!("HKDF failed: {}", err))?;
+    Ok(derived)
+}
+
+/// The key material of a `CryptoKey`. P-256 keys are used for both ECDSA
+/// and ECDH.
+enum Key {
+    P256Public(p256::PublicKey),
+    P256Private(p256::SecretKey),
+    Ed25519Public(VerifyingKey),
+    Ed25519Private(SigningKey),
+    RsaPublic(RsaPublicKey),
+    RsaPrivate(RsaPrivateKey),
+}
+
+pub(crate) struct CryptoKey {
+    algorithm: Algorithm,
+    extractable: bool,
+    key: Key,
+}
+
+impl CryptoKey {
+    pub(crate) async fn import_raw(algorithm: Algorithm, data: &[u8]) -> Result<Self> {
+        let key = match algorithm {
+            Algorithm::EcdsaP256 | Algorithm::EcdhP256 => {
+                Key::P256Public(p256::PublicKey::from_sec1_bytes(data)?)
+            }
+            Algorithm::Ed25519 => Key::Ed25519Public(VerifyingKey::from_bytes(&array(data)?)?),
+            Algorithm::RsaOaep(_) => bail!("RSA keys can't be imported from raw data"),
+        };
+        Ok(CryptoKey::public(algorithm, key))
+    }
+
+    pub(crate) async fn import_spki(algorithm: Algorithm, der: &[u8]) -> Result<Self> {
+        let key = match algorithm {
+            Algorithm::EcdsaP256 | Algorithm::EcdhP256 => {
+                Key::P256Public(p256::PublicKey::from_public_key_der(der)?)
+            }
+            Algorithm::Ed25519 => Key::Ed25519Public(VerifyingKey::from_public_key_der(der)?),
+            Algorithm::RsaOaep(_) => Key::RsaPublic(RsaPublicKey::from_public_key_der(der)?),
+        };
+        Ok(CryptoKey::public(algorithm, key))
+    }
+
+    pub(crate) async fn import_pkcs8(
+        algorithm: Algorithm,
+        der: &[u8],
+        extractable: bool,
+    ) -> Result<Self> {
+        let key = match algorithm {
+            Algorithm::EcdsaP256 | Algorithm::EcdhP256 => {
+                Key::P256Private(p256::SecretKey::from_pkcs8_der(der)?)
+            }
+            Algorithm::Ed25519 => Key::Ed25519Private(SigningKey::from_pkcs8_der(der)?),
+            Algorithm::RsaOaep(_) => Key::RsaPrivate(RsaPrivateKey::from_pkcs8_der(der)?),
+        };
+        Ok(CryptoKey {
+            algorithm,
+            extractable,
+            key,
+        })
+    }
+
+    pub(crate) async fn import_jwk(algorithm: Algorithm, jwk: &Jwk, extractable: bool) -> Result<Self> {
+        let expected = match algorithm {
+            Algorithm::EcdsaP256 | Algorithm::EcdhP256 => ("EC", Some("P-256")),
+            Algorithm::Ed25519 => ("OKP", Some("Ed25519")),
+            Algorithm::RsaOaep(_) => ("RSA", None),
+        };
+        if (jwk.kty.as_str(), jwk.crv.as_deref()) != expected {
+            bail!("The JWK is not a {:?} key", algorithm);
+        }
+        if let (Some(alg), Some(expected)) = (&jwk.alg, jwk_alg(algorithm)) {
+            if alg != expected {
+                bail!("The JWK is for {}, not {}", alg, expected);
+            }
+        }
+        let key = match algorithm {
+            Algorithm::EcdsaP256 | Algorithm::EcdhP256 => {
+                let (x, y) = (member(jwk, "x")?, member(jwk, "y")?);
+                if x.len() != 32 || y.len() != 32 {
+                    bail!("Invalid P-256 JWK coordinates");
+                }
+                let (x, y): (p256::FieldBytes, p256::FieldBytes) =
+                    (x.into_iter().collect(), y.into_iter().collect());
+                let point = p256::EncodedPoint::from_affine_coordinates(&x, &y, false);
+                let public = p256::PublicKey::from_sec1_bytes(point.as_bytes())?;
+                match &jwk.d {
+                    Some(_) => {
+                        let private = p256::SecretKey::from_slice(&member(jwk, "d")?)?;
+                        if private.public_key() != public {
+                            bail!("The JWK public and private keys don't match");
+                        }
+                        Key::P256Private(private)
+                    }
+                    None => Key::P256Public(public),
+                }
+            }
+            Algorithm::Ed25519 => {
+                let public = VerifyingKey::from_bytes(&array(&member(jwk, "x")?)?)?;
+                match &jwk.d {
+                    Some(_) => {
+                        let private = SigningKey::from_bytes(&array(&member(jwk, "d")?)?);
+                        if private.verifying_key() != public {
+                            bail!("The JWK public and private keys don't match");
+                        }
+                        Key::Ed25519Private(private)
+                    }
+                    None => Key::Ed25519Public(public),
+                }
+            }
+            Algorithm::RsaOaep(_) => {
+                let uint = |name| Ok::<_, failure::Error>(BigUint::from_bytes_be(&member(jwk, name)?));
+                match &jwk.d {
+                    Some(_) => {
+                        let primes = vec![uint("p")?, uint("q")?];
+                        let mut private =
+                            RsaPrivateKey::from_components(uint("n")?, uint("e")?, uint("d")?, primes)?;
+                        private.validate()?;
+                        private.precompute()?;
+                        Key::RsaPrivate(private)
+                    }
+                    None => Key::RsaPublic(RsaPublicKey::new(uint("n")?, uint("e")?)?),
+                }
+            }
+        };
+        Ok(match key {
+            Key::P256Public(_) | Key::Ed25519Public(_) | Key::RsaPublic(_) => {
+                CryptoKey::public(algorithm, key)
+            }
+            key => CryptoKey {
+                algorithm,
+                extractable,
+                key,
+            },
+        })
+    }
+
+    /// Public keys are always extractable.
+    fn public(algorithm: Algorithm, key: Key) -> Self {
+        CryptoKey {
+            algorithm,
+            extractable: true,
+            key,
+        }
+    }
+
+    pub(crate) fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub(crate) fn key_type(&self) -> KeyType {
+        match self.key {
+            Key::P256Public(_) | Key::Ed25519Public(_) | Key::RsaPublic(_) => KeyType::Public,
+            _ => KeyType::Private,
+        }
+    }
+
+    pub(crate) fn extractable(&self) -> bool {
+        self.extractable
+    }
+
+    pub(crate) async fn export_raw(&self) -> Result<Vec<u8>> {
+        match &self.key {
+            Key::P256Public(key) => Ok(key.to_encoded_point(false).as_bytes().to_vec()),
+            Key::Ed25519Public(key) => Ok(key.to_bytes().to_vec()),
+            _ => bail!("The key can't be exported as raw data"),
+        }
+    }
+
+    pub(crate) async fn export_spki(&self) -> Result<Vec<u8>> {
+        let der = match &self.key {
+            Key::P256Public(key) => key.to_public_key_der()?,
+            Key::Ed25519Public(key) => key.to_public_key_der()?,
+            Key::RsaPublic(key) => key.to_public_key_der()?,
+            _ => bail!("Private keys can't be exported as SPKI"),
+        };
+        Ok(der.as_bytes().to_vec())
+    }
+
+    pub(crate) async fn export_pkcs8(&self) -> Result<Vec<u8>> {
+        let der = match &self.key {
+            Key::P256Private(key) => key.to_pkcs8_der()?,
+            Key::Ed25519Private(key) => key.to_pkcs8_der()?,
+            Key::RsaPrivate(key) => key.to_pkcs8_der()?,
+            _ => bail!("Public keys can't be exported as PKCS #8"),
+        };
+        Ok(der.as_bytes().to_vec())
+    }
+
+    pub(crate) async fn export_jwk(&self) -> Result<Jwk> {
+        let mut jwk = Jwk {
+            alg: jwk_alg(self.algorithm).map(str::to_string),
+            key_ops: (self.algorithm.usages(self.key_type()).iter())
+                .map(|usage| usage.to_string())
+                .collect(),
+            ext: Some(self.extractable),
+            ..Jwk::default()
+        };
+        let ec = |jwk: &mut Jwk, public: &p256::PublicKey| {
+            let point = public.to_encoded_point(false);
+            jwk.kty = "EC".into();
+            jwk.crv = Some("P-256".into());
+            jwk.x = point.x().map(|x| encode(x));
+            jwk.y = point.y().map(|y| encode(y));
+        };
+        let okp = |jwk: &mut Jwk, public: &VerifyingKey| {
+            jwk.kty = "OKP".into();
+            jwk.crv = Some("Ed25519".into());
+            jwk.x = Some(encode(public.as_bytes()));
+        };
+        let rsa = |jwk: &mut Jwk, public: &RsaPublicKey| {
+            jwk.kty = "RSA".into();
+            jwk.n = Some(encode(&public.n().to_bytes_be()));
+            jwk.e = Some(encode(&public.e().to_bytes_be()));
+        };
+        match &self.key {
+            Key::P256Public(key) => ec(&mut jwk, key),
+            Key::P256Private(key) => {
+                ec(&mut jwk, &key.public_key());
+                jwk.d = Some(encode(&key.to_bytes()));
+            }
+            Key::Ed25519Public(key) => okp(&mut jwk, key),
+            Key::Ed25519Private(key) => {
+                okp(&mut jwk, &key.verifying_key());
+                jwk.d = Some(encode(key.as_bytes()));
+            }
+            Key::RsaPublic(key) => rsa(&mut jwk, key),
+            Key::RsaPrivate(key) => {
+                rsa(&mut jwk, &key.to_public_key());
+                let uint = |uint: Option<&BigUint>| uint.map(|uint| encode(&uint.to_bytes_be()));
+                jwk.d = uint(Some(key.d()));
+                jwk.p = uint(key.primes().first());
+                jwk.q = uint(key.primes().get(1));
+                jwk.dp = uint(key.dp());
+                jwk.dq = uint(key.dq());
+                jwk.qi = uint(key.crt_coefficient().as_ref());
+            }
+        }
+        Ok(jwk)
+    }
+}
+
+/// Returns the `alg` member of JWKs. Only RSA-OAEP keys are exported with
+/// it, like in browsers.
+fn jwk_alg(algorithm: Algorithm) -> Option<&'static str> {
+    match algorithm {
+        Algorithm::RsaOaep(Hash::Sha1) => Some("RSA-OAEP"),
+        Algorithm::RsaOaep(Hash::Sha256) => Some("RSA-OAEP-256"),
+        Algorithm::RsaOaep(Hash::Sha384) => Some("RSA-OAEP-384"),
+        Algorithm::RsaOaep(Hash::Sha512) => Some("RSA-OAEP-512"),
+        _ => None,
+    }
+}
+
+/// Decodes a binary member of a JWK.
+fn member(jwk: &Jwk, name: &str) -> Result<Vec<u8>> {
+    let value = jwk
+        .member(name)
+        .and_then(Option::as_ref)
+        .ok_or_else(|| format_err!("The JWK has no '{}' member", name))?;
+    base64::decode_config(value, base64::URL_SAFE_NO_PAD)
+        .map_err(|_| format_err!("Invalid JWK member '{}'", name))
+}
+
+fn encode(data: &[u8]) -> String {
+    base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+}
+
+fn array(data: &[u8]) -> Result<[u8; 32]> {
+    let mut array = [0; 32];
+    if data.len() != array.len() {
+        bail!("Ed25519 keys must be 32 bytes, not {}", data.len());
+    }
+    array.copy_from_slice(data);
+    Ok(array)
+}
+
+pub(crate) async fn generate_key_pair(
+    algorithm: Algorithm,
+    extractable: bool,
+) -> Result<(CryptoKey, CryptoKey)> {
+    let (public, private) = match algorithm {
+        Algorithm::EcdsaP256 | Algorithm::EcdhP256 => {
+            let private = p256::SecretKey::random(&mut OsRng);
+            (Key::P256Public(private.public_key()), Key::P256Private(private))
+        }
+        Algorithm::Ed25519 => {
+            let private = SigningKey::generate(&mut OsRng);
+            (Key::Ed25519Public(private.verifying_key()), Key::Ed25519Private(private))
+        }
+        Algorithm::RsaOaep(_) => {
+            let private = spawn_blocking(|| Ok(RsaPrivateKey::new(&mut OsRng, 2048)?)).await?;
+            (Key::RsaPublic(private.to_public_key()), Key::RsaPrivate(private))
+        }
+    };
+    let private = CryptoKey {
+        algorithm,
+        extractable,
+        key: private,
+    };
+    Ok((CryptoKey::public(algorithm, public), private))
+}
+
+pub(crate) async fn sign(key: &CryptoKey, data: &[u8]) -> Result<Vec<u8>> {
+    match &key.key {
+        Key::P256Private(key) => {
+            let signature: p256::ecdsa::Signature = p256::ecdsa::SigningKey::from(key).sign(data);
+            Ok(signature.to_bytes().to_vec())
+        }
+        Key::Ed25519Private(key) => Ok(key.sign(data).to_bytes().to_vec()),
+        _ => bail!("The key can't be used to sign"),
+    }
+}
+
+pub(crate) async fn verify(key: &CryptoKey, signature: &[u8], data: &[u8]) -> Result<bool> {
+    match &key.key {
+        Key::P256Public(key) => {
+            let signature = match p256::ecdsa::Signature::from_slice(signature) {
+                Ok(signature) => signature,
+                Err(_) => return Ok(false),
+            };
+            let key = p256::ecdsa::VerifyingKey::from(key);
+            Ok(key.verify(data, &signature).is_ok())
+        }
+        Key::Ed25519Public(key) => {
+            let signature = match ed25519_dalek::Signature::from_slice(signature) {
+                Ok(signature) => signature,
+                Err(_) => return Ok(false),
+            };
+            Ok(key.verify(data, &signature).is_ok())
+        }
+        _ => bail!("The key can't be used to verify"),
+    }
+}
+
+pub(crate) async fn encrypt(key: &CryptoKey, data: &[u8]) -> Result<Vec<u8>> {
+    match (&key.key, key.algorithm) {
+        (Key::RsaPublic(public), Algorithm::RsaOaep(hash)) => {
+            Ok(with_hash!(hash, D => public.encrypt(&mut OsRng, Oaep::new::<D>(), data))?)
+        }
+        _ => bail!("The key can't be used to encrypt"),
+    }
+}
+
+pub(crate) async fn decrypt(key: &CryptoKey, data: &[u8]) -> Result<Vec<u8>> {
+    match (&key.key, key.algorithm) {
+        (Key::RsaPrivate(private), Algorithm::RsaOaep(hash)) => {
+            with_hash!(hash, D => private.decrypt(Oaep::new::<D>(), data))
+                .map_err(|_| format_err!("RSA-OAEP decryption failed"))
+        }
+        _ => bail!("The key can't be used to decrypt"),
+    }
+}
+
+pub(crate) async fn derive_bits(
+    private_key: &CryptoKey,
+    public_key: &CryptoKey,
+    len: usize,
+) -> Result<Vec<u8>> {
+    match (&private_key.key, &public_key.key) {
+        (Key::P256Private(private), Key::P256Public(public)) => {
+            let shared = p256::ecdh::diffie_hellman(private.to_nonzero_scalar(), public.as_affine());
+            Ok(shared.raw_secret_bytes()[..len].to_vec())
+        }
+        _ => bail!("The keys can't be used to derive bits"),
+    }
+}
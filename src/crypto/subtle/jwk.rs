@@ -0,0 +1,199 @@
+use std::fmt;
+#[cfg(feature = "serde")]
+use {crate::Result, failure::format_err, serde_json::Value};
+
+/// A JSON Web Key as defined by RFC 7517.
+///
+/// Binary members are base64url encoded without padding.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Jwk {
+    /// The key type, `EC`, `OKP` or `RSA`.
+    pub kty: String,
+    /// The curve of `EC` and `OKP` keys.
+    pub crv: Option<String>,
+    /// The x coordinate of `EC` keys or the public key of `OKP` keys.
+    pub x: Option<String>,
+    /// The y coordinate of `EC` keys.
+    pub y: Option<String>,
+    /// The private key of `EC` and `OKP` keys, or the private exponent of
+    /// `RSA` keys.
+    pub d: Option<String>,
+    /// The modulus of `RSA` keys.
+    pub n: Option<String>,
+    /// The public exponent of `RSA` keys.
+    pub e: Option<String>,
+    /// The first prime factor of `RSA` keys.
+    pub p: Option<String>,
+    /// The second prime factor of `RSA` keys.
+    pub q: Option<String>,
+    /// The first factor CRT exponent of `RSA` keys.
+    pub dp: Option<String>,
+    /// The second factor CRT exponent of `RSA` keys.
+    pub dq: Option<String>,
+    /// The first CRT coefficient of `RSA` keys.
+    pub qi: Option<String>,
+    /// The algorithm the key is intended for.
+    pub alg: Option<String>,
+    /// The operations the key is intended for.
+    pub key_ops: Vec<String>,
+    /// Whether the key is extractable.
+    pub ext: Option<bool>,
+}
+
+impl Jwk {
+    /// The names of the optional string members.
+    pub(crate) const MEMBERS: [&'static str; 12] =
+        ["crv", "x", "y", "d", "n", "e", "p", "q", "dp", "dq", "qi", "alg"];
+
+    pub(crate) fn member(&self, name: &str) -> Option<&Option<String>> {
+        Some(match name {
+            "crv" => &self.crv,
+            "x" => &self.x,
+            "y" => &self.y,
+            "d" => &self.d,
+            "n" => &self.n,
+            "e" => &self.e,
+            "p" => &self.p,
+            "q" => &self.q,
+            "dp" => &self.dp,
+            "dq" => &self.dq,
+            "qi" => &self.qi,
+            "alg" => &self.alg,
+            _ => return None,
+        })
+    }
+
+    #[cfg(any(target_arch = "wasm32", feature = "serde"))]
+    pub(crate) fn member_mut(&mut self, name: &str) -> Option<&mut Option<String>> {
+        Some(match name {
+            "crv" => &mut self.crv,
+            "x" => &mut self.x,
+            "y" => &mut self.y,
+            "d" => &mut self.d,
+            "n" => &mut self.n,
+            "e" => &mut self.e,
+            "p" => &mut self.p,
+            "q" => &mut self.q,
+            "dp" => &mut self.dp,
+            "dq" => &mut self.dq,
+            "qi" => &mut self.qi,
+            "alg" => &mut self.alg,
+            _ => return None,
+        })
+    }
+
+    /// Converts the key to a JSON object.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> Value {
+        let mut object = serde_json::Map::new();
+        object.insert("kty".into(), self.kty.clone().into());
+        for name in &Jwk::MEMBERS {
+            if let Some(Some(value)) = self.member(name) {
+                object.insert(name.to_string(), value.clone().into());
+            }
+        }
+        if !self.key_ops.is_empty() {
+            object.insert("key_ops".into(), self.key_ops.clone().into());
+        }
+        if let Some(ext) = self.ext {
+            object.insert("ext".into(), ext.into());
+        }
+        Value::Object(object)
+    }
+
+    /// Parses a key from a JSON object, ignoring unknown members.
+    #[cfg(feature = "serde")]
+    pub fn from_json(value: &Value) -> Result<Jwk> {
+        let invalid = |name| format_err!("Invalid JWK member '{}'", name);
+        let kty = value["kty"].as_str().ok_or_else(|| invalid("kty"))?;
+        let mut jwk = Jwk {
+            kty: kty.to_string(),
+            ..Jwk::default()
+        };
+        for name in &Jwk::MEMBERS {
+            let member = match &value[*name] {
+                Value::Null => None,
+                member => Some(member.as_str().ok_or_else(|| invalid(name))?.to_string()),
+            };
+            if let Some(slot) = jwk.member_mut(name) {
+                *slot = member;
+            }
+        }
+        if let Some(key_ops) = value.get("key_ops") {
+            let key_ops = key_ops.as_array().ok_or_else(|| invalid("key_ops"))?;
+            for op in key_ops {
+                let op = op.as_str().ok_or_else(|| invalid("key_ops"))?;
+                jwk.key_ops.push(op.to_string());
+            }
+        }
+        if let Some(ext) = value.get("ext") {
+            jwk.ext = Some(ext.as_bool().ok_or_else(|| invalid("ext"))?);
+        }
+        Ok(jwk)
+    }
+}
+
+/// The members of private keys, which are hidden by `Debug`.
+const PRIVATE: [&str; 6] = ["d", "p", "q", "dp", "dq", "qi"];
+
+impl fmt::Debug for Jwk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut debug = f.debug_struct("Jwk");
+        debug.field("kty", &self.kty);
+        for name in &Jwk::MEMBERS {
+            match self.member(name) {
+                Some(Some(_)) if PRIVATE.contains(name) => {
+                    debug.field(name, &"..");
+                }
+                Some(Some(value)) => {
+                    debug.field(name, value);
+                }
+                _ => {}
+            }
+        }
+        debug
+            .field("key_ops", &self.key_ops)
+            .field("ext", &self.ext)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debug() {
+        let jwk = Jwk {
+            kty: "OKP".into(),
+            crv: Some("Ed25519".into()),
+            d: Some("secret".into()),
+            ..Jwk::default()
+        };
+        let debug = format!("{:?}", jwk);
+        assert!(debug.contains("Ed25519"));
+        assert!(!debug.contains("secret"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json() {
+        let json = serde_json::json!({
+            "kty": "EC",
+            "crv": "P-256",
+            "x": "x",
+            "y": "y",
+            "key_ops": ["verify"],
+            "ext": true,
+            "use": "sig",
+        });
+        let jwk = Jwk::from_json(&json).unwrap();
+        assert_eq!(jwk.crv.as_deref(), Some("P-256"));
+        assert_eq!(jwk.key_ops, vec!["verify"]);
+        assert_eq!(jwk.d, None);
+        let mut expected = json;
+        expected.as_object_mut().unwrap().remove("use");
+        assert_eq!(jwk.to_json(), expected);
+        assert!(Jwk::from_json(&serde_json::json!({ "kty": "EC", "x": 1 })).is_err());
+    }
+}
@@ -0,0 +1,678 @@
+//! The SubtleCrypto API
+//!
+//! Symmetric keys are passed as raw bytes. In the browser they are imported
+//! with `crypto.subtle.importKey` for every operation, on desktop the
+//! operations are implemented with the RustCrypto crates. Asymmetric keys
+//! are imported or generated as a `CryptoKey`.
+use crate::Result;
+use failure::bail;
+use std::fmt;
+
+#[cfg(target_arch = "wasm32")]
+mod browser;
+#[cfg(not(target_arch = "wasm32"))]
+mod desktop;
+mod jwk;
+
+#[cfg(target_arch = "wasm32")]
+use self::browser as backend;
+#[cfg(not(target_arch = "wasm32"))]
+use self::desktop as backend;
+
+pub use self::jwk::Jwk;
+
+/// The length of AES-GCM initialization vectors in bytes.
+pub const AES_GCM_IV_LEN: usize = 12;
+
+/// The length of AES-GCM authentication tags in bytes, which are appended
+/// to the ciphertext.
+pub const AES_GCM_TAG_LEN: usize = 16;
+
+/// A hash function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Hash {
+    /// SHA-1, which should only be used for compatibility.
+    Sha1,
+    /// SHA-256.
+    Sha256,
+    /// SHA-384.
+    Sha384,
+    /// SHA-512.
+    Sha512,
+}
+
+impl Hash {
+    /// Returns the name used by the Web Crypto API.
+    pub fn name(self) -> &'static str {
+        match self {
+            Hash::Sha1 => "SHA-1",
+            Hash::Sha256 => "SHA-256",
+            Hash::Sha384 => "SHA-384",
+            Hash::Sha512 => "SHA-512",
+        }
+    }
+
+    /// Returns the length of the digest in bytes.
+    pub fn output_len(self) -> usize {
+        match self {
+            Hash::Sha1 => 20,
+            Hash::Sha256 => 32,
+            Hash::Sha384 => 48,
+            Hash::Sha512 => 64,
+        }
+    }
+}
+
+impl fmt::Display for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Returns the digest of the data.
+pub async fn digest(hash: Hash, data: &[u8]) -> Result<Vec<u8>> {
+    backend::digest(hash, data).await
+}
+
+/// Signs the data with HMAC.
+pub async fn hmac_sign(hash: Hash, key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    check_hmac_key(key)?;
+    backend::hmac_sign(hash, key, data).await
+}
+
+/// Returns whether the HMAC signature of the data is valid.
+///
+/// The signature is compared in constant time.
+pub async fn hmac_verify(hash: Hash, key: &[u8], signature: &[u8], data: &[u8]) -> Result<bool> {
+    check_hmac_key(key)?;
+    backend::hmac_verify(hash, key, signature, data).await
+}
+
+/// Encrypts the data with AES-GCM, returning the ciphertext followed by the
+/// authentication tag.
+///
+/// The key must be 16 or 32 bytes and the initialization vector 12 bytes.
+/// An initialization vector must never be reused with the same key.
+pub async fn aes_gcm_encrypt(
+    key: &[u8],
+    iv: &[u8],
+    data: &[u8],
+    additional_data: &[u8],
+) -> Result<Vec<u8>> {
+    check_aes_gcm(key, iv)?;
+    backend::aes_gcm_encrypt(key, iv, data, additional_data).await
+}
+
+/// Decrypts and authenticates data encrypted by `aes_gcm_encrypt`.
+///
+/// Fails if the data or the additional data were modified.
+pub async fn aes_gcm_decrypt(
+    key: &[u8],
+    iv: &[u8],
+    data: &[u8],
+    additional_data: &[u8],
+) -> Result<Vec<u8>> {
+    check_aes_gcm(key, iv)?;
+    if data.len() < AES_GCM_TAG_LEN {
+        bail!("AES-GCM data must include the {} byte tag", AES_GCM_TAG_LEN);
+    }
+    backend::aes_gcm_decrypt(key, iv, data, additional_data).await
+}
+
+/// Derives `len` bytes from a password with PBKDF2.
+///
+/// On desktop the key is derived on a separate thread, since a high number of
+/// iterations takes a while.
+pub async fn pbkdf2(
+    hash: Hash,
+    password: &[u8],
+    salt: &[u8],
+    iterations: u32,
+    len: usize,
+) -> Result<Vec<u8>> {
+    if iterations == 0 {
+        bail!("PBKDF2 needs at least one iteration");
+    }
+    backend::pbkdf2(hash, password, salt, iterations, len).await
+}
+
+/// Derives `len` bytes from a key with HKDF.
+///
+/// At most 255 times the output length of the hash can be derived.
+pub async fn hkdf(hash: Hash, key: &[u8], salt: &[u8], info: &[u8], len: usize) -> Result<Vec<u8>> {
+    if len > 255 * hash.output_len() {
+        bail!("HKDF with {} can derive at most {} bytes", hash, 255 * hash.output_len());
+    }
+    backend::hkdf(hash, key, salt, info, len).await
+}
+
+/// An asymmetric key algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Algorithm {
+    /// ECDSA with the P-256 curve, signing SHA-256 digests.
+    EcdsaP256,
+    /// Ed25519 signatures.
+    Ed25519,
+    /// ECDH key agreement with the P-256 curve.
+    EcdhP256,
+    /// RSA-OAEP encryption using a hash function for padding. Generated keys
+    /// have a 2048 bit modulus and a public exponent of 65537.
+    RsaOaep(Hash),
+}
+
+impl Algorithm {
+    /// Returns the usages of keys, which are fixed for every algorithm and
+    /// key type.
+    pub(crate) fn usages(self, key_type: KeyType) -> &'static [&'static str] {
+        match (self, key_type) {
+            (Algorithm::EcdsaP256, KeyType::Public) | (Algorithm::Ed25519, KeyType::Public) => {
+                &["verify"]
+            }
+            (Algorithm::EcdsaP256, KeyType::Private) | (Algorithm::Ed25519, KeyType::Private) => {
+                &["sign"]
+            }
+            (Algorithm::EcdhP256, KeyType::Public) => &[],
+            (Algorithm::EcdhP256, KeyType::Private) => &["deriveBits"],
+            (Algorithm::RsaOaep(_), KeyType::Public) => &["encrypt"],
+            (Algorithm::RsaOaep(_), KeyType::Private) => &["decrypt"],
+        }
+    }
+}
+
+/// The type of an asymmetric key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyType {
+    /// A public key, which can always be exported.
+    Public,
+    /// A private key.
+    Private,
+}
+
+/// An asymmetric key.
+///
+/// Private keys can only be exported if they were imported or generated as
+/// extractable.
+pub struct CryptoKey {
+    inner: backend::CryptoKey,
+}
+
+impl CryptoKey {
+    /// Imports a public key from its raw encoding, which is an uncompressed
+    /// point for P-256 keys and 32 bytes for Ed25519 keys.
+    ///
+    /// RSA keys can't be imported from raw data.
+    pub async fn import_raw(algorithm: Algorithm, data: &[u8]) -> Result<Self> {
+        if let Algorithm::RsaOaep(_) = algorithm {
+            bail!("RSA keys can't be imported from raw data");
+        }
+        Ok(CryptoKey {
+            inner: backend::CryptoKey::import_raw(algorithm, data).await?,
+        })
+    }
+
+    /// Imports a public key from a DER encoded SubjectPublicKeyInfo.
+    pub async fn import_spki(algorithm: Algorithm, der: &[u8]) -> Result<Self> {
+        Ok(CryptoKey {
+            inner: backend::CryptoKey::import_spki(algorithm, der).await?,
+        })
+    }
+
+    /// Imports a private key from a DER encoded PKCS #8 PrivateKeyInfo.
+    pub async fn import_pkcs8(algorithm: Algorithm, der: &[u8], extractable: bool) -> Result<Self> {
+        Ok(CryptoKey {
+            inner: backend::CryptoKey::import_pkcs8(algorithm, der, extractable).await?,
+        })
+    }
+
+    /// Imports a public or private key from a JSON Web Key. Keys with a `d`
+    /// member are private.
+    pub async fn import_jwk(algorithm: Algorithm, jwk: &Jwk, extractable: bool) -> Result<Self> {
+        if jwk.ext == Some(false) && extractable {
+            bail!("The JWK can't be imported as extractable");
+        }
+        Ok(CryptoKey {
+            inner: backend::CryptoKey::import_jwk(algorithm, jwk, extractable).await?,
+        })
+    }
+
+    /// Returns the algorithm of the key.
+    pub fn algorithm(&self) -> Algorithm {
+        self.inner.algorithm()
+    }
+
+    /// Returns whether the key is public or private.
+    pub fn key_type(&self) -> KeyType {
+        self.inner.key_type()
+    }
+
+    /// Returns whether the key can be exported.
+    pub fn extractable(&self) -> bool {
+        self.inner.extractable()
+    }
+
+    /// Exports a public key in its raw encoding.
+    pub async fn export_raw(&self) -> Result<Vec<u8>> {
+        self.check_export(Some(KeyType::Public))?;
+        if let Algorithm::RsaOaep(_) = self.algorithm() {
+            bail!("RSA keys can't be exported as raw data");
+        }
+        self.inner.export_raw().await
+    }
+
+    /// Exports a public key as a DER encoded SubjectPublicKeyInfo.
+    pub async fn export_spki(&self) -> Result<Vec<u8>> {
+        self.check_export(Some(KeyType::Public))?;
+        self.inner.export_spki().await
+    }
+
+    /// Exports a private key as a DER encoded PKCS #8 PrivateKeyInfo.
+    pub async fn export_pkcs8(&self) -> Result<Vec<u8>> {
+        self.check_export(Some(KeyType::Private))?;
+        self.inner.export_pkcs8().await
+    }
+
+    /// Exports the key as a JSON Web Key.
+    pub async fn export_jwk(&self) -> Result<Jwk> {
+        self.check_export(None)?;
+        self.inner.export_jwk().await
+    }
+
+    fn check_export(&self, key_type: Option<KeyType>) -> Result<()> {
+        if !self.extractable() {
+            bail!("The key is not extractable");
+        }
+        match key_type {
+            Some(key_type) if key_type != self.key_type() => {
+                bail!("Only {:?} keys can be exported in this format", key_type)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails unless the key is of the type and one of the algorithms.
+    fn check(&self, key_type: KeyType, algorithms: &[Algorithm], operation: &str) -> Result<()> {
+        let algorithm = match self.algorithm() {
+            // RSA-OAEP keys use any hash.
+            Algorithm::RsaOaep(_) => Algorithm::RsaOaep(Hash::Sha256),
+            algorithm => algorithm,
+        };
+        if self.key_type() != key_type || !algorithms.contains(&algorithm) {
+            bail!(
+                "{:?} {:?} keys can't be used to {}",
+                self.algorithm(),
+                self.key_type(),
+                operation
+            );
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for CryptoKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CryptoKey")
+            .field("algorithm", &self.algorithm())
+            .field("key_type", &self.key_type())
+            .field("extractable", &self.extractable())
+            .finish()
+    }
+}
+
+/// A public and a private key.
+#[derive(Debug)]
+pub struct KeyPair {
+    /// The public key, which is always extractable.
+    pub public_key: CryptoKey,
+    /// The private key.
+    pub private_key: CryptoKey,
+}
+
+/// Generates a key pair.
+///
+/// RSA keys have 2048 bits. Generating them takes a while, so on desktop they
+/// are generated on a separate thread.
+pub async fn generate_key_pair(algorithm: Algorithm, extractable: bool) -> Result<KeyPair> {
+    let (public_key, private_key) = backend::generate_key_pair(algorithm, extractable).await?;
+    Ok(KeyPair {
+        public_key: CryptoKey { inner: public_key },
+        private_key: CryptoKey { inner: private_key },
+    })
+}
+
+/// Signs the data with an ECDSA or Ed25519 private key.
+///
+/// ECDSA signatures are the concatenated `r` and `s` values, like in the
+/// browser, not DER encoded.
+pub async fn sign(key: &CryptoKey, data: &[u8]) -> Result<Vec<u8>> {
+    let algorithms = [Algorithm::EcdsaP256, Algorithm::Ed25519];
+    key.check(KeyType::Private, &algorithms, "sign")?;
+    backend::sign(&key.inner, data).await
+}
+
+/// Returns whether the signature of the data is valid for an ECDSA or
+/// Ed25519 public key.
+pub async fn verify(key: &CryptoKey, signature: &[u8], data: &[u8]) -> Result<bool> {
+    let algorithms = [Algorithm::EcdsaP256, Algorithm::Ed25519];
+    key.check(KeyType::Public, &algorithms, "verify")?;
+    backend::verify(&key.inner, signature, data).await
+}
+
+/// Encrypts the data with an RSA-OAEP public key.
+pub async fn encrypt(key: &CryptoKey, data: &[u8]) -> Result<Vec<u8>> {
+    let algorithms = [Algorithm::RsaOaep(Hash::Sha256)];
+    key.check(KeyType::Public, &algorithms, "encrypt")?;
+    backend::encrypt(&key.inner, data).await
+}
+
+/// Decrypts the data with an RSA-OAEP private key.
+///
+/// On desktop decryption isn't constant time: the `rsa` crate is affected by
+/// the Marvin attack (RUSTSEC-2023-0071), which lets an attacker who can time
+/// many decryptions of chosen ciphertexts, for example over a network,
+/// recover plaintexts. Don't expose the timing of desktop decryptions to
+/// untrusted parties.
+pub async fn decrypt(key: &CryptoKey, data: &[u8]) -> Result<Vec<u8>> {
+    let algorithms = [Algorithm::RsaOaep(Hash::Sha256)];
+    key.check(KeyType::Private, &algorithms, "decrypt")?;
+    backend::decrypt(&key.inner, data).await
+}
+
+/// Derives up to 32 bytes shared by two parties with ECDH, from the private
+/// key of one party and the public key of the other.
+pub async fn derive_bits(
+    private_key: &CryptoKey,
+    public_key: &CryptoKey,
+    len: usize,
+) -> Result<Vec<u8>> {
+    let algorithms = [Algorithm::EcdhP256];
+    private_key.check(KeyType::Private, &algorithms, "derive bits")?;
+    public_key.check(KeyType::Public, &algorithms, "derive bits")?;
+    if len > 32 {
+        bail!("ECDH with P-256 can derive at most 32 bytes");
+    }
+    backend::derive_bits(&private_key.inner, &public_key.inner, len).await
+}
+
+/// Browsers reject empty HMAC keys.
+fn check_hmac_key(key: &[u8]) -> Result<()> {
+    if key.is_empty() {
+        bail!("HMAC keys can't be empty");
+    }
+    Ok(())
+}
+
+/// Only AES-128 and AES-256 are supported by all browsers.
+fn check_aes_gcm(key: &[u8], iv: &[u8]) -> Result<()> {
+    if key.len() != 16 && key.len() != 32 {
+        bail!("AES-GCM keys must be 16 or 32 bytes, not {}", key.len());
+    }
+    if iv.len() != AES_GCM_IV_LEN {
+        bail!("AES-GCM initialization vectors must be {} bytes", AES_GCM_IV_LEN);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    //! Test vectors from FIPS 180-2, RFC 4231, the GCM specification, RFC
+    //! 6070 (with SHA-256), RFC 5869 and RFC 8032, which run on both targets.
+    use super::*;
+    #[cfg(not(target_arch = "wasm32"))]
+    use futures_executor::block_on;
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    macro_rules! async_test {
+        ($name: ident, $body: block) => {
+            #[cfg(not(target_arch = "wasm32"))]
+            #[test]
+            fn $name() {
+                block_on(async $body)
+            }
+
+            #[cfg(target_arch = "wasm32")]
+            #[wasm_bindgen_test]
+            async fn $name() $body
+        };
+    }
+
+    fn hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    async_test!(test_digest, {
+        let vectors = [
+            (Hash::Sha1, "a9993e364706816aba3e25717850c26c9cd0d89d"),
+            (
+                Hash::Sha256,
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            (
+                Hash::Sha384,
+                "cb00753f45a35e8bb5a03d699ac65007272c32ab0eded1631a8b605a43ff5bed\
+                 8086072ba1e7cc2358baeca134c825a7",
+            ),
+            (
+                Hash::Sha512,
+                "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+                 2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f",
+            ),
+        ];
+        for (hash, expected) in &vectors {
+            let digest = digest(*hash, b"abc").await.unwrap();
+            assert_eq!(digest, hex(expected), "{}", hash);
+            assert_eq!(digest.len(), hash.output_len());
+        }
+    });
+
+    async_test!(test_hmac, {
+        let data = b"what do ya want for nothing?";
+        let signature = hex("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+        assert_eq!(hmac_sign(Hash::Sha256, b"Jefe", data).await.unwrap(), signature);
+        assert!(hmac_verify(Hash::Sha256, b"Jefe", &signature, data).await.unwrap());
+        assert!(!hmac_verify(Hash::Sha256, b"Jeff", &signature, data).await.unwrap());
+        assert!(!hmac_verify(Hash::Sha256, b"Jefe", &signature[1..], data).await.unwrap());
+        let signature = hex(
+            "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea250554\
+             9758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737",
+        );
+        assert_eq!(hmac_sign(Hash::Sha512, b"Jefe", data).await.unwrap(), signature);
+        assert!(hmac_sign(Hash::Sha256, b"", data).await.is_err());
+    });
+
+    async_test!(test_aes_gcm, {
+        let key = hex("feffe9928665731c6d6a8f9467308308");
+        let iv = hex("cafebabefacedbaddecaf888");
+        let aad = hex("feedfacedeadbeeffeedfacedeadbeefabaddad2");
+        let plaintext = hex(
+            "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a72\
+             1c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b39",
+        );
+        let ciphertext = hex(
+            "42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e\
+             21d514b25466931c7d8f6a5aac84aa051ba30b396a0aac973d58e091\
+             5bc94fbc3221a5db94fae95ae7121a47",
+        );
+        let encrypted = aes_gcm_encrypt(&key, &iv, &plaintext, &aad).await.unwrap();
+        assert_eq!(encrypted, ciphertext);
+        let decrypted = aes_gcm_decrypt(&key, &iv, &ciphertext, &aad).await.unwrap();
+        assert_eq!(decrypted, plaintext);
+        assert!(aes_gcm_decrypt(&key, &iv, &ciphertext, b"").await.is_err());
+        let mut tampered = ciphertext.clone();
+        tampered[0] ^= 1;
+        assert!(aes_gcm_decrypt(&key, &iv, &tampered, &aad).await.is_err());
+
+        let key = [0; 32];
+        let encrypted = aes_gcm_encrypt(&key, &iv, b"", b"").await.unwrap();
+        assert_eq!(encrypted.len(), AES_GCM_TAG_LEN);
+        assert_eq!(aes_gcm_decrypt(&key, &iv, &encrypted, b"").await.unwrap(), b"");
+        assert!(aes_gcm_encrypt(&key[..24], &iv, b"", b"").await.is_err());
+        assert!(aes_gcm_encrypt(&key, &iv[..8], b"", b"").await.is_err());
+    });
+
+    async_test!(test_pbkdf2, {
+        let vectors = [
+            (1, "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"),
+            (2, "ae4d0c95af6b46d32d0adff928f06dd02a303f8ef3c251dfd6e2d85a95474c43"),
+            (4096, "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"),
+        ];
+        for (iterations, expected) in &vectors {
+            let key = pbkdf2(Hash::Sha256, b"password", b"salt", *iterations, 32).await.unwrap();
+            assert_eq!(key, hex(expected));
+        }
+        assert!(pbkdf2(Hash::Sha256, b"password", b"salt", 0, 32).await.is_err());
+    });
+
+    async_test!(test_hkdf, {
+        let key = hex("0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b");
+        let salt = hex("000102030405060708090a0b0c");
+        let info = hex("f0f1f2f3f4f5f6f7f8f9");
+        let expected = hex(
+            "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf\
+             34007208d5b887185865",
+        );
+        assert_eq!(hkdf(Hash::Sha256, &key, &salt, &info, 42).await.unwrap(), expected);
+        assert!(hkdf(Hash::Sha1, &key, &salt, &info, 255 * 20 + 1).await.is_err());
+    });
+
+    /// A 1024 bit RSA key, which is faster than generating one.
+    const RSA_PKCS8: &str = "\
+        MIICdwIBADANBgkqhkiG9w0BAQEFAASCAmEwggJdAgEAAoGBAMPEpVCzagv6EpNa/Ik+UTht\
+        2wrGlcDDK4cYsnkERgEfN0RudTNc5bfY9vA5aUAv3F4hGnX4xvpG8ObmmhtPeQnEhrCiP0DO\
+        84H39PCBlph5wtR7wsvkpOTv3/siZisa/pVEzYhAxL1FGW1wx8l/izLKCdER6KkwUPjd33FG\
+        UOQhAgMBAAECgYBaaivfxb0oouqJW/BegoWIhlQv+d+YE4u67VEgSzT5t60S0eEXQ8Rk0LPb\
+        5ugRWQlx/9/0tF1RogoI2mA6VM4VQcYHMUCE7qEx5yjqqJu1sDv4fLoPK3Z35CykYbVZg+G0\
+        59bRF6C4CR6wIMw2AY3itp7cnWtBoxaEvuMdrS768QJBAOq1NiwKCUX+/JL9ner3vxqOk6mf\
+        5HOlmU8dTUGfGcJ5qerg+C8AxvDBzgPDnB87H4w72icngpywjkYz3ZhuJkcCQQDVhxwC6fVE\
+        XIwXB3p/BSs1tpCFYscv/mvYvxaiEux1ntxz108XzlFAfoSaJObsx80a8fegBc1PdKPsnw35\
+        5g5XAkAO4kb5K/nCkpT5zVOCXwujZ389toIKQ2ohdj479xbvGkAIQRP8BxSWl/O0kWcJI/qw\
+        Let7qCnEPf4HvapDzFhpAkEAkNze5iMvoLdg22bZdj9dd01qcvP2ytrC9AOAKWjJP2sNiRGz\
+        uSTh/u1WrPUILe/HeNZb9xa4mYU6iRfHdZjcdQJBAN2YvrKRMaHYk4gDo3aUQz9flMvbYcbl\
+        RSJzKu0pNzHClo0CkzFuPyPjGLwJKOm54f+lp/Wvhm8FKHLPpfEtIbM=";
+
+    async_test!(test_ed25519, {
+        let jwk = Jwk {
+            kty: "OKP".into(),
+            crv: Some("Ed25519".into()),
+            x: Some("11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo".into()),
+            d: Some("nWGxne_9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A".into()),
+            ..Jwk::default()
+        };
+        let signature = hex(
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e06522490155\
+             5fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+        );
+        let private_key = CryptoKey::import_jwk(Algorithm::Ed25519, &jwk, true).await.unwrap();
+        assert_eq!(private_key.key_type(), KeyType::Private);
+        assert_eq!(sign(&private_key, b"").await.unwrap(), signature);
+
+        let public = hex("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a");
+        let public_key = CryptoKey::import_raw(Algorithm::Ed25519, &public).await.unwrap();
+        assert!(verify(&public_key, &signature, b"").await.unwrap());
+        assert!(!verify(&public_key, &signature, b"x").await.unwrap());
+        assert_eq!(public_key.export_raw().await.unwrap(), public);
+        assert!(sign(&public_key, b"").await.is_err());
+
+        let exported = private_key.export_jwk().await.unwrap();
+        assert_eq!((&exported.x, &exported.d), (&jwk.x, &jwk.d));
+        assert_eq!(exported.key_ops, vec!["sign"]);
+        let pkcs8 = private_key.export_pkcs8().await.unwrap();
+        let private_key = CryptoKey::import_pkcs8(Algorithm::Ed25519, &pkcs8, false).await.unwrap();
+        assert_eq!(sign(&private_key, b"").await.unwrap(), signature);
+        assert!(private_key.export_pkcs8().await.is_err());
+    });
+
+    async_test!(test_ecdsa, {
+        let pair = generate_key_pair(Algorithm::EcdsaP256, false).await.unwrap();
+        assert!(pair.public_key.extractable());
+        assert!(!pair.private_key.extractable());
+        assert!(pair.private_key.export_jwk().await.is_err());
+
+        let signature = sign(&pair.private_key, b"frame").await.unwrap();
+        assert_eq!(signature.len(), 64);
+        assert!(verify(&pair.public_key, &signature, b"frame").await.unwrap());
+        assert!(!verify(&pair.public_key, &signature, b"other").await.unwrap());
+        assert!(!verify(&pair.public_key, &signature[1..], b"frame").await.unwrap());
+
+        let raw = pair.public_key.export_raw().await.unwrap();
+        assert_eq!((raw.len(), raw[0]), (65, 4));
+        let spki = pair.public_key.export_spki().await.unwrap();
+        let public_key = CryptoKey::import_spki(Algorithm::EcdsaP256, &spki).await.unwrap();
+        assert!(verify(&public_key, &signature, b"frame").await.unwrap());
+        let jwk = public_key.export_jwk().await.unwrap();
+        assert_eq!((jwk.kty.as_str(), jwk.crv.as_deref()), ("EC", Some("P-256")));
+        let public_key = CryptoKey::import_jwk(Algorithm::EcdsaP256, &jwk, true).await.unwrap();
+        assert!(verify(&public_key, &signature, b"frame").await.unwrap());
+        assert!(pair.public_key.export_pkcs8().await.is_err());
+    });
+
+    async_test!(test_ecdh, {
+        let alice = generate_key_pair(Algorithm::EcdhP256, true).await.unwrap();
+        let bob = generate_key_pair(Algorithm::EcdhP256, true).await.unwrap();
+        let secret = derive_bits(&alice.private_key, &bob.public_key, 32).await.unwrap();
+        assert_eq!(secret.len(), 32);
+        assert_eq!(derive_bits(&bob.private_key, &alice.public_key, 32).await.unwrap(), secret);
+        assert_eq!(
+            derive_bits(&bob.private_key, &alice.public_key, 16).await.unwrap(),
+            &secret[..16]
+        );
+        assert!(derive_bits(&alice.private_key, &bob.public_key, 33).await.is_err());
+
+        let jwk = alice.private_key.export_jwk().await.unwrap();
+        let private_key = CryptoKey::import_jwk(Algorithm::EcdhP256, &jwk, false).await.unwrap();
+        assert_eq!(derive_bits(&private_key, &bob.public_key, 32).await.unwrap(), secret);
+        let ecdsa = generate_key_pair(Algorithm::EcdsaP256, false).await.unwrap();
+        assert!(derive_bits(&alice.private_key, &ecdsa.public_key, 32).await.is_err());
+        assert!(sign(&alice.private_key, b"").await.is_err());
+    });
+
+    async_test!(test_rsa_oaep, {
+        let algorithm = Algorithm::RsaOaep(Hash::Sha256);
+        let pkcs8 = base64::decode(RSA_PKCS8).unwrap();
+        let private_key = CryptoKey::import_pkcs8(algorithm, &pkcs8, true).await.unwrap();
+        let jwk = private_key.export_jwk().await.unwrap();
+        assert_eq!(jwk.alg.as_deref(), Some("RSA-OAEP-256"));
+        assert!(jwk.qi.is_some());
+        let public_jwk = Jwk {
+            d: None,
+            p: None,
+            q: None,
+            dp: None,
+            dq: None,
+            qi: None,
+            key_ops: Vec::new(),
+            ..jwk.clone()
+        };
+        let public_key = CryptoKey::import_jwk(algorithm, &public_jwk, true).await.unwrap();
+        assert_eq!(public_key.key_type(), KeyType::Public);
+
+        let encrypted = encrypt(&public_key, b"payload").await.unwrap();
+        assert_eq!(encrypted.len(), 128);
+        assert_eq!(decrypt(&private_key, &encrypted).await.unwrap(), b"payload");
+        assert!(decrypt(&private_key, &encrypted[1..]).await.is_err());
+
+        let private_key = CryptoKey::import_jwk(algorithm, &jwk, false).await.unwrap();
+        assert_eq!(decrypt(&private_key, &encrypted).await.unwrap(), b"payload");
+        let other = Algorithm::RsaOaep(Hash::Sha1);
+        let private_key = CryptoKey::import_pkcs8(other, &pkcs8, true).await.unwrap();
+        assert!(decrypt(&private_key, &encrypted).await.is_err());
+        assert!(CryptoKey::import_jwk(other, &jwk, true).await.is_err());
+        assert!(CryptoKey::import_raw(algorithm, b"").await.is_err());
+    });
+
+    async_test!(test_jwk_ext, {
+        let pair = generate_key_pair(Algorithm::Ed25519, true).await.unwrap();
+        let mut jwk = pair.private_key.export_jwk().await.unwrap();
+        jwk.ext = Some(false);
+        assert!(CryptoKey::import_jwk(Algorithm::Ed25519, &jwk, true).await.is_err());
+        let key = CryptoKey::import_jwk(Algorithm::Ed25519, &jwk, false).await.unwrap();
+        assert!(!key.extractable());
+        assert!(CryptoKey::import_jwk(Algorithm::EcdsaP256, &jwk, false).await.is_err());
+    });
+}
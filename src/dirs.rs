@@ -0,0 +1,32 @@
+//! Directories for application data on desktop.
+use crate::Result;
+use failure::format_err;
+use std::env;
+use std::path::PathBuf;
+
+/// Returns the directory for data of this application, which is named after
+/// the executable and located in the data directory of the user.
+pub(crate) fn app_dir() -> Result<PathBuf> {
+    let app = env::current_exe()?
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .map(str::to_string)
+        .ok_or_else(|| format_err!("failed to determine the application name"))?;
+    Ok(data_dir()?.join(app))
+}
+
+/// Returns the directory for application data of the user.
+fn data_dir() -> Result<PathBuf> {
+    let home = || env::var_os("HOME").map(PathBuf::from);
+    let dir = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library").join("Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| home().map(|home| home.join(".local").join("share")))
+    };
+    dir.ok_or_else(|| format_err!("failed to determine the data directory"))
+}
@@ -0,0 +1,122 @@
+use crate::event_source::{
+    EventSource as EventSourceHandle, Handler, MessageEvent, ReadyState,
+};
+use crate::{js_value_to_error, Result};
+use failure::format_err;
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+pub(crate) fn connect<T: Handler + 'static>(url: &str) -> Result<()> {
+    let source = web_sys::EventSource::new(url).map_err(js_value_to_error)?;
+    let handler = T::new(EventSourceHandle {
+        inner: EventSource(source.clone()),
+    });
+    let event_types = handler
+        .event_types()
+        .iter()
+        .map(|event_type| event_type.to_string())
+        .collect::<Vec<_>>();
+    let handler = Rc::new(RefCell::new(handler));
+
+    let handler2 = Rc::clone(&handler);
+    let onopen: Closure<dyn FnMut(JsValue)> = Closure::new(move |_event: JsValue| {
+        let result = handler2.borrow_mut().on_open();
+        if let Err(err) = result {
+            handler2.borrow_mut().on_error(err);
+        }
+    });
+    source.set_onopen(Some(onopen.as_ref().unchecked_ref()));
+    onopen.forget();
+
+    let handler2 = Rc::clone(&handler);
+    let onmessage: Closure<dyn FnMut(web_sys::MessageEvent)> =
+        Closure::new(move |event: web_sys::MessageEvent| {
+            let event = MessageEvent {
+                event_type: event.type_(),
+                data: event.data().as_string().unwrap_or_default(),
+                last_event_id: event.last_event_id(),
+            };
+            let result = handler2.borrow_mut().on_message(event);
+            if let Err(err) = result {
+                handler2.borrow_mut().on_error(err);
+            }
+        });
+    source.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+    // Events with an `event` field are only dispatched to their listeners.
+    for event_type in event_types.iter().filter(|event_type| *event_type != "message") {
+        source
+            .add_event_listener_with_callback(event_type, onmessage.as_ref().unchecked_ref())
+            .map_err(js_value_to_error)?;
+    }
+    onmessage.forget();
+
+    let source2 = source.clone();
+    let url = url.to_string();
+    let onerror: Closure<dyn FnMut(JsValue)> = Closure::new(move |_event: JsValue| {
+        // The browser reconnects unless the connection failed permanently.
+        let err = if source2.ready_state() == web_sys::EventSource::CLOSED {
+            format_err!("Connecting to the event stream {} failed", url)
+        } else {
+            format_err!("The connection to the event stream {} was lost", url)
+        };
+        handler.borrow_mut().on_error(err);
+    });
+    source.set_onerror(Some(onerror.as_ref().unchecked_ref()));
+    onerror.forget();
+    Ok(())
+}
+
+#[derive(Clone)]
+pub(crate) struct EventSource(web_sys::EventSource);
+
+impl EventSource {
+    pub(crate) fn url(&self) -> String {
+        self.0.url()
+    }
+
+    pub(crate) fn ready_state(&self) -> ReadyState {
+        match self.0.ready_state() {
+            web_sys::EventSource::CONNECTING => ReadyState::Connecting,
+            web_sys::EventSource::OPEN => ReadyState::Open,
+            _ => ReadyState::Closed,
+        }
+    }
+
+    pub(crate) fn close(&self) {
+        self.0.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::event_source::{self, EventSource, Handler, ReadyState};
+    use std::cell::RefCell;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    thread_local! {
+        static SOURCE: RefCell<Option<EventSource>> = RefCell::new(None);
+    }
+
+    struct Closing;
+
+    impl Handler for Closing {
+        fn new(source: EventSource) -> Self {
+            source.close();
+            SOURCE.with(|cell| *cell.borrow_mut() = Some(source));
+            Closing
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_close() {
+        event_source::connect::<Closing>("http://127.0.0.1:1/events").unwrap();
+        let source = SOURCE.with(|cell| cell.borrow_mut().take()).unwrap();
+        assert_eq!(source.ready_state(), ReadyState::Closed);
+        assert_eq!(source.url(), "http://127.0.0.1:1/events");
+        assert!(event_source::connect::<Closing>("ws://127.0.0.1:1/").is_err());
+    }
+}
@@ -0,0 +1,646 @@
+use crate::event_source::{
+    EventSource as EventSourceHandle, Handler, MessageEvent, ReadyState, DEFAULT_RETRY,
+};
+use crate::{Error, Result};
+use failure::format_err;
+use std::io::{self, BufRead, BufReader, Read};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+pub(crate) fn connect<T: Handler + 'static>(url: &str) -> Result<()> {
+    let source = EventSource {
+        shared: Arc::new(Shared {
+            url: url.to_string(),
+            state: Mutex::new(ReadyState::Connecting),
+            changed: Condvar::new(),
+            wake: Mutex::new(None),
+        }),
+    };
+    let mut handler = T::new(EventSourceHandle {
+        inner: source.clone(),
+    });
+    let event_types = handler
+        .event_types()
+        .iter()
+        .map(|event_type| event_type.to_string())
+        .collect::<Vec<_>>();
+    let agent = ureq::AgentBuilder::new().build();
+    let mut parser = Parser::default();
+
+    loop {
+        let reader = match open(&agent, url, &parser.last_event_id) {
+            Ok(reader) => reader,
+            Err(Failure::Retry(err)) => {
+                handler.on_error(err);
+                if !source.reconnect(parser.retry) {
+                    break;
+                }
+                continue;
+            }
+            Err(Failure::Fatal(err)) => {
+                source.close();
+                handler.on_error(err);
+                break;
+            }
+        };
+        let lines = source.read_lines(reader)?;
+        if !source.set_state(ReadyState::Open) {
+            break;
+        }
+        if let Err(err) = handler.on_open() {
+            handler.on_error(err);
+        }
+
+        let result = loop {
+            let line = match lines.recv() {
+                Ok(Ok(Some(line))) => line,
+                Ok(Ok(None)) | Err(_) => break Ok(()),
+                Ok(Err(err)) => break Err(err),
+            };
+            let event = match parser.line(&line) {
+                Some(event) => event,
+                None => continue,
+            };
+            if source.ready_state() == ReadyState::Closed {
+                break Ok(());
+            }
+            if event.event_type != "message" && !event_types.contains(&event.event_type) {
+                continue;
+            }
+            if let Err(err) = handler.on_message(event) {
+                handler.on_error(err);
+            }
+        };
+        parser.reset();
+        if source.ready_state() == ReadyState::Closed {
+            break;
+        }
+        let err = match result {
+            Ok(()) => format_err!("The event stream {} ended", url),
+            Err(err) => format_err!("Reading the event stream {} failed: {}", url, err),
+        };
+        source.set_state(ReadyState::Connecting);
+        handler.on_error(err);
+        if !source.reconnect(parser.retry) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+enum Failure {
+    /// The connection can be retried.
+    Retry(Error),
+    /// The server rejected the connection.
+    Fatal(Error),
+}
+
+/// Sends the request, returning the body of a valid event stream.
+fn open(
+    agent: &ureq::Agent,
+    url: &str,
+    last_event_id: &str,
+) -> std::result::Result<Box<dyn Read + Send>, Failure> {
+    let mut request = agent
+        .get(url)
+        .set("Accept", "text/event-stream")
+        .set("Cache-Control", "no-cache");
+    if !last_event_id.is_empty() {
+        request = request.set("Last-Event-ID", last_event_id);
+    }
+    let response = match request.call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(status, _)) => {
+            return Err(Failure::Fatal(format_err!(
+                "Connecting to the event stream {} failed with status {}",
+                url,
+                status
+            )));
+        }
+        Err(ureq::Error::Transport(err)) if is_fatal(&err) => {
+            return Err(Failure::Fatal(format_err!(
+                "Connecting to the event stream {} failed: {}",
+                url,
+                err
+            )));
+        }
+        Err(ureq::Error::Transport(err)) => {
+            return Err(Failure::Retry(format_err!(
+                "Connecting to the event stream {} failed: {}",
+                url,
+                err
+            )));
+        }
+    };
+    if response.status() != 200 {
+        return Err(Failure::Fatal(format_err!(
+            "Connecting to the event stream {} failed with status {}",
+            url,
+            response.status()
+        )));
+    }
+    if response.content_type() != "text/event-stream" {
+        return Err(Failure::Fatal(format_err!(
+            "The event stream {} has the content type '{}'",
+            url,
+            response.content_type()
+        )));
+    }
+    Ok(response.into_reader())
+}
+
+/// Returns whether retrying can't fix a transport error, like an invalid url
+/// or an invalid certificate.
+fn is_fatal(err: &ureq::Transport) -> bool {
+    match err.kind() {
+        ureq::ErrorKind::InvalidUrl
+        | ureq::ErrorKind::UnknownScheme
+        | ureq::ErrorKind::InsecureRequestHttpsOnly
+        | ureq::ErrorKind::TooManyRedirects
+        | ureq::ErrorKind::InvalidProxyUrl
+        | ureq::ErrorKind::ProxyUnauthorized => true,
+        // TLS errors, including certificate errors, are reported as invalid
+        // data.
+        ureq::ErrorKind::ConnectionFailed | ureq::ErrorKind::Io => {
+            std::error::Error::source(err)
+                .and_then(|source| source.downcast_ref::<io::Error>())
+                .is_some_and(|err| err.kind() == io::ErrorKind::InvalidData)
+        }
+        _ => false,
+    }
+}
+
+/// A line read from the event stream, `None` at its end.
+type Line = io::Result<Option<String>>;
+
+#[derive(Clone)]
+pub(crate) struct EventSource {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    url: String,
+    state: Mutex<ReadyState>,
+    /// Notified when the state changes, to interrupt reconnection delays.
+    changed: Condvar,
+    /// Sends `None` to the lines of the open stream when it's closed.
+    wake: Mutex<Option<Sender<Line>>>,
+}
+
+impl EventSource {
+    pub(crate) fn url(&self) -> String {
+        self.shared.url.clone()
+    }
+
+    pub(crate) fn ready_state(&self) -> ReadyState {
+        *self.state()
+    }
+
+    pub(crate) fn close(&self) {
+        self.set_state(ReadyState::Closed);
+        let wake = self.shared.wake.lock().unwrap_or_else(|err| err.into_inner()).take();
+        if let Some(wake) = wake {
+            let _ = wake.send(Ok(None));
+        }
+    }
+
+    /// Reads the lines of the stream on a separate thread, so `close` doesn't
+    /// have to wait for the server to send something.
+    ///
+    /// After closing the thread exits and drops the connection once the
+    /// blocked read returns.
+    fn read_lines(&self, reader: Box<dyn Read + Send>) -> Result<Receiver<Line>> {
+        let (sender, receiver) = mpsc::channel();
+        *self.shared.wake.lock().unwrap_or_else(|err| err.into_inner()) = Some(sender.clone());
+        thread::Builder::new()
+            .name("libweb-event-source".to_string())
+            .spawn(move || {
+                let mut lines = Lines::new(reader);
+                loop {
+                    let line = lines.next();
+                    let end = !matches!(line, Ok(Some(_)));
+                    if sender.send(line).is_err() || end {
+                        break;
+                    }
+                }
+            })?;
+        Ok(receiver)
+    }
+
+    fn state(&self) -> MutexGuard<'_, ReadyState> {
+        self.shared.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Changes the state unless the event source is closed, returning
+    /// whether it was changed.
+    fn set_state(&self, state: ReadyState) -> bool {
+        let mut current = self.state();
+        if *current == ReadyState::Closed {
+            return false;
+        }
+        *current = state;
+        self.shared.changed.notify_all();
+        true
+    }
+
+    /// Waits for the reconnection time, returning `false` if the event
+    /// source was closed in the meantime.
+    fn reconnect(&self, delay: Duration) -> bool {
+        let state = self.state();
+        let (state, _) = self
+            .shared
+            .changed
+            .wait_timeout_while(state, delay, |state| *state != ReadyState::Closed)
+            .unwrap_or_else(|err| err.into_inner());
+        *state != ReadyState::Closed
+    }
+}
+
+/// Splits a stream into lines ending with `\r\n`, `\n` or `\r`.
+struct Lines<R> {
+    reader: BufReader<R>,
+    /// Whether the last line ended with `\r`, so a leading `\n` is skipped.
+    after_cr: bool,
+    bom_checked: bool,
+}
+
+impl<R: Read> Lines<R> {
+    fn new(reader: R) -> Self {
+        Lines {
+            reader: BufReader::new(reader),
+            after_cr: false,
+            bom_checked: false,
+        }
+    }
+
+    /// Returns the next line, or `None` at the end of the stream. An
+    /// unterminated last line is dropped.
+    fn next(&mut self) -> io::Result<Option<String>> {
+        let mut line = Vec::new();
+        loop {
+            let buf = self.reader.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(None);
+            }
+            let mut start = 0;
+            if self.after_cr && buf[0] == b'\n' {
+                start = 1;
+            }
+            self.after_cr = false;
+            match buf[start..].iter().position(|&b| b == b'\n' || b == b'\r') {
+                Some(end) => {
+                    line.extend_from_slice(&buf[start..start + end]);
+                    self.after_cr = buf[start + end] == b'\r';
+                    self.reader.consume(start + end + 1);
+                    break;
+                }
+                None => {
+                    line.extend_from_slice(&buf[start..]);
+                    let len = buf.len();
+                    self.reader.consume(len);
+                }
+            }
+        }
+        if !self.bom_checked {
+            self.bom_checked = true;
+            if line.starts_with("\u{feff}".as_bytes()) {
+                line.drain(..3);
+            }
+        }
+        Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+    }
+}
+
+/// Interprets the lines of an event stream.
+struct Parser {
+    event_type: String,
+    data: String,
+    last_event_id: String,
+    retry: Duration,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Parser {
+            event_type: String::new(),
+            data: String::new(),
+            last_event_id: String::new(),
+            retry: DEFAULT_RETRY,
+        }
+    }
+}
+
+impl Parser {
+    /// Processes a line, returning an event when a blank line ends one.
+    fn line(&mut self, line: &str) -> Option<MessageEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        let (field, value) = match line.find(':') {
+            Some(0) => return None,
+            Some(index) => {
+                let value = &line[index + 1..];
+                (&line[..index], value.strip_prefix(' ').unwrap_or(value))
+            }
+            None => (line, ""),
+        };
+        match field {
+            "event" => self.event_type = value.to_string(),
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            "id" if !value.contains('\0') => self.last_event_id = value.to_string(),
+            "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                if let Ok(millis) = value.parse() {
+                    self.retry = Duration::from_millis(millis);
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<MessageEvent> {
+        let event_type = std::mem::take(&mut self.event_type);
+        let mut data = std::mem::take(&mut self.data);
+        if data.is_empty() {
+            return None;
+        }
+        data.pop();
+        Some(MessageEvent {
+            event_type: if event_type.is_empty() {
+                "message".to_string()
+            } else {
+                event_type
+            },
+            data,
+            last_event_id: self.last_event_id.clone(),
+        })
+    }
+
+    /// Discards an incomplete event when the connection is lost.
+    fn reset(&mut self) {
+        self.event_type.clear();
+        self.data.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_source::{self, EventSource};
+    use crate::Error;
+    use std::io::Write;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    fn parse(input: &str) -> (Vec<MessageEvent>, Parser) {
+        let mut lines = Lines::new(input.as_bytes());
+        let mut parser = Parser::default();
+        let mut events = Vec::new();
+        while let Some(line) = lines.next().unwrap() {
+            events.extend(parser.line(&line));
+        }
+        (events, parser)
+    }
+
+    fn event(event_type: &str, data: &str, last_event_id: &str) -> MessageEvent {
+        MessageEvent {
+            event_type: event_type.into(),
+            data: data.into(),
+            last_event_id: last_event_id.into(),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let (events, parser) = parse(
+            "\u{feff}: comment\ndata: a\ndata:b\r\n\r\nevent: update\rid: 1\rdata\r\r\
+             data: c\nid\nretry: 50\nretry: x\nunknown: d\n\ndata: incomplete",
+        );
+        assert_eq!(
+            events,
+            vec![
+                event("message", "a\nb", ""),
+                event("update", "", "1"),
+                event("message", "c", ""),
+            ]
+        );
+        assert_eq!(parser.retry, Duration::from_millis(50));
+
+        let (events, _) = parse("event: empty\n\nid: 2\ndata: x\n\ndata: y\n\n");
+        assert_eq!(
+            events,
+            vec![event("message", "x", "2"), event("message", "y", "2")]
+        );
+    }
+
+    /// Reads a request, returning its path and `Last-Event-ID` header.
+    fn read_request(stream: &TcpStream) -> (String, Option<String>) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let path = line.split_whitespace().nth(1).unwrap().to_string();
+        let mut last_event_id = None;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line.split_at(line.find(':').unwrap());
+            if name.eq_ignore_ascii_case("last-event-id") {
+                last_event_id = Some(value[1..].trim().to_string());
+            }
+        }
+        (path, last_event_id)
+    }
+
+    /// Starts a server that sends `responses` to consecutive connections
+    /// and returns the `Last-Event-ID` headers it received.
+    fn server(responses: Vec<&'static str>) -> (String, thread::JoinHandle<Vec<Option<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut last_event_ids = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let (_, last_event_id) = read_request(&stream);
+                last_event_ids.push(last_event_id);
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            last_event_ids
+        });
+        (format!("http://{}/events", addr), server)
+    }
+
+    static EVENTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    struct Recorder(EventSource);
+
+    impl Handler for Recorder {
+        fn new(source: EventSource) -> Self {
+            Recorder(source)
+        }
+
+        fn event_types(&self) -> &[&str] {
+            &["update"]
+        }
+
+        fn on_open(&mut self) -> Result<()> {
+            EVENTS.lock().unwrap().push("open".into());
+            Ok(())
+        }
+
+        fn on_message(&mut self, event: MessageEvent) -> Result<()> {
+            let done = event.data == "done";
+            EVENTS.lock().unwrap().push(format!(
+                "{} {} {}",
+                event.event_type, event.last_event_id, event.data
+            ));
+            if done {
+                self.0.close();
+            }
+            Ok(())
+        }
+
+        fn on_error(&mut self, _: Error) {
+            let state = self.0.ready_state();
+            EVENTS.lock().unwrap().push(format!("error {:?}", state));
+        }
+    }
+
+    #[test]
+    fn test_connect() {
+        let (url, server) = server(vec![
+            "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\r\n\
+             retry: 10\nid: 1\ndata: a\n\nevent: other\ndata: ignored\n\ndata: lost",
+            "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream; charset=utf-8\r\n\r\n\
+             event: update\ndata: b\n\ndata: done\n\ndata: after close\n\n",
+        ]);
+        event_source::connect::<Recorder>(url.as_str()).unwrap();
+        assert_eq!(
+            *EVENTS.lock().unwrap(),
+            vec![
+                "open",
+                "message 1 a",
+                "error Connecting",
+                "open",
+                "update 1 b",
+                "message 1 done",
+            ]
+        );
+        assert_eq!(server.join().unwrap(), vec![None, Some("1".into())]);
+    }
+
+    static FAILURES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    struct Failing(EventSource);
+
+    impl Handler for Failing {
+        fn new(source: EventSource) -> Self {
+            Failing(source)
+        }
+
+        fn on_error(&mut self, err: Error) {
+            let state = self.0.ready_state();
+            FAILURES.lock().unwrap().push(format!("{:?} {}", state, err));
+        }
+    }
+
+    #[test]
+    fn test_fatal() {
+        let (url, server) = server(vec![
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 0\r\n\r\n",
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n",
+        ]);
+        event_source::connect::<Failing>(url.as_str()).unwrap();
+        event_source::connect::<Failing>(url.as_str()).unwrap();
+        server.join().unwrap();
+        let failures = FAILURES.lock().unwrap();
+        assert_eq!(failures.len(), 2);
+        assert!(failures[0].starts_with("Closed"), "{}", failures[0]);
+        assert!(failures[0].contains("text/plain"));
+        assert!(failures[1].contains("404"));
+        assert!(event_source::connect::<Failing>("ws://localhost/").is_err());
+    }
+
+    struct Closing(EventSource);
+
+    impl Handler for Closing {
+        fn new(source: EventSource) -> Self {
+            Closing(source)
+        }
+
+        fn on_message(&mut self, _: MessageEvent) -> Result<()> {
+            let source = self.0.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                source.close();
+            });
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_close_from_other_thread() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/events", listener.local_addr().unwrap());
+        let (done, finished) = mpsc::channel();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_request(&stream);
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\r\ndata: a\n\n")
+                .unwrap();
+            // The stream stays open without sending anything until the test
+            // finished.
+            let _ = finished.recv();
+        });
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(event_source::connect::<Closing>(url.as_str()));
+        });
+        let result = receiver.recv_timeout(Duration::from_secs(5));
+        let _ = done.send(());
+        assert!(result.expect("close didn't interrupt the stream").is_ok());
+    }
+
+    static TLS_ERRORS: Mutex<Vec<ReadyState>> = Mutex::new(Vec::new());
+
+    struct TlsFailing(EventSource);
+
+    impl Handler for TlsFailing {
+        fn new(source: EventSource) -> Self {
+            TlsFailing(source)
+        }
+
+        fn on_error(&mut self, _: Error) {
+            TLS_ERRORS.lock().unwrap().push(self.0.ready_state());
+        }
+    }
+
+    #[test]
+    fn test_tls_error_is_fatal() {
+        // A server that doesn't speak TLS.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("https://{}/events", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read(&mut [0; 1024]).unwrap();
+            stream.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n").unwrap();
+        });
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(event_source::connect::<TlsFailing>(url.as_str()));
+        });
+        let result = receiver.recv_timeout(Duration::from_secs(5));
+        assert!(result.expect("TLS errors are retried").is_ok());
+        server.join().unwrap();
+        assert_eq!(*TLS_ERRORS.lock().unwrap(), vec![ReadyState::Closed]);
+    }
+}
@@ -0,0 +1,120 @@
+//! The EventSource API for Server-Sent Events
+//!
+//! Lost connections are reestablished after the reconnection time, which
+//! the server can change with the `retry` field. The id of the last event is
+//! sent in the `Last-Event-ID` header so the server can resume the stream.
+use crate::url::IntoUrl;
+use crate::{Error, Result};
+use failure::bail;
+use log::*;
+use std::time::Duration;
+
+#[cfg(target_arch = "wasm32")]
+mod browser;
+#[cfg(not(target_arch = "wasm32"))]
+mod desktop;
+
+#[cfg(target_arch = "wasm32")]
+use self::browser as backend;
+#[cfg(not(target_arch = "wasm32"))]
+use self::desktop as backend;
+
+/// The reconnection time used until the server sends a `retry` field.
+pub const DEFAULT_RETRY: Duration = Duration::from_secs(3);
+
+/// Connects to an event stream at url.
+///
+/// Fails without connecting if url is invalid or its scheme is not `http`
+/// or `https`. In the browser this returns immediately, on desktop it
+/// blocks until the event source is closed with `EventSource::close` or the
+/// connection fails permanently.
+pub fn connect<T: Handler + 'static>(url: impl IntoUrl) -> Result<()> {
+    let url = url.into_url()?;
+    match url.protocol().as_str() {
+        "http:" | "https:" => {}
+        _ => bail!("Invalid EventSource URL '{}': the scheme must be http or https", url),
+    }
+    backend::connect::<T>(&url.href())
+}
+
+/// The state of the connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadyState {
+    /// The connection is being established or reestablished.
+    Connecting,
+    /// The connection is open and events are dispatched.
+    Open,
+    /// The connection was closed and won't be reestablished.
+    Closed,
+}
+
+/// A handle to the connection, used to close it.
+#[derive(Clone)]
+pub struct EventSource {
+    inner: backend::EventSource,
+}
+
+impl EventSource {
+    /// Returns the url of the event stream.
+    pub fn url(&self) -> String {
+        self.inner.url()
+    }
+
+    /// Returns the state of the connection.
+    pub fn ready_state(&self) -> ReadyState {
+        self.inner.ready_state()
+    }
+
+    /// Closes the connection. No more events are dispatched afterwards.
+    ///
+    /// On desktop `connect` returns immediately, while the socket is closed
+    /// in the background once the server sends data or ends the stream.
+    pub fn close(&self) {
+        self.inner.close()
+    }
+}
+
+/// An event received from the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageEvent {
+    /// The type set by the `event` field, `message` by default.
+    pub event_type: String,
+    /// The `data` fields joined by newlines.
+    pub data: String,
+    /// The last `id` field received on the stream, which isn't reset
+    /// between events.
+    pub last_event_id: String,
+}
+
+/// The handler of an event stream, created when connecting.
+pub trait Handler {
+    /// Creates a new handler.
+    fn new(source: EventSource) -> Self;
+
+    /// Returns the event types besides `message` that are dispatched to
+    /// `on_message`. Events of other types are ignored, like in the browser.
+    fn event_types(&self) -> &[&str] {
+        &[]
+    }
+
+    /// Called every time the connection is established.
+    fn on_open(&mut self) -> Result<()> {
+        debug!("EventSource open");
+        Ok(())
+    }
+
+    /// Called on incoming events.
+    fn on_message(&mut self, event: MessageEvent) -> Result<()> {
+        debug!("Received event {:?}", event);
+        Ok(())
+    }
+
+    /// Called when the connection is lost and will be reestablished, when
+    /// it fails permanently, and when the other methods return an error.
+    ///
+    /// Check `EventSource::ready_state` to see whether the connection was
+    /// closed.
+    fn on_error(&mut self, err: Error) {
+        error!("{:?}", err);
+    }
+}
@@ -0,0 +1,85 @@
+use crate::fetch::{Headers, Redirect, Request, Response};
+use crate::{js_value_to_error, Result};
+use failure::{bail, format_err};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+pub(crate) async fn fetch(request: Request) -> Result<Response> {
+    let headers = web_sys::Headers::new().map_err(js_value_to_error)?;
+    for (name, value) in request.headers.iter() {
+        headers.append(name, value).map_err(js_value_to_error)?;
+    }
+    let mut init = web_sys::RequestInit::new();
+    init.method(request.method.as_str());
+    init.headers(&headers);
+    init.redirect(match request.redirect {
+        Redirect::Follow => web_sys::RequestRedirect::Follow,
+        Redirect::Error => web_sys::RequestRedirect::Error,
+        Redirect::Manual => web_sys::RequestRedirect::Manual,
+    });
+    let body = request
+        .body
+        .as_ref()
+        .map(|body| JsValue::from(js_sys::Uint8Array::from(&body[..])));
+    init.body(body.as_ref());
+
+    let window = match web_sys::window() {
+        Some(window) => window,
+        None => bail!("fetch is only supported in a window"),
+    };
+    let promise = window.fetch_with_str_and_init(&request.url, &init);
+    let response: web_sys::Response = JsFuture::from(promise)
+        .await
+        .map_err(js_value_to_error)?
+        .dyn_into()
+        .map_err(js_value_to_error)?;
+
+    let mut headers = Headers::new();
+    let entries = js_sys::try_iter(&response.headers())
+        .map_err(js_value_to_error)?
+        .ok_or_else(|| format_err!("headers are not iterable"))?;
+    for entry in entries {
+        let entry: js_sys::Array = entry
+            .map_err(js_value_to_error)?
+            .dyn_into()
+            .map_err(js_value_to_error)?;
+        if let (Some(name), Some(value)) = (entry.get(0).as_string(), entry.get(1).as_string()) {
+            headers.append(&name, &value);
+        }
+    }
+    let buffer = JsFuture::from(response.array_buffer().map_err(js_value_to_error)?)
+        .await
+        .map_err(js_value_to_error)?;
+    Ok(Response {
+        url: response.url(),
+        status: response.status(),
+        status_text: response.status_text(),
+        headers,
+        redirected: response.redirected(),
+        body: js_sys::Uint8Array::new(&buffer).to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fetch::{fetch, Method, Request};
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    async fn test_fetch() {
+        let response = fetch("/").await.unwrap();
+        assert!(response.ok());
+        assert!(!response.text().unwrap().is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_not_found() {
+        let request = Request::new("/libweb-missing").method(Method::Head);
+        let response = fetch(request).await.unwrap();
+        assert_eq!(response.status(), 404);
+        assert!(!response.ok());
+    }
+}
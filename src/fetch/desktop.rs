@@ -0,0 +1,227 @@
+use crate::fetch::{Headers, Redirect, Request, Response, MAX_REDIRECTS};
+use crate::Result;
+use failure::{bail, format_err};
+use futures_channel::oneshot;
+use std::io::Read;
+use std::thread;
+use url::Url;
+
+pub(crate) async fn fetch(request: Request) -> Result<Response> {
+    let (sender, receiver) = oneshot::channel();
+    thread::Builder::new()
+        .name("libweb-fetch".to_string())
+        .spawn(move || {
+            let _ = sender.send(send(request));
+        })?;
+    receiver
+        .await
+        .map_err(|_| format_err!("fetch thread panicked"))?
+}
+
+/// Sends the request, blocking until the whole body was received.
+fn send(request: Request) -> Result<Response> {
+    let url = Url::parse(&request.url)?;
+    let redirects = match request.redirect {
+        Redirect::Follow => MAX_REDIRECTS,
+        Redirect::Error | Redirect::Manual => 0,
+    };
+    let agent = ureq::AgentBuilder::new().redirects(redirects).build();
+    let mut builder = agent.request(request.method.as_str(), url.as_str());
+    // `ureq` replaces headers with the same name, so multiple values are
+    // joined like in `Headers::get`.
+    for (name, _) in request.headers.iter() {
+        if builder.header(name).is_none() {
+            let value = request.headers.get(name).unwrap_or_default();
+            builder = builder.set(name, &value);
+        }
+    }
+    let result = match &request.body {
+        Some(body) => builder.send_bytes(body),
+        None => builder.call(),
+    };
+    let response = match result {
+        Ok(response) => response,
+        // Error statuses are returned as responses like in the browser.
+        Err(ureq::Error::Status(_, response)) => response,
+        Err(ureq::Error::Transport(err)) => bail!("fetch {} failed: {}", url, err),
+    };
+    if request.redirect == Redirect::Error && (300..400).contains(&response.status()) {
+        bail!("fetch {} failed: redirects are not allowed", url);
+    }
+
+    let mut headers = Headers::new();
+    for name in response.headers_names() {
+        // Names are repeated for every value.
+        if headers.has(&name) {
+            continue;
+        }
+        for value in response.all(&name) {
+            headers.append(&name, value);
+        }
+    }
+    let response_url = response.get_url().to_string();
+    let mut body = Vec::new();
+    let status = response.status();
+    let status_text = response.status_text().to_string();
+    response.into_reader().read_to_end(&mut body)?;
+    Ok(Response {
+        redirected: response_url != url.as_str(),
+        url: response_url,
+        status,
+        status_text,
+        headers,
+        body,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::{fetch, Method};
+    use futures_executor::block_on;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+
+    /// Starts an HTTP server answering every request on its own connection.
+    fn server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                thread::spawn(move || handle(stream));
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    fn handle(stream: TcpStream) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let mut parts = line.split_whitespace();
+        let method = parts.next().unwrap().to_string();
+        let path = parts.next().unwrap().to_string();
+
+        let mut request_headers = Vec::new();
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line.split_at(line.find(':').unwrap());
+            let value = value[1..].trim().to_string();
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.parse().unwrap();
+            }
+            request_headers.push(format!("{}: {}", name.to_ascii_lowercase(), value));
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+
+        let (status, headers, body) = match path.as_str() {
+            "/text" => ("200 OK", "X-Test: a\r\nX-Test: b\r\n", b"hello".to_vec()),
+            "/echo" => {
+                let mut echo = format!("{}\n", method).into_bytes();
+                echo.extend(request_headers.join("\n").into_bytes());
+                echo.extend(b"\n\n");
+                echo.extend(body);
+                ("201 Created", "", echo)
+            }
+            "/redirect" => ("302 Found", "Location: /text\r\n", Vec::new()),
+            _ => ("404 Not Found", "", b"not found".to_vec()),
+        };
+        let mut stream = stream;
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            headers,
+            body.len()
+        )
+        .unwrap();
+        stream.write_all(&body).unwrap();
+    }
+
+    #[test]
+    fn test_get() {
+        let url = server();
+        let response = block_on(fetch(format!("{}/text", url))).unwrap();
+        assert!(response.ok());
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.status_text(), "OK");
+        assert_eq!(response.text().unwrap(), "hello");
+        assert_eq!(response.headers().get("x-test").unwrap(), "a, b");
+        assert!(!response.redirected());
+    }
+
+    #[test]
+    fn test_post() {
+        let url = server();
+        let request = Request::new(&format!("{}/echo", url))
+            .method(Method::Post)
+            .header("X-Test", "a")
+            .header("X-Test", "b")
+            .text("hello");
+        let response = block_on(fetch(request)).unwrap();
+        assert_eq!(response.status(), 201);
+        let text = response.text().unwrap();
+        let (head, body) = text.split_at(text.find("\n\n").unwrap());
+        assert!(head.starts_with("POST\n"));
+        assert!(head.contains("x-test: a, b"));
+        assert!(head.contains("content-type: text/plain;charset=UTF-8"));
+        assert_eq!(body, "\n\nhello");
+    }
+
+    #[test]
+    fn test_status() {
+        let url = server();
+        let response = block_on(fetch(format!("{}/missing", url))).unwrap();
+        assert!(!response.ok());
+        assert_eq!(response.status(), 404);
+        assert_eq!(response.bytes(), b"not found");
+    }
+
+    #[test]
+    fn test_redirect() {
+        let url = server();
+        let response = block_on(fetch(format!("{}/redirect", url))).unwrap();
+        assert!(response.redirected());
+        assert_eq!(response.url(), format!("{}/text", url));
+        assert_eq!(response.text().unwrap(), "hello");
+
+        let request = Request::new(&format!("{}/redirect", url)).redirect(Redirect::Manual);
+        let response = block_on(fetch(request)).unwrap();
+        assert!(!response.redirected());
+        assert_eq!(response.status(), 302);
+        assert_eq!(response.headers().get("location").unwrap(), "/text");
+
+        let request = Request::new(&format!("{}/redirect", url)).redirect(Redirect::Error);
+        assert!(block_on(fetch(request)).is_err());
+    }
+
+    #[test]
+    fn test_network_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        drop(listener);
+        assert!(block_on(fetch(url)).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json() {
+        let url = server();
+        let request = Request::new(&format!("{}/echo", url))
+            .method(Method::Put)
+            .json(&vec![1, 2])
+            .unwrap();
+        let response = block_on(fetch(request)).unwrap();
+        let text = response.text().unwrap();
+        assert!(text.contains("content-type: application/json"));
+        assert!(text.ends_with("\n\n[1,2]"));
+    }
+}
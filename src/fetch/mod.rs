@@ -0,0 +1,357 @@
+//! The Fetch API
+use crate::Result;
+use failure::format_err;
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(target_arch = "wasm32")]
+mod browser;
+#[cfg(not(target_arch = "wasm32"))]
+mod desktop;
+
+/// Fetches a resource.
+///
+/// The returned future resolves once the whole response body was received.
+/// On desktop the request is sent on a separate thread, so the future can be
+/// polled by any executor.
+///
+/// Responses with an error status like 404 are returned as responses, only
+/// network errors and disallowed redirects fail.
+pub async fn fetch<R: Into<Request>>(request: R) -> Result<Response> {
+    let request = request.into();
+    #[cfg(target_arch = "wasm32")]
+    let response = browser::fetch(request).await;
+    #[cfg(not(target_arch = "wasm32"))]
+    let response = desktop::fetch(request).await;
+    response
+}
+
+/// The HTTP method of a request.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum Method {
+    /// `GET`
+    #[default]
+    Get,
+    /// `HEAD`
+    Head,
+    /// `POST`
+    Post,
+    /// `PUT`
+    Put,
+    /// `DELETE`
+    Delete,
+    /// `PATCH`
+    Patch,
+    /// `OPTIONS`
+    Options,
+}
+
+impl Method {
+    /// Returns the method's name.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Patch => "PATCH",
+            Method::Options => "OPTIONS",
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Method {
+    type Err = crate::Error;
+
+    fn from_str(method: &str) -> Result<Self> {
+        Ok(match method.to_ascii_uppercase().as_str() {
+            "GET" => Method::Get,
+            "HEAD" => Method::Head,
+            "POST" => Method::Post,
+            "PUT" => Method::Put,
+            "DELETE" => Method::Delete,
+            "PATCH" => Method::Patch,
+            "OPTIONS" => Method::Options,
+            _ => return Err(format_err!("unsupported method {}", method)),
+        })
+    }
+}
+
+/// How redirects are handled.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum Redirect {
+    /// Follows redirects.
+    #[default]
+    Follow,
+    /// Fails when the response is a redirect.
+    Error,
+    /// Returns redirects as responses. In the browser the response is opaque
+    /// and has the status 0.
+    Manual,
+}
+
+/// The maximum number of redirects that are followed.
+pub const MAX_REDIRECTS: u32 = 20;
+
+/// A list of HTTP headers. Names are case-insensitive.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Headers {
+    entries: Vec<(String, String)>,
+}
+
+impl Headers {
+    /// Creates an empty list of headers.
+    pub fn new() -> Self {
+        Headers::default()
+    }
+
+    /// Appends a value to a header.
+    pub fn append(&mut self, name: &str, value: &str) {
+        self.entries.push((name.to_ascii_lowercase(), value.to_string()));
+    }
+
+    /// Replaces the values of a header.
+    pub fn set(&mut self, name: &str, value: &str) {
+        self.delete(name);
+        self.append(name, value);
+    }
+
+    /// Returns the values of a header joined by `, `.
+    pub fn get(&self, name: &str) -> Option<String> {
+        let values = self.get_all(name);
+        if values.is_empty() {
+            None
+        } else {
+            Some(values.join(", "))
+        }
+    }
+
+    /// Returns the values of a header.
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.entries
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    /// Returns whether the header exists.
+    pub fn has(&self, name: &str) -> bool {
+        self.entries.iter().any(|(key, _)| key.eq_ignore_ascii_case(name))
+    }
+
+    /// Removes a header.
+    pub fn delete(&mut self, name: &str) {
+        self.entries.retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+    }
+
+    /// Iterates over the headers in insertion order. Names are lowercase.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
+/// An HTTP request.
+///
+/// ```
+/// use libweb::fetch::{Method, Request};
+///
+/// let request = Request::new("http://127.0.0.1/items")
+///     .method(Method::Post)
+///     .header("Accept", "text/plain")
+///     .text("hello");
+/// assert_eq!(request.headers().get("content-type").unwrap(), "text/plain;charset=UTF-8");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Request {
+    url: String,
+    method: Method,
+    headers: Headers,
+    body: Option<Vec<u8>>,
+    redirect: Redirect,
+}
+
+impl Request {
+    /// Creates a `GET` request.
+    pub fn new(url: &str) -> Self {
+        Request {
+            url: url.to_string(),
+            method: Method::Get,
+            headers: Headers::new(),
+            body: None,
+            redirect: Redirect::Follow,
+        }
+    }
+
+    /// Sets the method.
+    pub fn method(mut self, method: Method) -> Self {
+        self.method = method;
+        self
+    }
+
+    /// Appends a header.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    /// Sets the body.
+    pub fn body<B: Into<Vec<u8>>>(mut self, body: B) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    /// Sets a text body. The content type defaults to `text/plain`.
+    pub fn text(self, text: &str) -> Self {
+        self.body_with_type(text, "text/plain;charset=UTF-8")
+    }
+
+    /// Sets a JSON body. The content type defaults to `application/json`.
+    #[cfg(feature = "serde")]
+    pub fn json<T: serde::Serialize + ?Sized>(self, value: &T) -> Result<Self> {
+        let body = serde_json::to_vec(value)?;
+        Ok(self.body_with_type(body, "application/json"))
+    }
+
+    /// Sets how redirects are handled.
+    pub fn redirect(mut self, redirect: Redirect) -> Self {
+        self.redirect = redirect;
+        self
+    }
+
+    /// Returns the url.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Returns the headers.
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    fn body_with_type<B: Into<Vec<u8>>>(mut self, body: B, content_type: &str) -> Self {
+        if !self.headers.has("content-type") {
+            self.headers.set("content-type", content_type);
+        }
+        self.body(body)
+    }
+}
+
+impl<'a> From<&'a str> for Request {
+    fn from(url: &'a str) -> Self {
+        Request::new(url)
+    }
+}
+
+impl From<String> for Request {
+    fn from(url: String) -> Self {
+        Request::new(&url)
+    }
+}
+
+/// An HTTP response.
+#[derive(Debug, Clone)]
+pub struct Response {
+    url: String,
+    status: u16,
+    status_text: String,
+    headers: Headers,
+    redirected: bool,
+    body: Vec<u8>,
+}
+
+impl Response {
+    /// Returns the url of the response, which is the url of the last
+    /// redirect.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Returns the status code.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Returns the status message.
+    pub fn status_text(&self) -> &str {
+        &self.status_text
+    }
+
+    /// Returns whether the status is in the range 200-299.
+    pub fn ok(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Returns whether the response is the result of a redirect.
+    pub fn redirected(&self) -> bool {
+        self.redirected
+    }
+
+    /// Returns the headers.
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// Returns the body.
+    pub fn bytes(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Returns the body, consuming the response.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.body
+    }
+
+    /// Decodes the body as UTF-8.
+    pub fn text(&self) -> Result<String> {
+        Ok(String::from_utf8(self.body.clone())?)
+    }
+
+    /// Deserializes the body from JSON.
+    #[cfg(feature = "serde")]
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T> {
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headers() {
+        let mut headers = Headers::new();
+        headers.append("Accept", "text/plain");
+        headers.append("accept", "text/html");
+        assert_eq!(headers.get("ACCEPT").unwrap(), "text/plain, text/html");
+        assert_eq!(headers.get_all("accept"), vec!["text/plain", "text/html"]);
+        headers.set("Accept", "*/*");
+        assert_eq!(headers.iter().collect::<Vec<_>>(), vec![("accept", "*/*")]);
+        headers.delete("accept");
+        assert!(!headers.has("accept"));
+        assert_eq!(headers.get("accept"), None);
+    }
+
+    #[test]
+    fn test_method() {
+        assert_eq!("patch".parse::<Method>().unwrap(), Method::Patch);
+        assert_eq!(Method::Options.to_string(), "OPTIONS");
+        assert!("CONNECT".parse::<Method>().is_err());
+    }
+
+    #[test]
+    fn test_request() {
+        let request = Request::new("http://127.0.0.1/")
+            .header("Content-Type", "text/csv")
+            .text("a,b");
+        assert_eq!(request.headers().get("content-type").unwrap(), "text/csv");
+        assert_eq!(request.body.as_deref(), Some(&b"a,b"[..]));
+    }
+}
@@ -0,0 +1,574 @@
+use crate::idb::{self, ConstraintError, Direction, Key, KeyRange, Mode, Record, StoreOptions, Value};
+use crate::{js_value_to_error, Result};
+use failure::format_err;
+use std::cell::RefCell;
+use std::ops::Bound;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{IdbCursorDirection, IdbDatabase, IdbRequest, IdbTransaction, IdbTransactionMode};
+
+pub(crate) struct Database {
+    db: IdbDatabase,
+}
+
+impl Database {
+    pub(crate) async fn open<F>(name: &str, version: u32, upgrade: F) -> Result<Self>
+    where
+        F: FnOnce(&mut idb::Upgrade) -> Result<()> + 'static,
+    {
+        let request = factory()?
+            .open_with_u32(name, version)
+            .map_err(dom_error)?;
+        // Errors of the upgrade abort the version change transaction, which
+        // fails the request.
+        let error = Rc::new(RefCell::new(None));
+        let onupgradeneeded: Closure<dyn FnMut(web_sys::IdbVersionChangeEvent)> = {
+            let request = request.clone();
+            let error = error.clone();
+            Closure::once(move |event: web_sys::IdbVersionChangeEvent| {
+                let transaction = request.transaction();
+                let result = request
+                    .result()
+                    .map_err(js_value_to_error)
+                    .and_then(|db| db.dyn_into::<IdbDatabase>().map_err(js_value_to_error))
+                    .and_then(|db| {
+                        let transaction = transaction
+                            .as_ref()
+                            .ok_or_else(|| format_err!("No version change transaction"))?;
+                        upgrade(&mut idb::Upgrade {
+                            inner: Upgrade {
+                                db: &db,
+                                transaction,
+                                old_version: event.old_version() as u32,
+                                new_version: event.new_version().unwrap_or_default() as u32,
+                            },
+                        })
+                    });
+                if let Err(err) = result {
+                    if let Some(transaction) = transaction {
+                        let _ = transaction.abort();
+                    }
+                    *error.borrow_mut() = Some(err);
+                }
+            })
+        };
+        request.set_onupgradeneeded(Some(onupgradeneeded.as_ref().unchecked_ref()));
+        let result = wait(&request).await;
+        request.set_onupgradeneeded(None);
+        if let Some(err) = error.borrow_mut().take() {
+            return Err(err);
+        }
+        let db = result?.dyn_into().map_err(js_value_to_error)?;
+        Ok(Database { db })
+    }
+
+    pub(crate) async fn delete(name: &str) -> Result<()> {
+        let request = factory()?.delete_database(name).map_err(dom_error)?;
+        wait(&request).await?;
+        Ok(())
+    }
+
+    pub(crate) fn name(&self) -> String {
+        self.db.name()
+    }
+
+    pub(crate) fn version(&self) -> u32 {
+        self.db.version() as u32
+    }
+
+    pub(crate) fn store_names(&self) -> Vec<String> {
+        names(&self.db.object_store_names())
+    }
+
+    pub(crate) fn transaction(&self, stores: &[&str], mode: Mode) -> Result<Transaction> {
+        let stores = stores
+            .iter()
+            .map(|store| JsValue::from_str(store))
+            .collect::<js_sys::Array>();
+        let mode = match mode {
+            Mode::ReadOnly => IdbTransactionMode::Readonly,
+            Mode::ReadWrite => IdbTransactionMode::Readwrite,
+        };
+        let transaction = self
+            .db
+            .transaction_with_str_sequence_and_mode(&stores, mode)
+            .map_err(dom_error)?;
+        // Listen for the end of the transaction right away, as it commits on
+        // its own once no requests are pending.
+        let complete = JsFuture::from(js_sys::Promise::new(&mut |resolve, reject| {
+            transaction.set_oncomplete(Some(&resolve));
+            transaction.set_onerror(Some(&reject));
+            transaction.set_onabort(Some(&reject));
+        }));
+        Ok(Transaction {
+            transaction,
+            complete,
+        })
+    }
+}
+
+impl Drop for Database {
+    fn drop(&mut self) {
+        // Open connections block upgrades and deletes of the database.
+        self.db.close();
+    }
+}
+
+fn factory() -> Result<web_sys::IdbFactory> {
+    let window = web_sys::window().ok_or_else(|| format_err!("No global `window` exists"))?;
+    window
+        .indexed_db()
+        .map_err(js_value_to_error)?
+        .ok_or_else(|| format_err!("IndexedDB is not available"))
+}
+
+pub(crate) struct Upgrade<'a> {
+    db: &'a IdbDatabase,
+    transaction: &'a IdbTransaction,
+    old_version: u32,
+    new_version: u32,
+}
+
+impl Upgrade<'_> {
+    pub(crate) fn old_version(&self) -> u32 {
+        self.old_version
+    }
+
+    pub(crate) fn new_version(&self) -> u32 {
+        self.new_version
+    }
+
+    pub(crate) fn store_names(&self) -> Vec<String> {
+        names(&self.db.object_store_names())
+    }
+
+    pub(crate) fn create_store(&mut self, name: &str, options: &StoreOptions) -> Result<()> {
+        let mut parameters = web_sys::IdbObjectStoreParameters::new();
+        if let Some(key_path) = &options.key_path {
+            parameters.key_path(Some(&JsValue::from_str(key_path)));
+        }
+        parameters.auto_increment(options.auto_increment);
+        self.db
+            .create_object_store_with_optional_parameters(name, &parameters)
+            .map_err(dom_error)?;
+        Ok(())
+    }
+
+    pub(crate) fn delete_store(&mut self, name: &str) -> Result<()> {
+        self.db.delete_object_store(name).map_err(dom_error)
+    }
+
+    pub(crate) fn create_index(
+        &mut self,
+        store: &str,
+        name: &str,
+        key_path: &str,
+        unique: bool,
+    ) -> Result<()> {
+        let mut parameters = web_sys::IdbIndexParameters::new();
+        parameters.unique(unique);
+        self.transaction
+            .object_store(store)
+            .map_err(dom_error)?
+            .create_index_with_str_and_optional_parameters(name, key_path, &parameters)
+            .map_err(dom_error)?;
+        Ok(())
+    }
+
+    pub(crate) fn delete_index(&mut self, store: &str, name: &str) -> Result<()> {
+        self.transaction
+            .object_store(store)
+            .map_err(dom_error)?
+            .delete_index(name)
+            .map_err(dom_error)
+    }
+}
+
+pub(crate) struct Transaction {
+    transaction: IdbTransaction,
+    complete: JsFuture,
+}
+
+impl Transaction {
+    pub(crate) fn store(&self, name: &str) -> Result<ObjectStore> {
+        let store = self.transaction.object_store(name).map_err(dom_error)?;
+        Ok(ObjectStore { store })
+    }
+
+    pub(crate) async fn commit(self) -> Result<()> {
+        match self.complete.await {
+            Ok(_) => Ok(()),
+            Err(_) => Err(match self.transaction.error() {
+                Some(err) => dom_error(err.into()),
+                None => format_err!("The transaction was aborted"),
+            }),
+        }
+    }
+
+    pub(crate) fn abort(self) -> Result<()> {
+        self.transaction.abort().map_err(dom_error)
+    }
+}
+
+pub(crate) struct ObjectStore {
+    store: web_sys::IdbObjectStore,
+}
+
+impl ObjectStore {
+    pub(crate) async fn put(&self, value: &Value, key: Option<&Key>, add: bool) -> Result<Key> {
+        let value = value_to_js(value)?;
+        let request = match (key, add) {
+            (Some(key), false) => self.store.put_with_key(&value, &key_to_js(key)),
+            (None, false) => self.store.put(&value),
+            (Some(key), true) => self.store.add_with_key(&value, &key_to_js(key)),
+            (None, true) => self.store.add(&value),
+        }
+        .map_err(dom_error)?;
+        key_from_js(&wait(&request).await?)
+    }
+
+    pub(crate) async fn get(&self, key: &Key) -> Result<Option<Value>> {
+        let request = self.store.get(&key_to_js(key)).map_err(dom_error)?;
+        optional_value(&wait(&request).await?)
+    }
+
+    pub(crate) async fn get_all(&self, range: Option<&KeyRange>) -> Result<Vec<Value>> {
+        let request = self
+            .store
+            .get_all_with_key(&range_to_js(range)?)
+            .map_err(dom_error)?;
+        values(&wait(&request).await?)
+    }
+
+    pub(crate) async fn keys(&self, range: Option<&KeyRange>) -> Result<Vec<Key>> {
+        let request = self
+            .store
+            .get_all_keys_with_key(&range_to_js(range)?)
+            .map_err(dom_error)?;
+        let keys: js_sys::Array = wait(&request).await?.dyn_into().map_err(js_value_to_error)?;
+        keys.iter().map(|key| key_from_js(&key)).collect()
+    }
+
+    pub(crate) async fn count(&self, range: Option<&KeyRange>) -> Result<usize> {
+        let request = self
+            .store
+            .count_with_key(&range_to_js(range)?)
+            .map_err(dom_error)?;
+        count(&wait(&request).await?)
+    }
+
+    pub(crate) async fn delete(&self, key: &Key) -> Result<()> {
+        let request = self.store.delete(&key_to_js(key)).map_err(dom_error)?;
+        wait(&request).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn clear(&self) -> Result<()> {
+        let request = self.store.clear().map_err(dom_error)?;
+        wait(&request).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn open_cursor(
+        &self,
+        range: Option<&KeyRange>,
+        direction: Direction,
+    ) -> Result<Cursor> {
+        let request = self
+            .store
+            .open_cursor_with_range_and_direction(&range_to_js(range)?, cursor_direction(direction))
+            .map_err(dom_error)?;
+        Ok(Cursor::new(request))
+    }
+
+    pub(crate) fn index(&self, name: &str) -> Result<Index> {
+        let index = self.store.index(name).map_err(dom_error)?;
+        Ok(Index { index })
+    }
+}
+
+pub(crate) struct Index {
+    index: web_sys::IdbIndex,
+}
+
+impl Index {
+    pub(crate) async fn get(&self, key: &Key) -> Result<Option<Value>> {
+        let request = self.index.get(&key_to_js(key)).map_err(dom_error)?;
+        optional_value(&wait(&request).await?)
+    }
+
+    pub(crate) async fn get_all(&self, range: Option<&KeyRange>) -> Result<Vec<Value>> {
+        let request = self
+            .index
+            .get_all_with_key(&range_to_js(range)?)
+            .map_err(dom_error)?;
+        values(&wait(&request).await?)
+    }
+
+    pub(crate) async fn count(&self, range: Option<&KeyRange>) -> Result<usize> {
+        let request = self
+            .index
+            .count_with_key(&range_to_js(range)?)
+            .map_err(dom_error)?;
+        count(&wait(&request).await?)
+    }
+
+    pub(crate) async fn open_cursor(
+        &self,
+        range: Option<&KeyRange>,
+        direction: Direction,
+    ) -> Result<Cursor> {
+        let request = self
+            .index
+            .open_cursor_with_range_and_direction(&range_to_js(range)?, cursor_direction(direction))
+            .map_err(dom_error)?;
+        Ok(Cursor::new(request))
+    }
+}
+
+/// A cursor fires the success event of the same request every time it
+/// moves, until its result is `null`.
+pub(crate) struct Cursor {
+    request: IdbRequest,
+    pending: Option<JsFuture>,
+    cursor: Option<web_sys::IdbCursorWithValue>,
+}
+
+impl Cursor {
+    fn new(request: IdbRequest) -> Self {
+        Cursor {
+            pending: Some(listen(&request)),
+            request,
+            cursor: None,
+        }
+    }
+
+    pub(crate) async fn next(&mut self) -> Result<Option<Record>> {
+        if let Some(cursor) = self.cursor.take() {
+            cursor.continue_().map_err(dom_error)?;
+            self.pending = Some(listen(&self.request));
+        }
+        let pending = match self.pending.take() {
+            Some(pending) => pending,
+            None => return Ok(None),
+        };
+        pending.await.map_err(|_| request_error(&self.request))?;
+        let result = self.request.result().map_err(js_value_to_error)?;
+        if result.is_null() {
+            return Ok(None);
+        }
+        let cursor: web_sys::IdbCursorWithValue = result.dyn_into().map_err(js_value_to_error)?;
+        let record = Record {
+            key: key_from_js(&cursor.key().map_err(js_value_to_error)?)?,
+            primary_key: key_from_js(&cursor.primary_key().map_err(js_value_to_error)?)?,
+            value: value_from_js(&cursor.value().map_err(js_value_to_error)?)?,
+        };
+        self.cursor = Some(cursor);
+        Ok(Some(record))
+    }
+}
+
+/// Returns a future resolved by the next success event of the request.
+///
+/// The handlers are set right away, since the events are fired as soon as
+/// the browser returns to the event loop.
+fn listen(request: &IdbRequest) -> JsFuture {
+    JsFuture::from(js_sys::Promise::new(&mut |resolve, reject| {
+        request.set_onsuccess(Some(&resolve));
+        request.set_onerror(Some(&reject));
+    }))
+}
+
+async fn wait(request: &IdbRequest) -> Result<JsValue> {
+    listen(request)
+        .await
+        .map_err(|_| request_error(request))?;
+    request.result().map_err(js_value_to_error)
+}
+
+fn request_error(request: &IdbRequest) -> failure::Error {
+    match request.error() {
+        Ok(Some(err)) => dom_error(err.into()),
+        _ => format_err!("The request failed"),
+    }
+}
+
+/// Converts a `DOMException`, returning `ConstraintError` for violated
+/// constraints.
+fn dom_error(err: JsValue) -> failure::Error {
+    match err.dyn_ref::<web_sys::DomException>() {
+        Some(exception) if exception.name() == "ConstraintError" => ConstraintError {
+            message: exception.message(),
+        }
+        .into(),
+        Some(exception) => format_err!("{}: {}", exception.name(), exception.message()),
+        None => js_value_to_error(err),
+    }
+}
+
+fn names(list: &web_sys::DomStringList) -> Vec<String> {
+    (0..list.length()).filter_map(|i| list.get(i)).collect()
+}
+
+fn cursor_direction(direction: Direction) -> IdbCursorDirection {
+    match direction {
+        Direction::Next => IdbCursorDirection::Next,
+        Direction::Prev => IdbCursorDirection::Prev,
+    }
+}
+
+fn count(value: &JsValue) -> Result<usize> {
+    let count = value
+        .as_f64()
+        .ok_or_else(|| format_err!("Invalid count {:?}", value))?;
+    Ok(count as usize)
+}
+
+fn value_to_js(value: &Value) -> Result<JsValue> {
+    js_sys::JSON::parse(&serde_json::to_string(value)?).map_err(js_value_to_error)
+}
+
+fn value_from_js(value: &JsValue) -> Result<Value> {
+    let json = js_sys::JSON::stringify(value).map_err(js_value_to_error)?;
+    let json = json
+        .as_string()
+        .ok_or_else(|| format_err!("Can't convert {:?} to JSON", value))?;
+    Ok(serde_json::from_str(&json)?)
+}
+
+/// Converts the result of a get request, which is `undefined` for missing
+/// records.
+fn optional_value(value: &JsValue) -> Result<Option<Value>> {
+    if value.is_undefined() {
+        return Ok(None);
+    }
+    value_from_js(value).map(Some)
+}
+
+fn values(values: &JsValue) -> Result<Vec<Value>> {
+    let values: &js_sys::Array = values
+        .dyn_ref()
+        .ok_or_else(|| format_err!("Expected an array, got {:?}", values))?;
+    values.iter().map(|value| value_from_js(&value)).collect()
+}
+
+fn key_to_js(key: &Key) -> JsValue {
+    match key {
+        Key::Number(number) => JsValue::from_f64(*number),
+        Key::String(string) => JsValue::from_str(string),
+        Key::Binary(bytes) => js_sys::Uint8Array::from(&bytes[..]).buffer().into(),
+        Key::Array(keys) => keys.iter().map(key_to_js).collect::<js_sys::Array>().into(),
+    }
+}
+
+fn key_from_js(value: &JsValue) -> Result<Key> {
+    if let Some(number) = value.as_f64() {
+        Ok(Key::Number(number))
+    } else if let Some(string) = value.as_string() {
+        Ok(Key::String(string))
+    } else if let Some(date) = value.dyn_ref::<js_sys::Date>() {
+        Ok(Key::Number(date.get_time()))
+    } else if value.is_instance_of::<js_sys::ArrayBuffer>() {
+        Ok(Key::Binary(js_sys::Uint8Array::new(value).to_vec()))
+    } else if let Some(keys) = value.dyn_ref::<js_sys::Array>() {
+        keys.iter().map(|key| key_from_js(&key)).collect::<Result<_>>().map(Key::Array)
+    } else {
+        Err(format_err!("Invalid key {:?}", value))
+    }
+}
+
+/// Converts a key range to an `IDBKeyRange`, or `null` for all keys.
+fn range_to_js(range: Option<&KeyRange>) -> Result<JsValue> {
+    let range = match range {
+        Some(range) => range,
+        None => return Ok(JsValue::NULL),
+    };
+    let bound = |bound: &Bound<Key>| match bound {
+        Bound::Included(key) => Some((key_to_js(key), false)),
+        Bound::Excluded(key) => Some((key_to_js(key), true)),
+        Bound::Unbounded => None,
+    };
+    let range = match (bound(&range.lower), bound(&range.upper)) {
+        (None, None) => return Ok(JsValue::NULL),
+        (Some((lower, open)), None) => web_sys::IdbKeyRange::lower_bound_with_open(&lower, open),
+        (None, Some((upper, open))) => web_sys::IdbKeyRange::upper_bound_with_open(&upper, open),
+        (Some((lower, lower_open)), Some((upper, upper_open))) => {
+            web_sys::IdbKeyRange::bound_with_lower_open_and_upper_open(
+                &lower, &upper, lower_open, upper_open,
+            )
+        }
+    };
+    Ok(range.map_err(dom_error)?.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::idb::{ConstraintError, Database, Direction, Key, KeyRange, Mode, StoreOptions};
+    use serde_json::json;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    async fn open(name: &str) -> Database {
+        Database::delete(name).await.unwrap();
+        Database::open(name, 1, |upgrade| {
+            assert_eq!(upgrade.old_version(), 0);
+            let options = StoreOptions {
+                key_path: Some("id".into()),
+                auto_increment: true,
+            };
+            upgrade.create_store("notes", &options)?;
+            upgrade.create_index("notes", "by_title", "title", true)
+        })
+        .await
+        .unwrap()
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_put_get() {
+        let db = open("libweb-put-get").await;
+        assert_eq!(db.version(), 1);
+        assert_eq!(db.store_names(), vec!["notes"]);
+        let tx = db.transaction(&["notes"], Mode::ReadWrite).unwrap();
+        let notes = tx.store("notes").unwrap();
+        let key = notes.put(&json!({ "title": "a" }), None).await.unwrap();
+        assert_eq!(key, Key::from(1));
+        let note = notes.get(&key).await.unwrap().unwrap();
+        assert_eq!(note["title"], json!("a"));
+        assert_eq!(notes.get(&Key::from(2)).await.unwrap(), None);
+        tx.commit().await.unwrap();
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_constraint() {
+        let db = open("libweb-constraint").await;
+        let tx = db.transaction(&["notes"], Mode::ReadWrite).unwrap();
+        let notes = tx.store("notes").unwrap();
+        notes.add(&json!({ "id": 1, "title": "a" }), None).await.unwrap();
+        let err = notes.add(&json!({ "id": 1, "title": "b" }), None).await.unwrap_err();
+        assert!(err.downcast::<ConstraintError>().is_ok());
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_cursor() {
+        let db = open("libweb-cursor").await;
+        let tx = db.transaction(&["notes"], Mode::ReadWrite).unwrap();
+        let notes = tx.store("notes").unwrap();
+        for title in &["c", "a", "b"] {
+            notes.put(&json!({ "title": title }), None).await.unwrap();
+        }
+        assert_eq!(notes.count(None).await.unwrap(), 3);
+        let keys = notes.keys(Some(&KeyRange::at_least(2))).await.unwrap();
+        assert_eq!(keys, vec![Key::from(2), Key::from(3)]);
+
+        let index = notes.index("by_title").unwrap();
+        let mut cursor = index.open_cursor(None, Direction::Prev).await.unwrap();
+        let mut titles = Vec::new();
+        while let Some(record) = cursor.next().await.unwrap() {
+            titles.push(record.key);
+        }
+        assert_eq!(titles, vec![Key::from("c"), Key::from("b"), Key::from("a")]);
+    }
+}
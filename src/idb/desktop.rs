@@ -0,0 +1,1302 @@
+use crate::dirs::app_dir;
+use crate::idb::{self, ConstraintError, Direction, Key, KeyRange, Mode, Record, StoreOptions, Value};
+use crate::Result;
+use failure::{bail, format_err};
+use log::*;
+use serde_json::{json, Map};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::future;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, Weak};
+use std::task::{Poll, Waker};
+
+#[derive(Debug, Clone, PartialEq)]
+struct IndexState {
+    key_path: String,
+    unique: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct StoreState {
+    key_path: Option<String>,
+    auto_increment: bool,
+    /// The next key generated by the key generator.
+    next_key: f64,
+    indexes: BTreeMap<String, IndexState>,
+    records: BTreeMap<Key, Value>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct State {
+    version: u32,
+    stores: BTreeMap<String, StoreState>,
+}
+
+/// A database shared by all connections opened with the same path.
+struct Shared {
+    name: String,
+    path: PathBuf,
+    state: Mutex<State>,
+    queue: Mutex<Queue>,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn queue(&self) -> MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Removes a finished transaction from the queue, starting the
+    /// transactions waiting for it.
+    fn finish(&self, id: u64) {
+        let wakers = {
+            let mut queue = self.queue();
+            queue.transactions.retain(|tx| tx.id != id);
+            std::mem::take(&mut queue.wakers)
+        };
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+/// The unfinished transactions of a database in the order they were created.
+///
+/// Like in IndexedDB a transaction runs after the transactions created before
+/// it with an overlapping scope, unless both are read-only.
+#[derive(Default)]
+struct Queue {
+    next_id: u64,
+    transactions: Vec<Queued>,
+    wakers: Vec<Waker>,
+}
+
+struct Queued {
+    id: u64,
+    stores: Vec<String>,
+    mode: Mode,
+    tx: Weak<Tx>,
+}
+
+impl Queue {
+    /// Returns the unfinished transactions created before `id` that it has
+    /// to run after.
+    fn blocking(&self, id: u64) -> Vec<&Queued> {
+        let position = match self.transactions.iter().position(|tx| tx.id == id) {
+            Some(position) => position,
+            None => return Vec::new(),
+        };
+        let tx = &self.transactions[position];
+        self.transactions[..position]
+            .iter()
+            .filter(|other| {
+                (tx.mode == Mode::ReadWrite || other.mode == Mode::ReadWrite)
+                    && other.stores.iter().any(|store| tx.stores.contains(store))
+            })
+            .collect()
+    }
+}
+
+fn registry() -> MutexGuard<'static, HashMap<PathBuf, Arc<Shared>>> {
+    static OPEN: OnceLock<Mutex<HashMap<PathBuf, Arc<Shared>>>> = OnceLock::new();
+    OPEN.get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+}
+
+pub(crate) struct Database {
+    shared: Arc<Shared>,
+}
+
+impl Database {
+    pub(crate) async fn open<F>(name: &str, version: u32, upgrade: F) -> Result<Self>
+    where
+        F: FnOnce(&mut idb::Upgrade) -> Result<()>,
+    {
+        Database::open_named(&path(name)?, name, version, upgrade)
+    }
+
+    pub(crate) fn open_at<F>(path: &Path, version: u32, upgrade: F) -> Result<Self>
+    where
+        F: FnOnce(&mut idb::Upgrade) -> Result<()>,
+    {
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default()
+            .to_string();
+        Database::open_named(path, &name, version, upgrade)
+    }
+
+    fn open_named<F>(path: &Path, name: &str, version: u32, upgrade: F) -> Result<Self>
+    where
+        F: FnOnce(&mut idb::Upgrade) -> Result<()>,
+    {
+        if version == 0 {
+            bail!("The version of database '{}' must be greater than 0", name);
+        }
+        let shared = {
+            let mut open = registry();
+            match open.get(path) {
+                Some(shared) => shared.clone(),
+                None => {
+                    let state = match fs::read_to_string(path) {
+                        Ok(contents) => decode(&contents)?,
+                        Err(ref err) if err.kind() == ErrorKind::NotFound => State::default(),
+                        Err(err) => return Err(err.into()),
+                    };
+                    let shared = Arc::new(Shared {
+                        name: name.to_string(),
+                        path: path.to_path_buf(),
+                        state: Mutex::new(state),
+                        queue: Mutex::new(Queue::default()),
+                    });
+                    open.insert(path.to_path_buf(), shared.clone());
+                    shared
+                }
+            }
+        };
+
+        {
+            let mut state = shared.lock();
+            if version < state.version {
+                bail!(
+                    "Database '{}' has version {}, which is greater than {}",
+                    name,
+                    state.version,
+                    version
+                );
+            }
+            if version > state.version {
+                // The upgrade works on a copy, so it's discarded on errors.
+                let mut upgraded = state.clone();
+                upgraded.version = version;
+                upgrade(&mut idb::Upgrade {
+                    inner: Upgrade {
+                        state: &mut upgraded,
+                        old_version: state.version,
+                    },
+                })?;
+                persist(&shared.path, &upgraded)?;
+                *state = upgraded;
+            }
+        }
+        Ok(Database { shared })
+    }
+
+    pub(crate) async fn delete(name: &str) -> Result<()> {
+        Database::delete_at(&path(name)?, name)
+    }
+
+    /// Deletes the database stored at `path`. Fails if it's open, since the
+    /// connections would keep using the deleted database.
+    fn delete_at(path: &Path, name: &str) -> Result<()> {
+        // The registry stays locked until the file is removed, so the
+        // database can't be opened in between.
+        let mut open = registry();
+        if let Some(shared) = open.get(path) {
+            // Connections and transactions hold the database besides the
+            // registry.
+            if Arc::strong_count(shared) > 1 {
+                bail!("Database '{}' can't be deleted while it's open", name);
+            }
+            open.remove(path);
+        }
+        match fs::remove_file(path) {
+            Err(ref err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            result => Ok(result?),
+        }
+    }
+
+    pub(crate) fn name(&self) -> String {
+        self.shared.name.clone()
+    }
+
+    pub(crate) fn version(&self) -> u32 {
+        self.shared.lock().version
+    }
+
+    pub(crate) fn store_names(&self) -> Vec<String> {
+        self.shared.lock().stores.keys().cloned().collect()
+    }
+
+    pub(crate) fn transaction(&self, stores: &[&str], mode: Mode) -> Result<Transaction> {
+        if stores.is_empty() {
+            bail!("A transaction needs at least one object store");
+        }
+        {
+            let state = self.shared.lock();
+            for store in stores {
+                if !state.stores.contains_key(*store) {
+                    bail!("Object store '{}' not found", store);
+                }
+            }
+        }
+        let stores = stores.iter().map(|store| store.to_string()).collect::<Vec<_>>();
+        let mut queue = self.shared.queue();
+        let id = queue.next_id;
+        queue.next_id += 1;
+        let tx = Arc::new(Tx {
+            id,
+            shared: self.shared.clone(),
+            stores: stores.clone(),
+            mode,
+            staged: Mutex::new(BTreeMap::new()),
+            finished: AtomicBool::new(false),
+        });
+        queue.transactions.push(Queued {
+            id,
+            stores,
+            mode,
+            tx: Arc::downgrade(&tx),
+        });
+        Ok(Transaction { tx })
+    }
+}
+
+/// Returns the path of a database in the data directory.
+fn path(name: &str) -> Result<PathBuf> {
+    let mut file = String::new();
+    for byte in name.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' => file.push(byte as char),
+            byte => file.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    Ok(app_dir()?.join("idb").join(file + ".json"))
+}
+
+pub(crate) struct Upgrade<'a> {
+    state: &'a mut State,
+    old_version: u32,
+}
+
+impl Upgrade<'_> {
+    pub(crate) fn old_version(&self) -> u32 {
+        self.old_version
+    }
+
+    pub(crate) fn new_version(&self) -> u32 {
+        self.state.version
+    }
+
+    pub(crate) fn store_names(&self) -> Vec<String> {
+        self.state.stores.keys().cloned().collect()
+    }
+
+    pub(crate) fn create_store(&mut self, name: &str, options: &StoreOptions) -> Result<()> {
+        if self.state.stores.contains_key(name) {
+            return Err(constraint(format!("object store '{}' exists", name)));
+        }
+        let store = StoreState {
+            key_path: options.key_path.clone(),
+            auto_increment: options.auto_increment,
+            next_key: 1.0,
+            indexes: BTreeMap::new(),
+            records: BTreeMap::new(),
+        };
+        self.state.stores.insert(name.to_string(), store);
+        Ok(())
+    }
+
+    pub(crate) fn delete_store(&mut self, name: &str) -> Result<()> {
+        match self.state.stores.remove(name) {
+            Some(_) => Ok(()),
+            None => bail!("Object store '{}' not found", name),
+        }
+    }
+
+    pub(crate) fn create_index(
+        &mut self,
+        store: &str,
+        name: &str,
+        key_path: &str,
+        unique: bool,
+    ) -> Result<()> {
+        let store = self
+            .state
+            .stores
+            .get_mut(store)
+            .ok_or_else(|| format_err!("Object store '{}' not found", store))?;
+        if store.indexes.contains_key(name) {
+            return Err(constraint(format!("index '{}' exists", name)));
+        }
+        let index = IndexState {
+            key_path: key_path.to_string(),
+            unique,
+        };
+        if unique {
+            let mut keys = store
+                .records
+                .values()
+                .filter_map(|value| index_key(value, key_path))
+                .collect::<Vec<_>>();
+            let len = keys.len();
+            keys.sort();
+            keys.dedup();
+            if keys.len() != len {
+                return Err(constraint(format!("index '{}' has duplicate keys", name)));
+            }
+        }
+        store.indexes.insert(name.to_string(), index);
+        Ok(())
+    }
+
+    pub(crate) fn delete_index(&mut self, store: &str, name: &str) -> Result<()> {
+        let store = self
+            .state
+            .stores
+            .get_mut(store)
+            .ok_or_else(|| format_err!("Object store '{}' not found", store))?;
+        match store.indexes.remove(name) {
+            Some(_) => Ok(()),
+            None => bail!("Index '{}' not found", name),
+        }
+    }
+}
+
+/// The uncommitted changes of a transaction to an object store.
+#[derive(Default)]
+struct Staged {
+    /// Whether the committed records were cleared.
+    cleared: bool,
+    /// The written records, `None` for deleted ones.
+    records: BTreeMap<Key, Option<Value>>,
+    next_key: Option<f64>,
+}
+
+impl StoreState {
+    /// Applies changes to the store, returning the changes reverting them.
+    fn apply(&mut self, staged: Staged) -> Staged {
+        let mut revert = Staged {
+            cleared: staged.cleared,
+            records: BTreeMap::new(),
+            next_key: Some(self.next_key),
+        };
+        if staged.cleared {
+            revert.records = std::mem::take(&mut self.records)
+                .into_iter()
+                .map(|(key, value)| (key, Some(value)))
+                .collect();
+        }
+        for (key, value) in staged.records {
+            let old = match value {
+                Some(value) => self.records.insert(key.clone(), value),
+                None => self.records.remove(&key),
+            };
+            if !staged.cleared {
+                revert.records.insert(key, old);
+            }
+        }
+        if let Some(next_key) = staged.next_key {
+            self.next_key = next_key;
+        }
+        revert
+    }
+}
+
+/// An object store as seen by a transaction, including its uncommitted
+/// changes.
+struct View<'a> {
+    store: &'a StoreState,
+    staged: Option<&'a Staged>,
+}
+
+impl View<'_> {
+    fn get(&self, key: &Key) -> Option<&Value> {
+        if let Some(staged) = self.staged {
+            if let Some(value) = staged.records.get(key) {
+                return value.as_ref();
+            }
+            if staged.cleared {
+                return None;
+            }
+        }
+        self.store.records.get(key)
+    }
+
+    fn records(&self) -> BTreeMap<&Key, &Value> {
+        let mut records = match self.staged {
+            Some(staged) if staged.cleared => BTreeMap::new(),
+            _ => self.store.records.iter().collect::<BTreeMap<_, _>>(),
+        };
+        if let Some(staged) = self.staged {
+            for (key, value) in &staged.records {
+                match value {
+                    Some(value) => records.insert(key, value),
+                    None => records.remove(key),
+                };
+            }
+        }
+        records
+    }
+
+    fn next_key(&self) -> f64 {
+        self.staged
+            .and_then(|staged| staged.next_key)
+            .unwrap_or(self.store.next_key)
+    }
+}
+
+/// The state of a transaction shared by its object stores.
+///
+/// Changes are staged in the transaction, and applied to the database and
+/// persisted when it commits. Requests run after the transactions created
+/// before with an overlapping scope have finished, see `Queue`.
+struct Tx {
+    id: u64,
+    shared: Arc<Shared>,
+    stores: Vec<String>,
+    mode: Mode,
+    staged: Mutex<BTreeMap<String, Staged>>,
+    finished: AtomicBool,
+}
+
+impl Tx {
+    /// Commits the transactions the transaction has to run after.
+    ///
+    /// IndexedDB commits transactions once they have no pending requests,
+    /// which a transaction between two requests on desktop can't tell apart
+    /// from one that is used later. Waiting for it instead could wait forever
+    /// when it belongs to the same task.
+    async fn started(&self) {
+        future::poll_fn(|cx| loop {
+            let blocking = {
+                let mut queue = self.shared.queue();
+                let blocking = queue.blocking(self.id);
+                if blocking.is_empty() {
+                    return Poll::Ready(());
+                }
+                let blocking = blocking.iter().filter_map(|tx| tx.tx.upgrade()).collect::<Vec<_>>();
+                if blocking.is_empty() {
+                    // The transactions are being dropped, which commits them.
+                    queue.wakers.push(cx.waker().clone());
+                    return Poll::Pending;
+                }
+                blocking
+            };
+            for tx in blocking {
+                // It may have finished in the meantime.
+                if !tx.finished.load(Ordering::SeqCst) {
+                    if let Err(err) = tx.commit() {
+                        error!("Failed to commit transaction: {}", err);
+                    }
+                }
+            }
+        })
+        .await
+    }
+
+    /// Fails if the transaction has finished. Called with the staged changes
+    /// locked, so they can't be changed after the transaction committed.
+    fn check_finished(&self) -> Result<()> {
+        if self.finished.load(Ordering::SeqCst) {
+            bail!("The transaction has finished");
+        }
+        Ok(())
+    }
+
+    fn read<T, F>(&self, name: &str, f: F) -> Result<T>
+    where
+        F: FnOnce(View) -> Result<T>,
+    {
+        let state = self.shared.lock();
+        let store = state
+            .stores
+            .get(name)
+            .ok_or_else(|| format_err!("Object store '{}' not found", name))?;
+        let staged = self.staged.lock().unwrap_or_else(|err| err.into_inner());
+        self.check_finished()?;
+        f(View {
+            store,
+            staged: staged.get(name),
+        })
+    }
+
+    /// Stages a change. Constraint errors abort the transaction like in
+    /// IndexedDB.
+    fn write<T, F>(&self, name: &str, f: F) -> Result<T>
+    where
+        F: FnOnce(&StoreState, &mut Staged) -> Result<T>,
+    {
+        if self.mode == Mode::ReadOnly {
+            bail!("The transaction is read-only");
+        }
+        let result = {
+            let state = self.shared.lock();
+            let store = state
+                .stores
+                .get(name)
+                .ok_or_else(|| format_err!("Object store '{}' not found", name))?;
+            let mut staged = self.staged.lock().unwrap_or_else(|err| err.into_inner());
+            self.check_finished()?;
+            f(store, staged.entry(name.to_string()).or_default())
+        };
+        if let Err(err) = &result {
+            if err.downcast_ref::<ConstraintError>().is_some() {
+                let _ = self.abort();
+            }
+        }
+        result
+    }
+
+    fn commit(&self) -> Result<()> {
+        let staged = {
+            let mut staged = self.staged.lock().unwrap_or_else(|err| err.into_inner());
+            if self.finished.swap(true, Ordering::SeqCst) {
+                bail!("The transaction has finished");
+            }
+            std::mem::take(&mut *staged)
+        };
+        let result = if staged.is_empty() {
+            Ok(())
+        } else {
+            self.apply(staged)
+        };
+        self.shared.finish(self.id);
+        result
+    }
+
+    /// Applies the changes to the database and persists it. The changes are
+    /// reverted if the database can't be written.
+    fn apply(&self, staged: BTreeMap<String, Staged>) -> Result<()> {
+        let mut state = self.shared.lock();
+        let mut reverts = Vec::new();
+        for (name, staged) in staged {
+            if let Some(store) = state.stores.get_mut(&name) {
+                reverts.push((name, store.apply(staged)));
+            }
+        }
+        if let Err(err) = persist(&self.shared.path, &state) {
+            for (name, revert) in reverts.into_iter().rev() {
+                if let Some(store) = state.stores.get_mut(&name) {
+                    store.apply(revert);
+                }
+            }
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    fn abort(&self) -> Result<()> {
+        {
+            let mut staged = self.staged.lock().unwrap_or_else(|err| err.into_inner());
+            if self.finished.swap(true, Ordering::SeqCst) {
+                bail!("The transaction has finished");
+            }
+            staged.clear();
+        }
+        self.shared.finish(self.id);
+        Ok(())
+    }
+}
+
+impl Drop for Tx {
+    fn drop(&mut self) {
+        if !self.finished.load(Ordering::SeqCst) {
+            if let Err(err) = self.commit() {
+                error!("Failed to commit transaction: {}", err);
+            }
+        }
+    }
+}
+
+pub(crate) struct Transaction {
+    tx: Arc<Tx>,
+}
+
+impl Transaction {
+    pub(crate) fn store(&self, name: &str) -> Result<ObjectStore> {
+        if !self.tx.stores.iter().any(|store| store == name) {
+            bail!("Object store '{}' is not part of the transaction", name);
+        }
+        Ok(ObjectStore {
+            tx: self.tx.clone(),
+            name: name.to_string(),
+        })
+    }
+
+    pub(crate) async fn commit(self) -> Result<()> {
+        self.tx.commit()
+    }
+
+    pub(crate) fn abort(self) -> Result<()> {
+        self.tx.abort()
+    }
+}
+
+pub(crate) struct ObjectStore {
+    tx: Arc<Tx>,
+    name: String,
+}
+
+impl ObjectStore {
+    pub(crate) async fn put(&self, value: &Value, key: Option<&Key>, add: bool) -> Result<Key> {
+        self.tx.started().await;
+        self.tx.write(&self.name, |store, staged| {
+            let view = View {
+                store,
+                staged: Some(staged),
+            };
+            let mut value = value.clone();
+            let key = match (&store.key_path, key) {
+                (Some(_), Some(_)) => {
+                    bail!("Keys can't be passed to object stores with a key path")
+                }
+                (Some(key_path), None) => match lookup(&value, key_path) {
+                    Some(key) => Key::from_value(key)
+                        .ok_or_else(|| format_err!("Invalid key at key path '{}'", key_path))?,
+                    None if store.auto_increment => {
+                        let key = Key::Number(view.next_key());
+                        inject(&mut value, key_path, &key)?;
+                        key
+                    }
+                    None => bail!("No key at key path '{}'", key_path),
+                },
+                (None, Some(key)) => key.clone(),
+                (None, None) if store.auto_increment => Key::Number(view.next_key()),
+                (None, None) => bail!("A key is required"),
+            };
+            if add && view.get(&key).is_some() {
+                return Err(constraint(format!("key {:?} exists", key)));
+            }
+            let unique = store.indexes.iter().filter(|(_, index)| index.unique);
+            let mut records = None;
+            for (name, index) in unique {
+                if let Some(unique_key) = index_key(&value, &index.key_path) {
+                    let records = records.get_or_insert_with(|| view.records());
+                    let duplicate = records.iter().any(|(other, value)| {
+                        **other != key
+                            && index_key(value, &index.key_path).as_ref() == Some(&unique_key)
+                    });
+                    if duplicate {
+                        return Err(constraint(format!(
+                            "key {:?} exists in index '{}'",
+                            unique_key, name
+                        )));
+                    }
+                }
+            }
+            if let Key::Number(number) = key {
+                if store.auto_increment && number >= view.next_key() {
+                    staged.next_key = Some(number.floor() + 1.0);
+                }
+            }
+            staged.records.insert(key.clone(), Some(value));
+            Ok(key)
+        })
+    }
+
+    pub(crate) async fn get(&self, key: &Key) -> Result<Option<Value>> {
+        self.tx.started().await;
+        self.tx.read(&self.name, |view| Ok(view.get(key).cloned()))
+    }
+
+    pub(crate) async fn get_all(&self, range: Option<&KeyRange>) -> Result<Vec<Value>> {
+        let records = self.records(range, Direction::Next).await?;
+        Ok(records.into_iter().map(|record| record.value).collect())
+    }
+
+    pub(crate) async fn keys(&self, range: Option<&KeyRange>) -> Result<Vec<Key>> {
+        let records = self.records(range, Direction::Next).await?;
+        Ok(records.into_iter().map(|record| record.key).collect())
+    }
+
+    pub(crate) async fn count(&self, range: Option<&KeyRange>) -> Result<usize> {
+        Ok(self.records(range, Direction::Next).await?.len())
+    }
+
+    pub(crate) async fn delete(&self, key: &Key) -> Result<()> {
+        self.tx.started().await;
+        self.tx.write(&self.name, |_, staged| {
+            staged.records.insert(key.clone(), None);
+            Ok(())
+        })
+    }
+
+    pub(crate) async fn clear(&self) -> Result<()> {
+        self.tx.started().await;
+        self.tx.write(&self.name, |_, staged| {
+            staged.cleared = true;
+            staged.records.clear();
+            Ok(())
+        })
+    }
+
+    pub(crate) async fn open_cursor(
+        &self,
+        range: Option<&KeyRange>,
+        direction: Direction,
+    ) -> Result<Cursor> {
+        Ok(Cursor {
+            records: self.records(range, direction).await?.into_iter(),
+        })
+    }
+
+    pub(crate) fn index(&self, name: &str) -> Result<Index> {
+        let key_path = self.tx.read(&self.name, |view| {
+            view.store
+                .indexes
+                .get(name)
+                .map(|index| index.key_path.clone())
+                .ok_or_else(|| format_err!("Index '{}' not found", name))
+        })?;
+        Ok(Index {
+            tx: self.tx.clone(),
+            store: self.name.clone(),
+            key_path,
+        })
+    }
+
+    async fn records(&self, range: Option<&KeyRange>, direction: Direction) -> Result<Vec<Record>> {
+        self.tx.started().await;
+        let mut records = self.tx.read(&self.name, |view| {
+            Ok(view
+                .records()
+                .into_iter()
+                .filter(|(key, _)| range.is_none_or(|range| range.contains(key)))
+                .map(|(key, value)| Record {
+                    key: key.clone(),
+                    primary_key: key.clone(),
+                    value: value.clone(),
+                })
+                .collect::<Vec<_>>())
+        })?;
+        if direction == Direction::Prev {
+            records.reverse();
+        }
+        Ok(records)
+    }
+}
+
+pub(crate) struct Index {
+    tx: Arc<Tx>,
+    store: String,
+    key_path: String,
+}
+
+impl Index {
+    pub(crate) async fn get(&self, key: &Key) -> Result<Option<Value>> {
+        let records = self.records(Some(&KeyRange::only(key.clone())), Direction::Next).await?;
+        Ok(records.into_iter().next().map(|record| record.value))
+    }
+
+    pub(crate) async fn get_all(&self, range: Option<&KeyRange>) -> Result<Vec<Value>> {
+        let records = self.records(range, Direction::Next).await?;
+        Ok(records.into_iter().map(|record| record.value).collect())
+    }
+
+    pub(crate) async fn count(&self, range: Option<&KeyRange>) -> Result<usize> {
+        Ok(self.records(range, Direction::Next).await?.len())
+    }
+
+    pub(crate) async fn open_cursor(
+        &self,
+        range: Option<&KeyRange>,
+        direction: Direction,
+    ) -> Result<Cursor> {
+        Ok(Cursor {
+            records: self.records(range, direction).await?.into_iter(),
+        })
+    }
+
+    /// Returns the records with a valid key at the key path ordered by that
+    /// key and their primary key.
+    async fn records(&self, range: Option<&KeyRange>, direction: Direction) -> Result<Vec<Record>> {
+        self.tx.started().await;
+        let mut records = self.tx.read(&self.store, |view| {
+            Ok(view
+                .records()
+                .into_iter()
+                .filter_map(|(primary_key, value)| {
+                    let key = index_key(value, &self.key_path)?;
+                    Some(Record {
+                        key,
+                        primary_key: primary_key.clone(),
+                        value: value.clone(),
+                    })
+                })
+                .filter(|record| range.is_none_or(|range| range.contains(&record.key)))
+                .collect::<Vec<_>>())
+        })?;
+        records.sort_by(|a, b| (&a.key, &a.primary_key).cmp(&(&b.key, &b.primary_key)));
+        if direction == Direction::Prev {
+            records.reverse();
+        }
+        Ok(records)
+    }
+}
+
+pub(crate) struct Cursor {
+    records: std::vec::IntoIter<Record>,
+}
+
+impl Cursor {
+    pub(crate) async fn next(&mut self) -> Result<Option<Record>> {
+        Ok(self.records.next())
+    }
+}
+
+fn constraint(message: String) -> failure::Error {
+    ConstraintError { message }.into()
+}
+
+/// Returns the value at a key path like `user.id`. An empty path refers to
+/// the value itself.
+fn lookup<'a>(value: &'a Value, key_path: &str) -> Option<&'a Value> {
+    if key_path.is_empty() {
+        return Some(value);
+    }
+    key_path
+        .split('.')
+        .try_fold(value, |value, field| value.as_object()?.get(field))
+}
+
+fn index_key(value: &Value, key_path: &str) -> Option<Key> {
+    lookup(value, key_path).and_then(Key::from_value)
+}
+
+/// Sets the value at a key path to a generated key, creating missing
+/// objects.
+fn inject(value: &mut Value, key_path: &str, key: &Key) -> Result<()> {
+    let mut fields = key_path.split('.').peekable();
+    let mut value = value;
+    while let Some(field) = fields.next() {
+        let object = value
+            .as_object_mut()
+            .ok_or_else(|| format_err!("Can't set the key at key path '{}'", key_path))?;
+        if fields.peek().is_none() {
+            object.insert(field.to_string(), key.to_value());
+            return Ok(());
+        }
+        value = object
+            .entry(field.to_string())
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+    bail!("Can't set the key at key path '{}'", key_path)
+}
+
+/// Writes the database to a temporary file and moves it to the path, so the
+/// file is never partially written.
+fn persist(path: &Path, state: &State) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, encode(state).to_string())?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+fn encode(state: &State) -> Value {
+    let stores = state
+        .stores
+        .iter()
+        .map(|(name, store)| {
+            let indexes = store
+                .indexes
+                .iter()
+                .map(|(name, index)| {
+                    let index = json!({ "key_path": index.key_path, "unique": index.unique });
+                    (name.clone(), index)
+                })
+                .collect::<Map<_, _>>();
+            let records = store
+                .records
+                .iter()
+                .map(|(key, value)| json!([encode_key(key), value]))
+                .collect::<Vec<_>>();
+            let store = json!({
+                "key_path": store.key_path,
+                "auto_increment": store.auto_increment,
+                "next_key": store.next_key,
+                "indexes": indexes,
+                "records": records,
+            });
+            (name.clone(), store)
+        })
+        .collect::<Map<_, _>>();
+    json!({ "version": state.version, "stores": stores })
+}
+
+fn decode(contents: &str) -> Result<State> {
+    let invalid = || format_err!("Invalid database file");
+    let value: Value = serde_json::from_str(contents)?;
+    let mut state = State {
+        version: value["version"].as_u64().ok_or_else(invalid)? as u32,
+        stores: BTreeMap::new(),
+    };
+    for (name, store) in value["stores"].as_object().ok_or_else(invalid)? {
+        let mut indexes = BTreeMap::new();
+        for (name, index) in store["indexes"].as_object().ok_or_else(invalid)? {
+            let index = IndexState {
+                key_path: index["key_path"].as_str().ok_or_else(invalid)?.to_string(),
+                unique: index["unique"].as_bool().ok_or_else(invalid)?,
+            };
+            indexes.insert(name.clone(), index);
+        }
+        let mut records = BTreeMap::new();
+        for record in store["records"].as_array().ok_or_else(invalid)? {
+            let key = decode_key(&record[0]).ok_or_else(invalid)?;
+            records.insert(key, record[1].clone());
+        }
+        let store = StoreState {
+            key_path: store["key_path"].as_str().map(str::to_string),
+            auto_increment: store["auto_increment"].as_bool().ok_or_else(invalid)?,
+            next_key: store["next_key"].as_f64().ok_or_else(invalid)?,
+            indexes,
+            records,
+        };
+        state.stores.insert(name.clone(), store);
+    }
+    Ok(state)
+}
+
+/// Encodes a key as JSON, wrapping binary keys in an object to tell them
+/// apart from arrays.
+fn encode_key(key: &Key) -> Value {
+    match key {
+        Key::Binary(_) => json!({ "binary": key.to_value() }),
+        Key::Array(keys) => Value::Array(keys.iter().map(encode_key).collect()),
+        key => key.to_value(),
+    }
+}
+
+fn decode_key(value: &Value) -> Option<Key> {
+    match value {
+        Value::Object(object) => object
+            .get("binary")?
+            .as_array()?
+            .iter()
+            .map(|byte| byte.as_u64().map(|byte| byte as u8))
+            .collect::<Option<Vec<_>>>()
+            .map(Key::Binary),
+        Value::Array(items) => items
+            .iter()
+            .map(decode_key)
+            .collect::<Option<Vec<_>>>()
+            .map(Key::Array),
+        value => Key::from_value(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::idb::{Database, Upgrade};
+    use futures_executor::block_on;
+    use std::env;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = env::temp_dir()
+            .join(format!("libweb-{}", std::process::id()))
+            .join(format!("{}.json", name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn upgrade(upgrade: &mut Upgrade) -> Result<()> {
+        let options = StoreOptions {
+            key_path: Some("id".into()),
+            auto_increment: true,
+        };
+        upgrade.create_store("notes", &options)?;
+        upgrade.create_index("notes", "by_title", "title", true)?;
+        upgrade.create_index("notes", "by_tag", "tag", false)?;
+        upgrade.create_store("plain", &StoreOptions::default())
+    }
+
+    fn open(path: &Path) -> Database {
+        block_on(Database::open_at(path, 1, upgrade)).unwrap()
+    }
+
+    #[test]
+    fn test_put_get() {
+        let db = open(&temp_path("put_get"));
+        assert_eq!(db.version(), 1);
+        assert_eq!(db.name(), "put_get");
+        assert_eq!(db.store_names(), vec!["notes", "plain"]);
+        block_on(async {
+            let tx = db.transaction(&["notes", "plain"], Mode::ReadWrite).unwrap();
+            let notes = tx.store("notes").unwrap();
+            let key = notes.put(&json!({ "title": "a" }), None).await.unwrap();
+            assert_eq!(key, Key::from(1));
+            let key = notes.put(&json!({ "id": 5, "title": "b" }), None).await.unwrap();
+            assert_eq!(key, Key::from(5));
+            let key = notes.put(&json!({ "title": "c" }), None).await.unwrap();
+            assert_eq!(key, Key::from(6));
+            assert_eq!(
+                notes.get(&Key::from(1)).await.unwrap(),
+                Some(json!({ "id": 1, "title": "a" }))
+            );
+            assert!(notes.put(&json!({}), Some(&Key::from(1))).await.is_err());
+
+            let plain = tx.store("plain").unwrap();
+            assert!(plain.put(&json!("value"), None).await.is_err());
+            plain.put(&json!("value"), Some(&Key::from("key"))).await.unwrap();
+            assert_eq!(plain.get(&Key::from("key")).await.unwrap(), Some(json!("value")));
+            assert_eq!(plain.get(&Key::from("missing")).await.unwrap(), None);
+            tx.commit().await.unwrap();
+        });
+    }
+
+    #[test]
+    fn test_constraints() {
+        let db = open(&temp_path("constraints"));
+        block_on(async {
+            let tx = db.transaction(&["notes"], Mode::ReadWrite).unwrap();
+            let notes = tx.store("notes").unwrap();
+            notes.add(&json!({ "id": 1, "title": "a" }), None).await.unwrap();
+            tx.commit().await.unwrap();
+
+            let tx = db.transaction(&["notes"], Mode::ReadWrite).unwrap();
+            let notes = tx.store("notes").unwrap();
+            notes.put(&json!({ "id": 3, "title": "c" }), None).await.unwrap();
+            let err = notes.add(&json!({ "id": 1, "title": "b" }), None).await.unwrap_err();
+            assert!(err.downcast::<ConstraintError>().is_ok());
+            // The constraint error aborted the transaction.
+            assert!(notes.get(&Key::from(1)).await.is_err());
+            assert!(tx.commit().await.is_err());
+
+            let tx = db.transaction(&["notes"], Mode::ReadWrite).unwrap();
+            let notes = tx.store("notes").unwrap();
+            assert_eq!(notes.get(&Key::from(3)).await.unwrap(), None);
+            let err = notes.put(&json!({ "id": 2, "title": "a" }), None).await.unwrap_err();
+            assert!(err.downcast::<ConstraintError>().is_ok());
+
+            let tx = db.transaction(&["notes"], Mode::ReadWrite).unwrap();
+            let notes = tx.store("notes").unwrap();
+            // Replacing a record keeps its own index key.
+            notes.put(&json!({ "id": 1, "title": "a", "tag": "x" }), None).await.unwrap();
+            tx.commit().await.unwrap();
+        });
+    }
+
+    #[test]
+    fn test_ranges_and_cursors() {
+        let db = open(&temp_path("cursors"));
+        block_on(async {
+            let tx = db.transaction(&["notes"], Mode::ReadWrite).unwrap();
+            let notes = tx.store("notes").unwrap();
+            for (title, tag) in &[("d", "x"), ("b", "y"), ("c", "x"), ("a", "y")] {
+                notes.put(&json!({ "title": title, "tag": tag }), None).await.unwrap();
+            }
+            notes.put(&json!({ "title": "untagged" }), None).await.unwrap();
+            assert_eq!(notes.count(None).await.unwrap(), 5);
+            let range = KeyRange::new(std::ops::Bound::Excluded(Key::from(1)), std::ops::Bound::Included(Key::from(3)));
+            assert_eq!(notes.keys(Some(&range)).await.unwrap(), vec![Key::from(2), Key::from(3)]);
+
+            let mut cursor = notes.open_cursor(None, Direction::Prev).await.unwrap();
+            assert_eq!(cursor.next().await.unwrap().unwrap().key, Key::from(5));
+
+            let by_title = notes.index("by_title").unwrap();
+            assert_eq!(by_title.get(&Key::from("c")).await.unwrap().unwrap()["id"], json!(3));
+            let titles = by_title.get_all(Some(&KeyRange::at_most("c"))).await.unwrap();
+            let titles = titles.iter().map(|note| note["title"].clone()).collect::<Vec<_>>();
+            assert_eq!(titles, vec![json!("a"), json!("b"), json!("c")]);
+
+            let by_tag = notes.index("by_tag").unwrap();
+            assert_eq!(by_tag.count(None).await.unwrap(), 4);
+            let mut cursor = by_tag.open_cursor(Some(&KeyRange::only("x")), Direction::Next).await.unwrap();
+            let mut keys = Vec::new();
+            while let Some(record) = cursor.next().await.unwrap() {
+                assert_eq!(record.key, Key::from("x"));
+                keys.push(record.primary_key);
+            }
+            assert_eq!(keys, vec![Key::from(1), Key::from(3)]);
+            assert!(notes.index("missing").is_err());
+        });
+    }
+
+    #[test]
+    fn test_abort() {
+        let db = open(&temp_path("abort"));
+        block_on(async {
+            let tx = db.transaction(&["plain"], Mode::ReadWrite).unwrap();
+            let plain = tx.store("plain").unwrap();
+            plain.put(&json!(1), Some(&Key::from("a"))).await.unwrap();
+            tx.commit().await.unwrap();
+            assert!(plain.put(&json!(2), Some(&Key::from("b"))).await.is_err());
+
+            let tx = db.transaction(&["plain"], Mode::ReadWrite).unwrap();
+            let plain = tx.store("plain").unwrap();
+            plain.put(&json!(2), Some(&Key::from("a"))).await.unwrap();
+            plain.put(&json!(3), Some(&Key::from("b"))).await.unwrap();
+            plain.clear().await.unwrap();
+            tx.abort().unwrap();
+
+            let tx = db.transaction(&["plain"], Mode::ReadOnly).unwrap();
+            let plain = tx.store("plain").unwrap();
+            assert_eq!(plain.get_all(None).await.unwrap(), vec![json!(1)]);
+            assert!(plain.delete(&Key::from("a")).await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_isolation() {
+        let path = temp_path("isolation");
+        let db = open(&path);
+        block_on(async {
+            // Transactions over different stores run concurrently.
+            let plain_tx = db.transaction(&["plain"], Mode::ReadWrite).unwrap();
+            let notes_tx = db.transaction(&["notes"], Mode::ReadWrite).unwrap();
+            let plain = plain_tx.store("plain").unwrap();
+            let notes = notes_tx.store("notes").unwrap();
+            notes.put(&json!({ "title": "a" }), None).await.unwrap();
+            plain.put(&json!(1), Some(&Key::from("a"))).await.unwrap();
+            plain.clear().await.unwrap();
+            plain.put(&json!(2), Some(&Key::from("b"))).await.unwrap();
+            assert_eq!(plain.keys(None).await.unwrap(), vec![Key::from("b")]);
+            assert_eq!(notes.count(None).await.unwrap(), 1);
+            plain_tx.commit().await.unwrap();
+
+            // Only the changes of the committed transaction are persisted.
+            let state = decode(&fs::read_to_string(&path).unwrap()).unwrap();
+            assert_eq!(state.stores["plain"].records.len(), 1);
+            assert!(state.stores["notes"].records.is_empty());
+
+            notes_tx.abort().unwrap();
+            let tx = db.transaction(&["notes", "plain"], Mode::ReadOnly).unwrap();
+            assert_eq!(tx.store("notes").unwrap().count(None).await.unwrap(), 0);
+            assert_eq!(tx.store("plain").unwrap().get_all(None).await.unwrap(), vec![json!(2)]);
+            tx.commit().await.unwrap();
+            // The key generator is reverted with the aborted transaction.
+            let tx = db.transaction(&["notes"], Mode::ReadWrite).unwrap();
+            let notes = tx.store("notes").unwrap();
+            assert_eq!(notes.put(&json!({ "title": "b" }), None).await.unwrap(), Key::from(1));
+        });
+    }
+
+    #[test]
+    fn test_overlapping_transactions() {
+        let db = open(&temp_path("overlapping"));
+        block_on(async {
+            let first = db.transaction(&["plain"], Mode::ReadWrite).unwrap();
+            let reader = db.transaction(&["notes"], Mode::ReadOnly).unwrap();
+            let second = db.transaction(&["plain", "notes"], Mode::ReadWrite).unwrap();
+            let plain = first.store("plain").unwrap();
+            plain.put(&json!(1), Some(&Key::from("a"))).await.unwrap();
+            assert_eq!(reader.store("notes").unwrap().count(None).await.unwrap(), 0);
+
+            // The second transaction commits the idle earlier ones instead of
+            // waiting for them.
+            let second_plain = second.store("plain").unwrap();
+            assert_eq!(second_plain.get(&Key::from("a")).await.unwrap(), Some(json!(1)));
+            assert!(plain.put(&json!(2), Some(&Key::from("b"))).await.is_err());
+            assert!(first.abort().is_err());
+            assert!(reader.store("notes").unwrap().count(None).await.is_err());
+            second_plain.put(&json!(3), Some(&Key::from("c"))).await.unwrap();
+            second.commit().await.unwrap();
+
+            let tx = db.transaction(&["plain"], Mode::ReadOnly).unwrap();
+            let plain = tx.store("plain").unwrap();
+            assert_eq!(plain.keys(None).await.unwrap(), vec![Key::from("a"), Key::from("c")]);
+        });
+    }
+
+    #[test]
+    fn test_overlapping_transactions_across_threads() {
+        let db = open(&temp_path("overlapping_threads"));
+        let first = db.transaction(&["plain"], Mode::ReadWrite).unwrap();
+        let second = db.transaction(&["plain"], Mode::ReadWrite).unwrap();
+        let plain = first.store("plain").unwrap();
+        let handle = std::thread::spawn(move || {
+            block_on(async {
+                let plain = second.store("plain").unwrap();
+                plain.put(&json!(2), Some(&Key::from("b"))).await.unwrap();
+                second.commit().await.unwrap();
+            })
+        });
+        // Depending on the timing the put runs before or after the first
+        // transaction was committed by the second one, but never in between.
+        let put = block_on(plain.put(&json!(1), Some(&Key::from("a"))));
+        handle.join().unwrap();
+        drop(first);
+        block_on(async {
+            let tx = db.transaction(&["plain"], Mode::ReadOnly).unwrap();
+            let keys = tx.store("plain").unwrap().keys(None).await.unwrap();
+            match put {
+                Ok(_) => assert_eq!(keys, vec![Key::from("a"), Key::from("b")]),
+                Err(_) => assert_eq!(keys, vec![Key::from("b")]),
+            }
+        });
+    }
+
+    #[test]
+    fn test_delete() {
+        let path = temp_path("delete");
+        let db = open(&path);
+        let tx = db.transaction(&["plain"], Mode::ReadWrite).unwrap();
+        drop(db);
+        // The open transaction still uses the database.
+        assert!(super::Database::delete_at(&path, "delete").is_err());
+        drop(tx);
+        assert!(path.exists());
+        super::Database::delete_at(&path, "delete").unwrap();
+        assert!(!path.exists());
+        assert!(registry().get(&path).is_none());
+        // Deleting a missing database succeeds.
+        super::Database::delete_at(&path, "delete").unwrap();
+        let db = open(&path);
+        assert_eq!(db.version(), 1);
+    }
+
+    #[test]
+    fn test_persist() {
+        let path = temp_path("persist");
+        let db = open(&path);
+        block_on(async {
+            // The transaction commits when its last handle is dropped.
+            let store = db.transaction(&["plain"], Mode::ReadWrite).unwrap().store("plain").unwrap();
+            store.put(&json!({ "a": [1] }), Some(&Key::Binary(vec![1, 2]))).await.unwrap();
+            store.put(&json!(null), Some(&Key::Array(vec![Key::from(1), Key::from("a")]))).await.unwrap();
+        });
+        let state = decode(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(state, *db.inner.shared.lock());
+        assert_eq!(state.version, 1);
+        assert_eq!(state.stores["notes"].indexes.len(), 2);
+        assert_eq!(state.stores["plain"].records[&Key::Binary(vec![1, 2])], json!({ "a": [1] }));
+    }
+
+    #[test]
+    fn test_versions() {
+        let path = temp_path("versions");
+        drop(open(&path));
+        let err = block_on(Database::open_at(&path, 2, |upgrade| {
+            assert_eq!(upgrade.old_version(), 1);
+            assert_eq!(upgrade.new_version(), 2);
+            upgrade.delete_store("plain")?;
+            upgrade.create_store("notes", &StoreOptions::default())
+        }));
+        assert!(err.unwrap_err().downcast::<ConstraintError>().is_ok());
+        // The failed upgrade is discarded.
+        let db = block_on(Database::open_at(&path, 1, |_| Ok(()))).unwrap();
+        assert_eq!(db.store_names(), vec!["notes", "plain"]);
+        assert!(block_on(Database::open_at(&path, 0, |_| Ok(()))).is_err());
+        let db = block_on(Database::open_at(&path, 3, |upgrade| upgrade.delete_index("notes", "by_tag"))).unwrap();
+        assert_eq!(db.version(), 3);
+        assert!(block_on(Database::open_at(&path, 2, |_| Ok(()))).is_err());
+    }
+
+    #[test]
+    fn test_key_path() {
+        let mut value = json!({ "user": {} });
+        inject(&mut value, "user.id", &Key::from(1)).unwrap();
+        assert_eq!(lookup(&value, "user.id"), Some(&json!(1)));
+        assert_eq!(lookup(&value, "user.name"), None);
+        assert_eq!(lookup(&value, ""), Some(&value));
+        assert!(inject(&mut json!(1), "id", &Key::from(1)).is_err());
+    }
+
+    #[test]
+    fn test_path() {
+        let path = path("my db").unwrap();
+        assert!(path.ends_with("idb/my%20db.json"));
+    }
+}
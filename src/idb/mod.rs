@@ -0,0 +1,640 @@
+//! The IndexedDB API
+//!
+//! Records are JSON values stored in object stores under a key. In the
+//! browser databases are stored in IndexedDB, on desktop every database is
+//! stored in a file in the data directory of the user, see `Database::open`.
+//!
+//! This module is only available with the `serde` feature.
+//!
+//! # Transactions
+//!
+//! Like in IndexedDB a transaction runs after the transactions created before
+//! it whose scope overlaps with its own, unless both are read-only. Changes
+//! are only visible to other transactions once they are committed.
+//!
+//! IndexedDB commits a transaction once it has no pending requests. On desktop
+//! the first request of a transaction commits the earlier transactions it has
+//! to run after instead, so their later requests fail as if they had been
+//! committed when they became idle.
+//!
+//! # Desktop storage
+//!
+//! The desktop implementation is meant for small databases like settings or
+//! caches. Every database is kept in memory while it's open, and the whole
+//! database is rewritten as a JSON file when a read-write transaction
+//! commits, so the cost of a commit grows with the size of the database.
+//! Files are replaced atomically, so a crash never leaves a partially
+//! written database.
+//!
+//! ```no_run
+//! use libweb::idb::{Database, Mode, StoreOptions};
+//! use serde_json::json;
+//!
+//! # async fn example() -> libweb::Result<()> {
+//! let db = Database::open("app", 1, |upgrade| {
+//!     let options = StoreOptions {
+//!         key_path: Some("id".into()),
+//!         auto_increment: true,
+//!     };
+//!     upgrade.create_store("notes", &options)?;
+//!     upgrade.create_index("notes", "by_title", "title", false)
+//! })
+//! .await?;
+//!
+//! let tx = db.transaction(&["notes"], Mode::ReadWrite)?;
+//! let key = tx.store("notes")?.put(&json!({ "title": "hello" }), None).await?;
+//! tx.commit().await?;
+//! # Ok(())
+//! # }
+//! ```
+use crate::Result;
+use failure::Fail;
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Bound;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+
+#[cfg(target_arch = "wasm32")]
+mod browser;
+#[cfg(not(target_arch = "wasm32"))]
+mod desktop;
+
+#[cfg(target_arch = "wasm32")]
+use self::browser as backend;
+#[cfg(not(target_arch = "wasm32"))]
+use self::desktop as backend;
+
+pub use serde_json::Value;
+
+/// The largest integer a number key can represent exactly.
+const MAX_SAFE_INTEGER: f64 = 9007199254740991.0;
+
+/// The mode of a transaction.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Mode {
+    /// The transaction can only read records.
+    ReadOnly,
+    /// The transaction can read and write records.
+    ReadWrite,
+}
+
+/// The direction records are iterated in by a cursor.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum Direction {
+    /// In ascending order of keys.
+    #[default]
+    Next,
+    /// In descending order of keys.
+    Prev,
+}
+
+/// The options of an object store.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct StoreOptions {
+    /// The path of the key in the values, like `id` or `user.id`. If `None`
+    /// keys are passed separately from values.
+    pub key_path: Option<String>,
+    /// Whether missing keys are generated from an increasing counter.
+    pub auto_increment: bool,
+}
+
+/// The key of a record.
+///
+/// Keys of different types are ordered like in IndexedDB: numbers before
+/// strings before binaries before arrays.
+#[derive(Debug, Clone)]
+pub enum Key {
+    /// A number, which must not be NaN.
+    Number(f64),
+    /// A string.
+    String(String),
+    /// Binary data.
+    Binary(Vec<u8>),
+    /// An array of keys.
+    Array(Vec<Key>),
+}
+
+impl Key {
+    /// Converts a JSON value to a key. Returns `None` for values that aren't
+    /// valid keys like objects.
+    pub fn from_value(value: &Value) -> Option<Key> {
+        match value {
+            Value::Number(number) => number.as_f64().map(Key::Number),
+            Value::String(string) => Some(Key::String(string.clone())),
+            Value::Array(items) => items
+                .iter()
+                .map(Key::from_value)
+                .collect::<Option<Vec<_>>>()
+                .map(Key::Array),
+            _ => None,
+        }
+    }
+
+    /// Converts the key to a JSON value. Integral numbers are converted to
+    /// JSON integers and binary keys to arrays of bytes.
+    pub fn to_value(&self) -> Value {
+        match self {
+            // Integral numbers are converted like `JSON.stringify` would.
+            Key::Number(number) if number.fract() == 0.0 && number.abs() <= MAX_SAFE_INTEGER => {
+                Value::from(*number as i64)
+            }
+            Key::Number(number) => serde_json::Number::from_f64(*number)
+                .map(Value::Number)
+                .unwrap_or(Value::Null),
+            Key::String(string) => Value::String(string.clone()),
+            Key::Binary(bytes) => Value::Array(bytes.iter().map(|byte| Value::from(*byte)).collect()),
+            Key::Array(keys) => Value::Array(keys.iter().map(Key::to_value).collect()),
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            Key::Number(_) => 0,
+            Key::String(_) => 1,
+            Key::Binary(_) => 2,
+            Key::Array(_) => 3,
+        }
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Key) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Key {}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Key) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Key {
+    fn cmp(&self, other: &Key) -> Ordering {
+        match (self, other) {
+            (Key::Number(a), Key::Number(b)) => a.total_cmp(b),
+            (Key::String(a), Key::String(b)) => a.cmp(b),
+            (Key::Binary(a), Key::Binary(b)) => a.cmp(b),
+            (Key::Array(a), Key::Array(b)) => a.cmp(b),
+            (a, b) => a.rank().cmp(&b.rank()),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Key {
+    fn from(string: &'a str) -> Self {
+        Key::String(string.to_string())
+    }
+}
+
+impl From<String> for Key {
+    fn from(string: String) -> Self {
+        Key::String(string)
+    }
+}
+
+macro_rules! impl_from_number {
+    ($($ty: ty),*) => {
+        $(
+            impl From<$ty> for Key {
+                fn from(number: $ty) -> Self {
+                    Key::Number(number as f64)
+                }
+            }
+        )*
+    }
+}
+
+impl_from_number!(i32, i64, u32, u64, usize, f64);
+
+/// A range of keys.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct KeyRange {
+    /// The lower bound.
+    pub lower: Bound<Key>,
+    /// The upper bound.
+    pub upper: Bound<Key>,
+}
+
+impl KeyRange {
+    /// Creates a range from its bounds.
+    pub fn new(lower: Bound<Key>, upper: Bound<Key>) -> Self {
+        KeyRange { lower, upper }
+    }
+
+    /// Creates a range containing a single key.
+    pub fn only<K: Into<Key>>(key: K) -> Self {
+        let key = key.into();
+        KeyRange::new(Bound::Included(key.clone()), Bound::Included(key))
+    }
+
+    /// Creates a range containing the keys greater than or equal to `key`.
+    pub fn at_least<K: Into<Key>>(key: K) -> Self {
+        KeyRange::new(Bound::Included(key.into()), Bound::Unbounded)
+    }
+
+    /// Creates a range containing the keys less than or equal to `key`.
+    pub fn at_most<K: Into<Key>>(key: K) -> Self {
+        KeyRange::new(Bound::Unbounded, Bound::Included(key.into()))
+    }
+
+    /// Returns whether the range contains the key.
+    pub fn contains(&self, key: &Key) -> bool {
+        let lower = match &self.lower {
+            Bound::Included(lower) => key >= lower,
+            Bound::Excluded(lower) => key > lower,
+            Bound::Unbounded => true,
+        };
+        let upper = match &self.upper {
+            Bound::Included(upper) => key <= upper,
+            Bound::Excluded(upper) => key < upper,
+            Bound::Unbounded => true,
+        };
+        lower && upper
+    }
+}
+
+/// A record returned by a cursor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    /// The key of the record in the store or index the cursor iterates over.
+    pub key: Key,
+    /// The key of the record in its store.
+    pub primary_key: Key,
+    /// The value.
+    pub value: Value,
+}
+
+/// Error returned when a write violates a constraint, like adding an existing
+/// key or a duplicate key to a unique index.
+#[derive(Debug)]
+pub struct ConstraintError {
+    /// The description of the violation.
+    pub message: String,
+}
+
+impl fmt::Display for ConstraintError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Constraint error: {}", self.message)
+    }
+}
+
+impl Fail for ConstraintError {}
+
+/// A connection to a database.
+pub struct Database {
+    inner: backend::Database,
+}
+
+impl Database {
+    /// Opens a database, creating it if it doesn't exist.
+    ///
+    /// If the version of the database is lower than `version`, `upgrade` is
+    /// called to create or change its object stores and indexes. Opening a
+    /// database with a lower version than its current version fails.
+    ///
+    /// On desktop the database is stored in the data directory of the user,
+    /// in a directory named after the executable.
+    pub async fn open<F>(name: &str, version: u32, upgrade: F) -> Result<Self>
+    where
+        F: FnOnce(&mut Upgrade) -> Result<()> + 'static,
+    {
+        Ok(Database {
+            inner: backend::Database::open(name, version, upgrade).await?,
+        })
+    }
+
+    /// Opens a database stored in a file.
+    ///
+    /// Only available on desktop. Databases opened with the same path share
+    /// their records.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn open_at<P, F>(path: P, version: u32, upgrade: F) -> Result<Self>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(&mut Upgrade) -> Result<()> + 'static,
+    {
+        Ok(Database {
+            inner: backend::Database::open_at(path.as_ref(), version, upgrade)?,
+        })
+    }
+
+    /// Deletes a database.
+    ///
+    /// In the browser the deletion waits until all connections to the
+    /// database are closed, on desktop it fails while the database is open.
+    pub async fn delete(name: &str) -> Result<()> {
+        backend::Database::delete(name).await
+    }
+
+    /// Returns the name.
+    pub fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    /// Returns the version.
+    pub fn version(&self) -> u32 {
+        self.inner.version()
+    }
+
+    /// Returns the names of the object stores.
+    pub fn store_names(&self) -> Vec<String> {
+        self.inner.store_names()
+    }
+
+    /// Starts a transaction over some object stores.
+    ///
+    /// Transactions commit automatically when they are dropped, use `abort`
+    /// to discard their changes.
+    pub fn transaction(&self, stores: &[&str], mode: Mode) -> Result<Transaction> {
+        Ok(Transaction {
+            inner: self.inner.transaction(stores, mode)?,
+        })
+    }
+}
+
+impl fmt::Debug for Database {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Database")
+            .field("name", &self.name())
+            .field("version", &self.version())
+            .finish()
+    }
+}
+
+/// Changes the object stores and indexes of a database while it is opened.
+pub struct Upgrade<'a> {
+    inner: backend::Upgrade<'a>,
+}
+
+impl Upgrade<'_> {
+    /// Returns the version before the upgrade, 0 for new databases.
+    pub fn old_version(&self) -> u32 {
+        self.inner.old_version()
+    }
+
+    /// Returns the version after the upgrade.
+    pub fn new_version(&self) -> u32 {
+        self.inner.new_version()
+    }
+
+    /// Returns the names of the object stores.
+    pub fn store_names(&self) -> Vec<String> {
+        self.inner.store_names()
+    }
+
+    /// Creates an object store.
+    pub fn create_store(&mut self, name: &str, options: &StoreOptions) -> Result<()> {
+        self.inner.create_store(name, options)
+    }
+
+    /// Deletes an object store.
+    pub fn delete_store(&mut self, name: &str) -> Result<()> {
+        self.inner.delete_store(name)
+    }
+
+    /// Creates an index of an object store over the values at `key_path`.
+    pub fn create_index(
+        &mut self,
+        store: &str,
+        name: &str,
+        key_path: &str,
+        unique: bool,
+    ) -> Result<()> {
+        self.inner.create_index(store, name, key_path, unique)
+    }
+
+    /// Deletes an index of an object store.
+    pub fn delete_index(&mut self, store: &str, name: &str) -> Result<()> {
+        self.inner.delete_index(store, name)
+    }
+}
+
+impl fmt::Debug for Upgrade<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Upgrade")
+            .field("old_version", &self.old_version())
+            .field("new_version", &self.new_version())
+            .finish()
+    }
+}
+
+/// A transaction over some object stores of a database.
+pub struct Transaction {
+    inner: backend::Transaction,
+}
+
+impl Transaction {
+    /// Returns an object store of the transaction.
+    pub fn store(&self, name: &str) -> Result<ObjectStore> {
+        Ok(ObjectStore {
+            inner: self.inner.store(name)?,
+        })
+    }
+
+    /// Commits the transaction and waits until it's complete.
+    pub async fn commit(self) -> Result<()> {
+        self.inner.commit().await
+    }
+
+    /// Aborts the transaction, discarding its changes.
+    pub fn abort(self) -> Result<()> {
+        self.inner.abort()
+    }
+}
+
+impl fmt::Debug for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Transaction")
+    }
+}
+
+/// An object store of a transaction.
+pub struct ObjectStore {
+    inner: backend::ObjectStore,
+}
+
+impl ObjectStore {
+    /// Inserts or replaces a record, returning its key.
+    ///
+    /// `key` must be `None` if the store has a key path.
+    pub async fn put(&self, value: &Value, key: Option<&Key>) -> Result<Key> {
+        self.inner.put(value, key, false).await
+    }
+
+    /// Inserts a record, returning its key. Fails with `ConstraintError` if
+    /// the key exists.
+    pub async fn add(&self, value: &Value, key: Option<&Key>) -> Result<Key> {
+        self.inner.put(value, key, true).await
+    }
+
+    /// Returns the value of a key.
+    pub async fn get(&self, key: &Key) -> Result<Option<Value>> {
+        self.inner.get(key).await
+    }
+
+    /// Returns the values of the keys in a range, or of all keys.
+    pub async fn get_all(&self, range: Option<&KeyRange>) -> Result<Vec<Value>> {
+        self.inner.get_all(range).await
+    }
+
+    /// Returns the keys in a range, or all keys.
+    pub async fn keys(&self, range: Option<&KeyRange>) -> Result<Vec<Key>> {
+        self.inner.keys(range).await
+    }
+
+    /// Returns the number of keys in a range, or of all keys.
+    pub async fn count(&self, range: Option<&KeyRange>) -> Result<usize> {
+        self.inner.count(range).await
+    }
+
+    /// Deletes a record.
+    pub async fn delete(&self, key: &Key) -> Result<()> {
+        self.inner.delete(key).await
+    }
+
+    /// Deletes all records.
+    pub async fn clear(&self) -> Result<()> {
+        self.inner.clear().await
+    }
+
+    /// Opens a cursor over the records in a range, or over all records.
+    pub async fn open_cursor(
+        &self,
+        range: Option<&KeyRange>,
+        direction: Direction,
+    ) -> Result<Cursor> {
+        Ok(Cursor {
+            inner: self.inner.open_cursor(range, direction).await?,
+        })
+    }
+
+    /// Returns an index of the store.
+    pub fn index(&self, name: &str) -> Result<Index> {
+        Ok(Index {
+            inner: self.inner.index(name)?,
+        })
+    }
+}
+
+impl fmt::Debug for ObjectStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ObjectStore")
+    }
+}
+
+/// An index of an object store, which looks up records by the values at its
+/// key path.
+pub struct Index {
+    inner: backend::Index,
+}
+
+impl Index {
+    /// Returns the value of the first record with the key.
+    pub async fn get(&self, key: &Key) -> Result<Option<Value>> {
+        self.inner.get(key).await
+    }
+
+    /// Returns the values of the records with keys in a range, or of all
+    /// records in the index.
+    pub async fn get_all(&self, range: Option<&KeyRange>) -> Result<Vec<Value>> {
+        self.inner.get_all(range).await
+    }
+
+    /// Returns the number of records with keys in a range, or of all records
+    /// in the index.
+    pub async fn count(&self, range: Option<&KeyRange>) -> Result<usize> {
+        self.inner.count(range).await
+    }
+
+    /// Opens a cursor over the records with keys in a range, or over all
+    /// records in the index.
+    pub async fn open_cursor(
+        &self,
+        range: Option<&KeyRange>,
+        direction: Direction,
+    ) -> Result<Cursor> {
+        Ok(Cursor {
+            inner: self.inner.open_cursor(range, direction).await?,
+        })
+    }
+}
+
+impl fmt::Debug for Index {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Index")
+    }
+}
+
+/// Iterates over records of an object store or index.
+pub struct Cursor {
+    inner: backend::Cursor,
+}
+
+impl Cursor {
+    /// Returns the next record, or `None` when all records were returned.
+    pub async fn next(&mut self) -> Result<Option<Record>> {
+        self.inner.next().await
+    }
+}
+
+impl fmt::Debug for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Cursor")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_key_order() {
+        let mut keys = vec![
+            Key::Array(vec![Key::from(1)]),
+            Key::Binary(vec![0]),
+            Key::from("b"),
+            Key::from("a"),
+            Key::from(2),
+            Key::from(-1.5),
+        ];
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec![
+                Key::from(-1.5),
+                Key::from(2),
+                Key::from("a"),
+                Key::from("b"),
+                Key::Binary(vec![0]),
+                Key::Array(vec![Key::from(1)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_key_value() {
+        let value = json!([1, "a", [2]]);
+        let key = Key::from_value(&value).unwrap();
+        assert_eq!(key.to_value(), json!([1, "a", [2]]));
+        assert_eq!(Key::from(1.5).to_value(), json!(1.5));
+        assert_eq!(Key::from(1e300).to_value(), json!(1e300));
+        assert_eq!(Key::from_value(&json!({ "a": 1 })), None);
+        assert_eq!(Key::from_value(&json!([true])), None);
+    }
+
+    #[test]
+    fn test_key_range() {
+        let range = KeyRange::new(Bound::Excluded(Key::from(1)), Bound::Included(Key::from(3)));
+        assert!(!range.contains(&Key::from(1)));
+        assert!(range.contains(&Key::from(3)));
+        assert!(!range.contains(&Key::from("a")));
+        assert!(KeyRange::only("a").contains(&Key::from("a")));
+        assert!(KeyRange::at_least(2).contains(&Key::from("a")));
+        assert!(!KeyRange::at_most(2).contains(&Key::from(3)));
+    }
+}
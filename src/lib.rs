@@ -1,10 +1,28 @@
 //! # libweb
 //!
 //! Implements the web API with fallbacks for desktop applications.
+//!
+//! # Features
+//!
+//! - `serde`: enables the `idb` module, `console::table` and JSON conversions
+//!   in `console`, `fetch` and `crypto::subtle`.
+//! - `tracing`: enables `console::ConsoleLayer`.
 #![deny(missing_docs)]
 #![deny(warnings)]
 
+mod clock;
+#[cfg(not(target_arch = "wasm32"))]
+mod dirs;
 pub mod console;
+pub mod crypto;
+pub mod event_source;
+pub mod fetch;
+#[cfg(feature = "serde")]
+pub mod idb;
+pub mod performance;
+pub mod storage;
+pub mod timers;
+pub mod url;
 pub mod websocket;
 
 /// The `Error` type used throughout the crate.
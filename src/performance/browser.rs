@@ -0,0 +1,100 @@
+use crate::performance::{Entry, EntryType};
+use crate::{js_value_to_error, Result};
+use failure::format_err;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+fn performance() -> Result<web_sys::Performance> {
+    crate::clock::performance().ok_or_else(|| format_err!("The Performance API is not available"))
+}
+
+pub(crate) fn mark(name: &str) -> Result<()> {
+    performance()?.mark(name).map_err(js_value_to_error)
+}
+
+pub(crate) fn measure(name: &str, start_mark: Option<&str>, end_mark: Option<&str>) -> Result<()> {
+    let performance = performance()?;
+    match (start_mark, end_mark) {
+        (None, None) => performance.measure(name),
+        (Some(start_mark), None) => performance.measure_with_start_mark(name, start_mark),
+        (Some(start_mark), Some(end_mark)) => {
+            performance.measure_with_start_mark_and_end_mark(name, start_mark, end_mark)
+        }
+        // `web_sys` has no binding for an end mark without a start mark.
+        (None, Some(end_mark)) => {
+            let measure: js_sys::Function = js_sys::Reflect::get(&performance, &"measure".into())
+                .and_then(|measure| measure.dyn_into().map_err(JsValue::from))
+                .map_err(js_value_to_error)?;
+            measure
+                .call3(&performance, &name.into(), &JsValue::UNDEFINED, &end_mark.into())
+                .map(|_| ())
+        }
+    }
+    .map_err(js_value_to_error)
+}
+
+pub(crate) fn get_entries_by_name(name: &str) -> Vec<Entry> {
+    let performance = match performance() {
+        Ok(performance) => performance,
+        Err(_) => return Vec::new(),
+    };
+    performance
+        .get_entries_by_name(name)
+        .iter()
+        .filter_map(|entry| {
+            let entry: web_sys::PerformanceEntry = entry.dyn_into().ok()?;
+            let entry_type = match entry.entry_type().as_str() {
+                "mark" => EntryType::Mark,
+                "measure" => EntryType::Measure,
+                _ => return None,
+            };
+            Some(Entry {
+                name: entry.name(),
+                entry_type,
+                start_time: entry.start_time(),
+                duration: entry.duration(),
+            })
+        })
+        .collect()
+}
+
+pub(crate) fn clear(entry_type: EntryType, name: Option<&str>) {
+    let performance = match performance() {
+        Ok(performance) => performance,
+        Err(_) => return,
+    };
+    match (entry_type, name) {
+        (EntryType::Mark, None) => performance.clear_marks(),
+        (EntryType::Mark, Some(name)) => performance.clear_marks_with_mark_name(name),
+        (EntryType::Measure, None) => performance.clear_measures(),
+        (EntryType::Measure, Some(name)) => performance.clear_measures_with_measure_name(name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::performance::{self, EntryType};
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn test_measure() {
+        performance::mark("browser-start").unwrap();
+        performance::mark("browser-end").unwrap();
+        performance::measure("browser", Some("browser-start"), Some("browser-end")).unwrap();
+        performance::measure("browser-origin", None, Some("browser-end")).unwrap();
+        let entries = performance::get_entries_by_name("browser");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].entry_type, EntryType::Measure);
+        assert!(entries[0].duration >= 0.0);
+        let origin = &performance::get_entries_by_name("browser-origin")[0];
+        assert_eq!(origin.start_time, 0.0);
+        assert!(performance::measure("browser", Some("browser-missing"), None).is_err());
+
+        performance::clear_marks(Some("browser-start"));
+        assert!(performance::get_entries_by_name("browser-start").is_empty());
+        performance::clear_measures(None);
+        assert!(performance::get_entries_by_name("browser").is_empty());
+    }
+}
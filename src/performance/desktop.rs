@@ -0,0 +1,109 @@
+use crate::performance::{now, Entry, EntryType};
+use crate::Result;
+use failure::bail;
+use std::sync::{Mutex, MutexGuard};
+
+static ENTRIES: Mutex<Vec<Entry>> = Mutex::new(Vec::new());
+
+fn entries() -> MutexGuard<'static, Vec<Entry>> {
+    ENTRIES.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+pub(crate) fn mark(name: &str) -> Result<()> {
+    let entry = Entry {
+        name: name.to_string(),
+        entry_type: EntryType::Mark,
+        start_time: now(),
+        duration: 0.0,
+    };
+    entries().push(entry);
+    Ok(())
+}
+
+pub(crate) fn measure(name: &str, start_mark: Option<&str>, end_mark: Option<&str>) -> Result<()> {
+    let mut entries = entries();
+    let time = |mark: &str| -> Result<f64> {
+        let entry = entries
+            .iter()
+            .rev()
+            .find(|entry| entry.entry_type == EntryType::Mark && entry.name == mark);
+        match entry {
+            Some(entry) => Ok(entry.start_time),
+            None => bail!("The mark '{}' does not exist", mark),
+        }
+    };
+    let start_time = match start_mark {
+        Some(mark) => time(mark)?,
+        None => 0.0,
+    };
+    let end_time = match end_mark {
+        Some(mark) => time(mark)?,
+        None => now(),
+    };
+    entries.push(Entry {
+        name: name.to_string(),
+        entry_type: EntryType::Measure,
+        start_time,
+        duration: end_time - start_time,
+    });
+    Ok(())
+}
+
+pub(crate) fn get_entries_by_name(name: &str) -> Vec<Entry> {
+    let mut entries = entries()
+        .iter()
+        .filter(|entry| entry.name == name)
+        .cloned()
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+    entries
+}
+
+pub(crate) fn clear(entry_type: EntryType, name: Option<&str>) {
+    entries().retain(|entry| {
+        entry.entry_type != entry_type || name.is_some_and(|name| entry.name != name)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::performance::{self, EntryType};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_measure() {
+        performance::mark("desktop-start").unwrap();
+        thread::sleep(Duration::from_millis(2));
+        performance::mark("desktop-end").unwrap();
+        performance::measure("desktop", Some("desktop-start"), Some("desktop-end")).unwrap();
+        performance::measure("desktop", Some("desktop-start"), None).unwrap();
+        performance::measure("desktop-origin", None, Some("desktop-end")).unwrap();
+
+        let entries = performance::get_entries_by_name("desktop");
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].entry_type, EntryType::Measure);
+        assert!(entries[0].duration >= 2.0);
+        assert!(entries[1].duration >= entries[0].duration);
+        let start = &performance::get_entries_by_name("desktop-start")[0];
+        assert_eq!(entries[0].start_time, start.start_time);
+        let origin = &performance::get_entries_by_name("desktop-origin")[0];
+        assert_eq!(origin.start_time, 0.0);
+
+        assert!(performance::measure("desktop", Some("desktop-missing"), None).is_err());
+    }
+
+    #[test]
+    fn test_clear() {
+        performance::mark("desktop-clear").unwrap();
+        performance::mark("desktop-clear").unwrap();
+        performance::measure("desktop-clear", None, None).unwrap();
+        assert_eq!(performance::get_entries_by_name("desktop-clear").len(), 3);
+        performance::clear_marks(Some("desktop-clear"));
+        let entries = performance::get_entries_by_name("desktop-clear");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].entry_type, EntryType::Measure);
+        performance::clear_measures(Some("desktop-clear"));
+        assert!(performance::get_entries_by_name("desktop-clear").is_empty());
+    }
+}
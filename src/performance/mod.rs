@@ -0,0 +1,127 @@
+//! The Performance API
+//!
+//! Marks and measures are recorded with `performance.mark` and
+//! `performance.measure` in the browser, so they show up in the developer
+//! tools. On desktop they are kept in memory until they are cleared.
+use crate::Result;
+use std::fmt;
+
+#[cfg(target_arch = "wasm32")]
+mod browser;
+#[cfg(not(target_arch = "wasm32"))]
+mod desktop;
+
+#[cfg(target_arch = "wasm32")]
+use self::browser as backend;
+#[cfg(not(target_arch = "wasm32"))]
+use self::desktop as backend;
+
+/// The type of a performance entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EntryType {
+    /// A named point in time created by `mark`.
+    Mark,
+    /// A named duration created by `measure`.
+    Measure,
+}
+
+impl EntryType {
+    /// Returns the name used by the Performance API.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EntryType::Mark => "mark",
+            EntryType::Measure => "measure",
+        }
+    }
+}
+
+impl fmt::Display for EntryType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A mark or a measure.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// The name of the entry.
+    pub name: String,
+    /// The type of the entry.
+    pub entry_type: EntryType,
+    /// The time of a mark or the start of a measure, in milliseconds since
+    /// the time origin.
+    pub start_time: f64,
+    /// The duration of a measure in milliseconds, which is 0 for marks.
+    pub duration: f64,
+}
+
+/// Returns the milliseconds elapsed since the time origin, with sub
+/// millisecond precision.
+///
+/// The time origin is the start of the page or worker in the browser. On
+/// desktop it's the first use of the clock, see `init`. The clock is
+/// monotonic.
+pub fn now() -> f64 {
+    crate::clock::now()
+}
+
+/// Starts the clock on desktop, so the time origin is the start of the
+/// program when it's called at the start of `main`. Otherwise the clock
+/// starts when it's first used, for example by `now` or `mark`.
+///
+/// Does nothing in the browser or if the clock was started.
+pub fn init() {
+    #[cfg(not(target_arch = "wasm32"))]
+    crate::clock::init();
+}
+
+/// Records a mark at the current time.
+///
+/// ```
+/// use libweb::performance;
+///
+/// performance::mark("parse-start").unwrap();
+/// performance::mark("parse-end").unwrap();
+/// performance::measure("parse", Some("parse-start"), Some("parse-end")).unwrap();
+/// let entries = performance::get_entries_by_name("parse");
+/// assert!(entries[0].duration >= 0.0);
+/// ```
+pub fn mark(name: &str) -> Result<()> {
+    backend::mark(name)
+}
+
+/// Records a measure between two marks.
+///
+/// Without a start mark the measure starts at the time origin, without an
+/// end mark it ends at the current time. If several marks have the same
+/// name the latest one is used. Fails if a mark doesn't exist.
+pub fn measure(name: &str, start_mark: Option<&str>, end_mark: Option<&str>) -> Result<()> {
+    backend::measure(name, start_mark, end_mark)
+}
+
+/// Returns the marks and measures with a name, ordered by their start time.
+pub fn get_entries_by_name(name: &str) -> Vec<Entry> {
+    backend::get_entries_by_name(name)
+}
+
+/// Removes the marks with a name, or all marks.
+pub fn clear_marks(name: Option<&str>) {
+    backend::clear(EntryType::Mark, name)
+}
+
+/// Removes the measures with a name, or all measures.
+pub fn clear_measures(name: Option<&str>) {
+    backend::clear(EntryType::Measure, name)
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_now() {
+        let start = now();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        assert!(now() - start >= 2.0);
+    }
+}
@@ -0,0 +1,105 @@
+use crate::storage::QuotaExceeded;
+use crate::{js_value_to_error, Result};
+use failure::format_err;
+use wasm_bindgen::{JsCast, JsValue};
+
+pub(crate) struct Storage {
+    storage: web_sys::Storage,
+}
+
+impl Storage {
+    pub(crate) fn local() -> Result<Self> {
+        let storage = window()?.local_storage().map_err(js_value_to_error)?;
+        Storage::new(storage)
+    }
+
+    pub(crate) fn session() -> Result<Self> {
+        let storage = window()?.session_storage().map_err(js_value_to_error)?;
+        Storage::new(storage)
+    }
+
+    fn new(storage: Option<web_sys::Storage>) -> Result<Self> {
+        let storage = storage.ok_or_else(|| format_err!("Storage is not available"))?;
+        Ok(Storage { storage })
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Result<Option<String>> {
+        self.storage.get_item(key).map_err(js_value_to_error)
+    }
+
+    pub(crate) fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.storage.set_item(key, value).map_err(|err| {
+            if is_quota_exceeded(&err) {
+                QuotaExceeded { key: key.to_string() }.into()
+            } else {
+                js_value_to_error(err)
+            }
+        })
+    }
+
+    pub(crate) fn remove(&self, key: &str) -> Result<()> {
+        self.storage.remove_item(key).map_err(js_value_to_error)
+    }
+
+    pub(crate) fn clear(&self) -> Result<()> {
+        self.storage.clear().map_err(js_value_to_error)
+    }
+
+    pub(crate) fn keys(&self) -> Result<Vec<String>> {
+        let len = self.storage.length().map_err(js_value_to_error)?;
+        let mut keys = Vec::with_capacity(len as usize);
+        for i in 0..len {
+            if let Some(key) = self.storage.key(i).map_err(js_value_to_error)? {
+                keys.push(key);
+            }
+        }
+        Ok(keys)
+    }
+
+    pub(crate) fn len(&self) -> Result<usize> {
+        Ok(self.storage.length().map_err(js_value_to_error)? as usize)
+    }
+}
+
+fn window() -> Result<web_sys::Window> {
+    web_sys::window().ok_or_else(|| format_err!("No global `window` exists"))
+}
+
+fn is_quota_exceeded(err: &JsValue) -> bool {
+    match err.dyn_ref::<web_sys::DomException>() {
+        // Older versions of Firefox use a different name.
+        Some(err) => matches!(
+            err.name().as_str(),
+            "QuotaExceededError" | "NS_ERROR_DOM_QUOTA_REACHED"
+        ),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::{QuotaExceeded, Storage};
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn test_session() {
+        let storage = Storage::session().unwrap();
+        storage.clear().unwrap();
+        storage.set("a", "1").unwrap();
+        storage.set("b", "2").unwrap();
+        assert_eq!(storage.get("a").unwrap().as_deref(), Some("1"));
+        assert_eq!(storage.len().unwrap(), 2);
+        storage.remove("a").unwrap();
+        assert_eq!(storage.keys().unwrap(), vec!["b"]);
+        storage.clear().unwrap();
+        assert!(storage.is_empty().unwrap());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_quota() {
+        let storage = Storage::local().unwrap();
+        let value = "x".repeat(16 * 1024 * 1024);
+        let err = storage.set("libweb-quota", &value).unwrap_err();
+        assert!(err.downcast::<QuotaExceeded>().is_ok());
+    }
+}
@@ -0,0 +1,254 @@
+use crate::dirs::app_dir;
+use crate::storage::{QuotaExceeded, QUOTA};
+use crate::Result;
+use failure::format_err;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+
+type Entries = Arc<Mutex<BTreeMap<String, String>>>;
+
+pub(crate) struct Storage {
+    entries: Entries,
+    path: Option<PathBuf>,
+}
+
+impl Storage {
+    pub(crate) fn local() -> Result<Self> {
+        Storage::open(&app_dir()?.join("local_storage"))
+    }
+
+    pub(crate) fn session() -> Result<Self> {
+        static SESSION: OnceLock<Entries> = OnceLock::new();
+        Ok(Storage {
+            entries: SESSION.get_or_init(Default::default).clone(),
+            path: None,
+        })
+    }
+
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        // Storages with the same path share their entries, like the local
+        // storages of different windows of the same origin.
+        static OPEN: OnceLock<Mutex<HashMap<PathBuf, Entries>>> = OnceLock::new();
+        let mut open = OPEN
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        let entries = match open.get(path) {
+            Some(entries) => entries.clone(),
+            None => {
+                let entries = match fs::read_to_string(path) {
+                    Ok(contents) => decode(&contents)?,
+                    Err(ref err) if err.kind() == ErrorKind::NotFound => BTreeMap::new(),
+                    Err(err) => return Err(err.into()),
+                };
+                let entries = Arc::new(Mutex::new(entries));
+                open.insert(path.to_path_buf(), entries.clone());
+                entries
+            }
+        };
+        Ok(Storage {
+            entries,
+            path: Some(path.to_path_buf()),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<String, String>> {
+        self.entries.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.lock().get(key).cloned())
+    }
+
+    pub(crate) fn set(&self, key: &str, value: &str) -> Result<()> {
+        let mut entries = self.lock();
+        let old = entries.get(key).map_or(0, |old| key.len() + old.len());
+        if size(&entries) - old + key.len() + value.len() > QUOTA {
+            return Err(QuotaExceeded { key: key.to_string() }.into());
+        }
+        let previous = entries.insert(key.to_string(), value.to_string());
+        if let Err(err) = self.persist(&entries) {
+            match previous {
+                Some(previous) => entries.insert(key.to_string(), previous),
+                None => entries.remove(key),
+            };
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn remove(&self, key: &str) -> Result<()> {
+        let mut entries = self.lock();
+        if entries.remove(key).is_some() {
+            self.persist(&entries)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn clear(&self) -> Result<()> {
+        let mut entries = self.lock();
+        entries.clear();
+        self.persist(&entries)
+    }
+
+    pub(crate) fn keys(&self) -> Result<Vec<String>> {
+        Ok(self.lock().keys().cloned().collect())
+    }
+
+    pub(crate) fn len(&self) -> Result<usize> {
+        Ok(self.lock().len())
+    }
+
+    /// Writes the entries to a temporary file and moves it to the path, so
+    /// the file is never partially written.
+    fn persist(&self, entries: &BTreeMap<String, String>) -> Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, encode(entries))?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+fn size(entries: &BTreeMap<String, String>) -> usize {
+    entries.iter().map(|(key, value)| key.len() + value.len()).sum()
+}
+
+/// Encodes the entries as lines of tab separated keys and values.
+fn encode(entries: &BTreeMap<String, String>) -> String {
+    let mut contents = String::new();
+    for (key, value) in entries {
+        contents.push_str(&escape(key));
+        contents.push('\t');
+        contents.push_str(&escape(value));
+        contents.push('\n');
+    }
+    contents
+}
+
+fn decode(contents: &str) -> Result<BTreeMap<String, String>> {
+    let mut entries = BTreeMap::new();
+    for line in contents.lines() {
+        let mut parts = line.splitn(2, '\t');
+        match (parts.next(), parts.next()) {
+            (Some(key), Some(value)) => entries.insert(unescape(key)?, unescape(value)?),
+            _ => return Err(format_err!("invalid storage entry '{}'", line)),
+        };
+    }
+    Ok(entries)
+}
+
+fn escape(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len());
+    for c in string.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(string: &str) -> Result<String> {
+    let mut unescaped = String::with_capacity(string.len());
+    let mut chars = string.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => unescaped.push('\\'),
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            _ => return Err(format_err!("invalid escape in '{}'", string)),
+        }
+    }
+    Ok(unescaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage;
+    use std::env;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = env::temp_dir()
+            .join(format!("libweb-{}", std::process::id()))
+            .join(name);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_encoding() {
+        let mut entries = BTreeMap::new();
+        entries.insert("key\twith\ttabs".to_string(), "line\nbreak \\ ".to_string());
+        entries.insert(String::new(), String::new());
+        let encoded = encode(&entries);
+        assert_eq!(encoded.lines().count(), 2);
+        assert_eq!(decode(&encoded).unwrap(), entries);
+        assert!(decode("no tab").is_err());
+        assert!(decode("a\\x\tb").is_err());
+    }
+
+    #[test]
+    fn test_persist() {
+        let path = temp_path("persist");
+        let storage = storage::Storage::open(&path).unwrap();
+        storage.set("a", "1").unwrap();
+        storage.set("b", "2\n").unwrap();
+        storage.remove("a").unwrap();
+        assert_eq!(storage.keys().unwrap(), vec!["b"]);
+        assert_eq!(storage.len().unwrap(), 1);
+
+        // Reload from the file, ignoring the shared entries.
+        let entries = decode(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(entries.get("b").map(String::as_str), Some("2\n"));
+        assert_eq!(entries.len(), 1);
+
+        // Storages opened with the same path share their entries.
+        let shared = storage::Storage::open(&path).unwrap();
+        assert_eq!(shared.get("b").unwrap().as_deref(), Some("2\n"));
+        shared.clear().unwrap();
+        assert!(storage.is_empty().unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+    }
+
+    #[test]
+    fn test_quota() {
+        let storage = storage::Storage::open(temp_path("quota")).unwrap();
+        let value = "x".repeat(QUOTA / 2);
+        storage.set("a", &value).unwrap();
+        // Replacing a value only counts the difference.
+        storage.set("a", &value).unwrap();
+        let err = storage.set("b", &value).unwrap_err();
+        let err = err.downcast::<QuotaExceeded>().unwrap();
+        assert_eq!(err.key, "b");
+        assert_eq!(storage.get("b").unwrap(), None);
+    }
+
+    #[test]
+    fn test_session() {
+        let storage = storage::Storage::session().unwrap();
+        storage.set("libweb-test-session", "1").unwrap();
+        let shared = storage::Storage::session().unwrap();
+        assert_eq!(shared.get("libweb-test-session").unwrap().as_deref(), Some("1"));
+        shared.remove("libweb-test-session").unwrap();
+        assert_eq!(storage.get("libweb-test-session").unwrap(), None);
+    }
+}
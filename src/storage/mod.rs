@@ -0,0 +1,124 @@
+//! The Web Storage API
+//!
+//! On desktop the local storage is persisted to a file per application and
+//! the session storage is kept in memory until the process exits.
+use crate::Result;
+use failure::Fail;
+use std::fmt;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+
+#[cfg(target_arch = "wasm32")]
+mod browser;
+#[cfg(not(target_arch = "wasm32"))]
+mod desktop;
+
+#[cfg(target_arch = "wasm32")]
+type InnerStorage = browser::Storage;
+#[cfg(not(target_arch = "wasm32"))]
+type InnerStorage = desktop::Storage;
+
+/// The maximum size of a desktop storage in bytes, counting keys and values.
+pub const QUOTA: usize = 5 * 1024 * 1024;
+
+/// Error returned when a value doesn't fit into the storage.
+#[derive(Debug)]
+pub struct QuotaExceeded {
+    /// The key that was set.
+    pub key: String,
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Storage quota exceeded setting '{}'", self.key)
+    }
+}
+
+impl Fail for QuotaExceeded {}
+
+/// A key-value store of strings.
+///
+/// ```
+/// use libweb::storage::Storage;
+///
+/// let storage = Storage::session().unwrap();
+/// storage.set("theme", "dark").unwrap();
+/// assert_eq!(storage.get("theme").unwrap().as_deref(), Some("dark"));
+/// ```
+pub struct Storage {
+    inner: InnerStorage,
+}
+
+impl Storage {
+    /// Returns the local storage, which persists across sessions.
+    ///
+    /// On desktop it is stored in the data directory of the user, in a
+    /// directory named after the executable.
+    pub fn local() -> Result<Self> {
+        Ok(Storage {
+            inner: InnerStorage::local()?,
+        })
+    }
+
+    /// Returns the session storage, which is cleared when the page or the
+    /// process exits.
+    pub fn session() -> Result<Self> {
+        Ok(Storage {
+            inner: InnerStorage::session()?,
+        })
+    }
+
+    /// Opens a storage persisted to a file.
+    ///
+    /// Only available on desktop. Storages opened with the same path share
+    /// their entries.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Storage {
+            inner: InnerStorage::open(path.as_ref())?,
+        })
+    }
+
+    /// Returns the value of a key.
+    pub fn get(&self, key: &str) -> Result<Option<String>> {
+        self.inner.get(key)
+    }
+
+    /// Sets the value of a key.
+    ///
+    /// Fails with `QuotaExceeded` if the storage is full.
+    pub fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.inner.set(key, value)
+    }
+
+    /// Removes a key.
+    pub fn remove(&self, key: &str) -> Result<()> {
+        self.inner.remove(key)
+    }
+
+    /// Removes all keys.
+    pub fn clear(&self) -> Result<()> {
+        self.inner.clear()
+    }
+
+    /// Returns the keys.
+    pub fn keys(&self) -> Result<Vec<String>> {
+        self.inner.keys()
+    }
+
+    /// Returns the number of keys.
+    pub fn len(&self) -> Result<usize> {
+        self.inner.len()
+    }
+
+    /// Returns whether the storage is empty.
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+}
+
+impl fmt::Debug for Storage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Storage")
+    }
+}
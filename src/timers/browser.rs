@@ -0,0 +1,101 @@
+use std::time::Duration;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+#[wasm_bindgen]
+extern "C" {
+    // The global functions work in windows, workers and node.
+    #[wasm_bindgen(js_name = setTimeout)]
+    fn set_timeout(handler: &js_sys::Function, timeout: i32) -> JsValue;
+
+    #[wasm_bindgen(js_name = setInterval)]
+    fn set_interval(handler: &js_sys::Function, timeout: i32) -> JsValue;
+
+    #[wasm_bindgen(js_name = clearTimeout)]
+    fn clear_timeout(handle: &JsValue);
+
+    #[wasm_bindgen(js_name = clearInterval)]
+    fn clear_interval(handle: &JsValue);
+}
+
+/// A scheduled timer, which is canceled when dropped.
+pub(crate) struct Timer {
+    handle: JsValue,
+    interval: bool,
+    _callback: Closure<dyn FnMut()>,
+}
+
+impl Timer {
+    pub(crate) fn timeout<F: FnOnce() + 'static>(delay: Duration, callback: F) -> Self {
+        let callback: Closure<dyn FnMut()> = Closure::once(callback);
+        Timer {
+            handle: set_timeout(callback.as_ref().unchecked_ref(), millis(delay)),
+            interval: false,
+            _callback: callback,
+        }
+    }
+
+    pub(crate) fn interval<F: FnMut() + 'static>(interval: Duration, callback: F) -> Self {
+        let callback = Closure::wrap(Box::new(callback) as Box<dyn FnMut()>);
+        Timer {
+            handle: set_interval(callback.as_ref().unchecked_ref(), millis(interval)),
+            interval: true,
+            _callback: callback,
+        }
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if self.interval {
+            clear_interval(&self.handle);
+        } else {
+            clear_timeout(&self.handle);
+        }
+    }
+}
+
+/// Converts the duration to milliseconds, saturating at the maximum delay.
+fn millis(duration: Duration) -> i32 {
+    duration.as_millis().min(i32::MAX as u128) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::timers::{sleep, Interval, Timeout};
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::time::Duration;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    async fn test_timeout() {
+        let fired = Rc::new(Cell::new(false));
+        let flag = fired.clone();
+        let _timeout = Timeout::new(10, move || flag.set(true));
+        sleep(Duration::from_millis(50)).await;
+        assert!(fired.get());
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_cancel() {
+        let fired = Rc::new(Cell::new(false));
+        let flag = fired.clone();
+        Timeout::new(10, move || flag.set(true)).cancel();
+        sleep(Duration::from_millis(50)).await;
+        assert!(!fired.get());
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_interval() {
+        let count = Rc::new(Cell::new(0));
+        let counter = count.clone();
+        let interval = Interval::new(10, move || counter.set(counter.get() + 1));
+        sleep(Duration::from_millis(100)).await;
+        drop(interval);
+        let ticks = count.get();
+        assert!(ticks >= 2);
+        sleep(Duration::from_millis(50)).await;
+        assert_eq!(count.get(), ticks);
+    }
+}
@@ -0,0 +1,143 @@
+use log::*;
+use std::collections::{BTreeMap, HashMap};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Condvar, Mutex, MutexGuard, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+type Callback = Box<dyn FnMut() + Send>;
+
+/// The shortest period of an interval. Browsers clamp repeating timers to
+/// 4ms, shorter ones would keep the timer thread busy.
+const MIN_INTERVAL: Duration = Duration::from_millis(4);
+
+struct Task {
+    callback: Callback,
+    interval: Option<Duration>,
+}
+
+/// The timers scheduled on the timer thread.
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    /// The tasks ordered by deadline.
+    queue: BTreeMap<(Instant, u64), Task>,
+    /// The deadlines of the scheduled tasks, including the running one.
+    deadlines: HashMap<u64, Instant>,
+}
+
+struct Scheduler {
+    state: Mutex<State>,
+    condvar: Condvar,
+}
+
+impl Scheduler {
+    fn get() -> &'static Scheduler {
+        static SCHEDULER: OnceLock<Scheduler> = OnceLock::new();
+        SCHEDULER.get_or_init(|| {
+            thread::Builder::new()
+                .name("libweb-timers".to_string())
+                .spawn(|| Scheduler::get().run())
+                .expect("failed to spawn the timer thread");
+            Scheduler {
+                state: Mutex::new(State::default()),
+                condvar: Condvar::new(),
+            }
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn schedule(&self, delay: Duration, interval: Option<Duration>, callback: Callback) -> u64 {
+        let mut state = self.lock();
+        let id = state.next_id;
+        state.next_id += 1;
+        let deadline = Instant::now() + delay;
+        state.deadlines.insert(id, deadline);
+        state.queue.insert((deadline, id), Task { callback, interval });
+        self.condvar.notify_one();
+        id
+    }
+
+    fn cancel(&self, id: u64) {
+        let mut state = self.lock();
+        if let Some(deadline) = state.deadlines.remove(&id) {
+            state.queue.remove(&(deadline, id));
+        }
+    }
+
+    fn run(&self) {
+        let mut state = self.lock();
+        loop {
+            let now = Instant::now();
+            let next = state.queue.keys().next().copied();
+            match next {
+                Some((deadline, id)) if deadline <= now => {
+                    let mut task = state.queue.remove(&(deadline, id)).unwrap();
+                    drop(state);
+                    let result = panic::catch_unwind(AssertUnwindSafe(&mut task.callback));
+                    if result.is_err() {
+                        error!("timer callback panicked");
+                    }
+                    state = self.lock();
+                    // The timer may have been canceled by its callback.
+                    match task.interval {
+                        Some(interval) if state.deadlines.contains_key(&id) => {
+                            // Missed ticks are skipped like in the browser.
+                            let deadline = (deadline + interval).max(Instant::now());
+                            state.deadlines.insert(id, deadline);
+                            state.queue.insert((deadline, id), task);
+                        }
+                        _ => {
+                            state.deadlines.remove(&id);
+                        }
+                    }
+                }
+                Some((deadline, _)) => {
+                    state = self
+                        .condvar
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(|err| err.into_inner())
+                        .0;
+                }
+                None => {
+                    state = self.condvar.wait(state).unwrap_or_else(|err| err.into_inner());
+                }
+            }
+        }
+    }
+}
+
+/// A scheduled timer, which is canceled when dropped.
+pub(crate) struct Timer {
+    id: u64,
+}
+
+impl Timer {
+    pub(crate) fn timeout<F: FnOnce() + Send + 'static>(delay: Duration, callback: F) -> Self {
+        let mut callback = Some(callback);
+        let callback = Box::new(move || {
+            if let Some(callback) = callback.take() {
+                callback();
+            }
+        });
+        Timer {
+            id: Scheduler::get().schedule(delay, None, callback),
+        }
+    }
+
+    pub(crate) fn interval<F: FnMut() + Send + 'static>(interval: Duration, callback: F) -> Self {
+        let interval = interval.max(MIN_INTERVAL);
+        Timer {
+            id: Scheduler::get().schedule(interval, Some(interval), Box::new(callback)),
+        }
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        Scheduler::get().cancel(self.id);
+    }
+}
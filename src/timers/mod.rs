@@ -0,0 +1,242 @@
+//! The timers API
+//!
+//! In the browser timers are scheduled using `setTimeout` and `setInterval`,
+//! on desktop callbacks run on a timer thread shared by all timers.
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+#[cfg(target_arch = "wasm32")]
+mod browser;
+#[cfg(not(target_arch = "wasm32"))]
+mod desktop;
+
+#[cfg(target_arch = "wasm32")]
+use self::browser::Timer;
+#[cfg(not(target_arch = "wasm32"))]
+use self::desktop::Timer;
+
+/// Implemented by types that are `Send` on desktop, where callbacks run on the
+/// timer thread. Implemented by all types in the browser.
+#[cfg(not(target_arch = "wasm32"))]
+pub trait MaybeSend: Send {}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<T: Send> MaybeSend for T {}
+
+/// Implemented by types that are `Send` on desktop, where callbacks run on the
+/// timer thread. Implemented by all types in the browser.
+#[cfg(target_arch = "wasm32")]
+pub trait MaybeSend {}
+
+#[cfg(target_arch = "wasm32")]
+impl<T> MaybeSend for T {}
+
+/// A callback that runs once after a delay. It is canceled when dropped.
+///
+/// ```
+/// use libweb::timers::Timeout;
+///
+/// let timeout = Timeout::new(1_000, || println!("one second later"));
+/// // Runs the callback even though the handle is dropped.
+/// timeout.forget();
+/// ```
+#[must_use = "the timeout is canceled when dropped"]
+pub struct Timeout {
+    timer: Timer,
+}
+
+impl Timeout {
+    /// Schedules the callback to run after `millis` milliseconds.
+    pub fn new<F: FnOnce() + MaybeSend + 'static>(millis: u32, callback: F) -> Self {
+        Timeout {
+            timer: Timer::timeout(Duration::from_millis(millis.into()), callback),
+        }
+    }
+
+    /// Cancels the timeout.
+    pub fn cancel(self) {}
+
+    /// Lets the timeout run without keeping its handle.
+    pub fn forget(self) {
+        std::mem::forget(self.timer);
+    }
+}
+
+impl std::fmt::Debug for Timeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("Timeout")
+    }
+}
+
+/// A callback that runs repeatedly. It is canceled when dropped.
+#[must_use = "the interval is canceled when dropped"]
+pub struct Interval {
+    timer: Timer,
+}
+
+impl Interval {
+    /// Schedules the callback to run every `millis` milliseconds.
+    ///
+    /// Like in the browser, periods shorter than 4 milliseconds are raised to
+    /// 4 milliseconds.
+    pub fn new<F: FnMut() + MaybeSend + 'static>(millis: u32, callback: F) -> Self {
+        Interval {
+            timer: Timer::interval(Duration::from_millis(millis.into()), callback),
+        }
+    }
+
+    /// Cancels the interval.
+    pub fn cancel(self) {}
+
+    /// Lets the interval run forever without keeping its handle.
+    pub fn forget(self) {
+        std::mem::forget(self.timer);
+    }
+}
+
+impl std::fmt::Debug for Interval {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("Interval")
+    }
+}
+
+/// Returns a future that completes after `duration`.
+pub fn sleep(duration: Duration) -> Sleep {
+    Sleep {
+        duration,
+        timeout: None,
+        state: Arc::new(Mutex::new(SleepState::default())),
+    }
+}
+
+/// A future that completes after a duration, created by `sleep`.
+///
+/// The timer starts when the future is first polled.
+#[must_use = "futures do nothing unless polled"]
+pub struct Sleep {
+    duration: Duration,
+    timeout: Option<Timer>,
+    state: Arc<Mutex<SleepState>>,
+}
+
+#[derive(Default)]
+struct SleepState {
+    done: bool,
+    waker: Option<Waker>,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let this = self.get_mut();
+        {
+            let mut state = this.state.lock().unwrap_or_else(|err| err.into_inner());
+            if state.done {
+                return Poll::Ready(());
+            }
+            state.waker = Some(cx.waker().clone());
+        }
+        if this.timeout.is_none() {
+            let state = this.state.clone();
+            this.timeout = Some(Timer::timeout(this.duration, move || {
+                let mut state = state.lock().unwrap_or_else(|err| err.into_inner());
+                state.done = true;
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+            }));
+        }
+        Poll::Pending
+    }
+}
+
+impl std::fmt::Debug for Sleep {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Sleep").field("duration", &self.duration).finish()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use futures_executor::block_on;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Instant;
+
+    #[test]
+    fn test_timeout() {
+        let (sender, receiver) = mpsc::channel();
+        let start = Instant::now();
+        let _timeout = Timeout::new(20, move || sender.send(Instant::now()).unwrap());
+        let fired = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(fired - start >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_cancel() {
+        let (sender, receiver) = mpsc::channel();
+        let timeout = Timeout::new(20, move || sender.send(()).unwrap());
+        drop(timeout);
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+
+    #[test]
+    fn test_ordering() {
+        let (sender, receiver) = mpsc::channel();
+        let first = sender.clone();
+        let _second = Timeout::new(40, move || sender.send(2).unwrap());
+        let _first = Timeout::new(10, move || first.send(1).unwrap());
+        assert_eq!(receiver.recv().unwrap(), 1);
+        assert_eq!(receiver.recv().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_interval() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = count.clone();
+        let interval = Interval::new(10, move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        thread::sleep(Duration::from_millis(100));
+        interval.cancel();
+        let ticks = count.load(Ordering::SeqCst);
+        assert!(ticks >= 2, "{} ticks", ticks);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(count.load(Ordering::SeqCst), ticks);
+    }
+
+    #[test]
+    fn test_interval_clamped() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = count.clone();
+        let interval = Interval::new(0, move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        thread::sleep(Duration::from_millis(100));
+        interval.cancel();
+        // At most one tick per 4ms instead of spinning.
+        let ticks = count.load(Ordering::SeqCst);
+        assert!((1..=26).contains(&ticks), "{} ticks", ticks);
+    }
+
+    #[test]
+    fn test_panic() {
+        let _timeout = Timeout::new(0, || panic!("timer panicked"));
+        let (sender, receiver) = mpsc::channel();
+        let _timeout = Timeout::new(10, move || sender.send(()).unwrap());
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+
+    #[test]
+    fn test_sleep() {
+        let start = Instant::now();
+        block_on(sleep(Duration::from_millis(20)));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+}
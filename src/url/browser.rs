@@ -0,0 +1,126 @@
+use crate::{js_value_to_error, Result};
+
+pub(crate) struct Url(web_sys::Url);
+
+impl Url {
+    pub(crate) fn parse(input: &str, base: Option<&Url>) -> Result<Url> {
+        let url = match base {
+            Some(base) => web_sys::Url::new_with_base(input, &base.href()),
+            None => web_sys::Url::new(input),
+        };
+        url.map(Url).map_err(js_value_to_error)
+    }
+
+    pub(crate) fn href(&self) -> String {
+        self.0.href()
+    }
+
+    pub(crate) fn origin(&self) -> String {
+        self.0.origin()
+    }
+
+    pub(crate) fn protocol(&self) -> String {
+        self.0.protocol()
+    }
+
+    pub(crate) fn set_protocol(&mut self, protocol: &str) {
+        self.0.set_protocol(protocol)
+    }
+
+    pub(crate) fn username(&self) -> String {
+        self.0.username()
+    }
+
+    pub(crate) fn set_username(&mut self, username: &str) {
+        self.0.set_username(username)
+    }
+
+    pub(crate) fn password(&self) -> String {
+        self.0.password()
+    }
+
+    pub(crate) fn set_password(&mut self, password: &str) {
+        self.0.set_password(password)
+    }
+
+    pub(crate) fn host(&self) -> String {
+        self.0.host()
+    }
+
+    pub(crate) fn set_host(&mut self, host: &str) {
+        self.0.set_host(host)
+    }
+
+    pub(crate) fn hostname(&self) -> String {
+        self.0.hostname()
+    }
+
+    pub(crate) fn set_hostname(&mut self, hostname: &str) {
+        self.0.set_hostname(hostname)
+    }
+
+    pub(crate) fn port(&self) -> String {
+        self.0.port()
+    }
+
+    pub(crate) fn set_port(&mut self, port: &str) {
+        self.0.set_port(port)
+    }
+
+    pub(crate) fn pathname(&self) -> String {
+        self.0.pathname()
+    }
+
+    pub(crate) fn set_pathname(&mut self, pathname: &str) {
+        self.0.set_pathname(pathname)
+    }
+
+    pub(crate) fn search(&self) -> String {
+        self.0.search()
+    }
+
+    pub(crate) fn set_search(&mut self, search: &str) {
+        self.0.set_search(search)
+    }
+
+    pub(crate) fn hash(&self) -> String {
+        self.0.hash()
+    }
+
+    pub(crate) fn set_hash(&mut self, hash: &str) {
+        self.0.set_hash(hash)
+    }
+}
+
+impl Clone for Url {
+    /// `web_sys::Url` is a reference to a mutable JavaScript object, so the
+    /// url is parsed again instead of sharing the object.
+    fn clone(&self) -> Self {
+        Url(web_sys::Url::new(&self.0.href()).expect("a parsed url is valid"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::url::Url;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn test_parse() {
+        let base = Url::parse("https://example.com/a/b").unwrap();
+        let url = base.join("../c?x=1").unwrap();
+        assert_eq!(url.href(), "https://example.com/c?x=1");
+        assert_eq!(url.search_params().get("x"), Some("1"));
+        assert!(Url::parse("/relative").is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_clone() {
+        let url = Url::parse("http://example.com/").unwrap();
+        let mut copy = url.clone();
+        copy.set_pathname("/copy");
+        assert_eq!(url.pathname(), "/");
+    }
+}
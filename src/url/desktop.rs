@@ -0,0 +1,97 @@
+use crate::Result;
+use failure::format_err;
+use url::quirks;
+
+#[derive(Clone)]
+pub(crate) struct Url(url::Url);
+
+impl Url {
+    pub(crate) fn parse(input: &str, base: Option<&Url>) -> Result<Url> {
+        let url = match base {
+            Some(base) => base.0.join(input),
+            None => url::Url::parse(input),
+        };
+        url.map(Url)
+            .map_err(|err| format_err!("Invalid URL '{}': {}", input, err))
+    }
+
+    pub(crate) fn href(&self) -> String {
+        quirks::href(&self.0).to_string()
+    }
+
+    pub(crate) fn origin(&self) -> String {
+        quirks::origin(&self.0)
+    }
+
+    pub(crate) fn protocol(&self) -> String {
+        quirks::protocol(&self.0).to_string()
+    }
+
+    pub(crate) fn set_protocol(&mut self, protocol: &str) {
+        let _ = quirks::set_protocol(&mut self.0, protocol);
+    }
+
+    pub(crate) fn username(&self) -> String {
+        quirks::username(&self.0).to_string()
+    }
+
+    pub(crate) fn set_username(&mut self, username: &str) {
+        let _ = quirks::set_username(&mut self.0, username);
+    }
+
+    pub(crate) fn password(&self) -> String {
+        quirks::password(&self.0).to_string()
+    }
+
+    pub(crate) fn set_password(&mut self, password: &str) {
+        let _ = quirks::set_password(&mut self.0, password);
+    }
+
+    pub(crate) fn host(&self) -> String {
+        quirks::host(&self.0).to_string()
+    }
+
+    pub(crate) fn set_host(&mut self, host: &str) {
+        let _ = quirks::set_host(&mut self.0, host);
+    }
+
+    pub(crate) fn hostname(&self) -> String {
+        quirks::hostname(&self.0).to_string()
+    }
+
+    pub(crate) fn set_hostname(&mut self, hostname: &str) {
+        let _ = quirks::set_hostname(&mut self.0, hostname);
+    }
+
+    pub(crate) fn port(&self) -> String {
+        quirks::port(&self.0).to_string()
+    }
+
+    pub(crate) fn set_port(&mut self, port: &str) {
+        let _ = quirks::set_port(&mut self.0, port);
+    }
+
+    pub(crate) fn pathname(&self) -> String {
+        quirks::pathname(&self.0).to_string()
+    }
+
+    pub(crate) fn set_pathname(&mut self, pathname: &str) {
+        quirks::set_pathname(&mut self.0, pathname)
+    }
+
+    pub(crate) fn search(&self) -> String {
+        quirks::search(&self.0).to_string()
+    }
+
+    pub(crate) fn set_search(&mut self, search: &str) {
+        quirks::set_search(&mut self.0, search)
+    }
+
+    pub(crate) fn hash(&self) -> String {
+        quirks::hash(&self.0).to_string()
+    }
+
+    pub(crate) fn set_hash(&mut self, hash: &str) {
+        quirks::set_hash(&mut self.0, hash)
+    }
+}
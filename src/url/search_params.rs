@@ -0,0 +1,238 @@
+use std::fmt;
+use std::iter::FromIterator;
+
+/// A list of name-value pairs in the `application/x-www-form-urlencoded`
+/// format, as used in the query of a `Url`.
+///
+/// The same implementation is used on all targets, so parsing and
+/// serialization don't depend on the browser.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct UrlSearchParams {
+    pairs: Vec<(String, String)>,
+}
+
+impl UrlSearchParams {
+    /// Creates an empty list.
+    pub fn new() -> Self {
+        UrlSearchParams::default()
+    }
+
+    /// Parses a query, with or without the leading `?`.
+    ///
+    /// Parsing never fails, invalid percent escapes are kept as they are and
+    /// invalid UTF-8 is replaced.
+    pub fn parse(input: &str) -> Self {
+        let input = input.strip_prefix('?').unwrap_or(input);
+        let pairs = input
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (name, value) = match pair.find('=') {
+                    Some(index) => (&pair[..index], &pair[index + 1..]),
+                    None => (pair, ""),
+                };
+                (decode(name), decode(value))
+            })
+            .collect();
+        UrlSearchParams { pairs }
+    }
+
+    /// Returns the number of pairs.
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    /// Returns `true` if there are no pairs.
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// Appends a pair.
+    pub fn append(&mut self, name: &str, value: &str) {
+        self.pairs.push((name.to_string(), value.to_string()));
+    }
+
+    /// Removes all pairs with a name.
+    pub fn delete(&mut self, name: &str) {
+        self.pairs.retain(|(key, _)| key != name);
+    }
+
+    /// Returns the value of the first pair with a name.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the values of all pairs with a name.
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.pairs
+            .iter()
+            .filter(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    /// Returns `true` if there is a pair with a name.
+    pub fn has(&self, name: &str) -> bool {
+        self.pairs.iter().any(|(key, _)| key == name)
+    }
+
+    /// Sets the value of the first pair with a name and removes the others,
+    /// or appends a pair if there is none.
+    pub fn set(&mut self, name: &str, value: &str) {
+        let mut found = false;
+        self.pairs.retain_mut(|(key, current)| {
+            if key != name {
+                return true;
+            }
+            if found {
+                return false;
+            }
+            found = true;
+            *current = value.to_string();
+            true
+        });
+        if !found {
+            self.append(name, value);
+        }
+    }
+
+    /// Sorts the pairs by name, keeping the order of pairs with the same
+    /// name.
+    ///
+    /// Names are compared by UTF-16 code units like in the browser.
+    pub fn sort(&mut self) {
+        self.pairs
+            .sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+    }
+
+    /// Returns an iterator over the pairs.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
+impl fmt::Display for UrlSearchParams {
+    /// Serializes the pairs without the leading `?`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, (name, value)) in self.pairs.iter().enumerate() {
+            if index > 0 {
+                f.write_str("&")?;
+            }
+            encode(f, name)?;
+            f.write_str("=")?;
+            encode(f, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl<K: AsRef<str>, V: AsRef<str>> FromIterator<(K, V)> for UrlSearchParams {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut params = UrlSearchParams::new();
+        params.extend(iter);
+        params
+    }
+}
+
+impl<K: AsRef<str>, V: AsRef<str>> Extend<(K, V)> for UrlSearchParams {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (name, value) in iter {
+            self.append(name.as_ref(), value.as_ref());
+        }
+    }
+}
+
+fn encode(f: &mut fmt::Formatter, input: &str) -> fmt::Result {
+    for byte in input.bytes() {
+        match byte {
+            b'*' | b'-' | b'.' | b'_' => write!(f, "{}", byte as char)?,
+            _ if byte.is_ascii_alphanumeric() => write!(f, "{}", byte as char)?,
+            b' ' => f.write_str("+")?,
+            _ => write!(f, "%{:02X}", byte)?,
+        }
+    }
+    Ok(())
+}
+
+fn decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let byte = match bytes[index] {
+            b'+' => b' ',
+            b'%' if index + 2 < bytes.len()
+                && bytes[index + 1].is_ascii_hexdigit()
+                && bytes[index + 2].is_ascii_hexdigit() =>
+            {
+                index += 2;
+                (hex(bytes[index - 1]) << 4) | hex(bytes[index])
+            }
+            byte => byte,
+        };
+        output.push(byte);
+        index += 1;
+    }
+    String::from_utf8_lossy(&output).into_owned()
+}
+
+fn hex(digit: u8) -> u8 {
+    match digit {
+        b'0'..=b'9' => digit - b'0',
+        b'a'..=b'f' => digit - b'a' + 10,
+        _ => digit - b'A' + 10,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let params = UrlSearchParams::parse("?a=1&b=x+y&&c&a=%E2%82%AC&d=%zz&e=1=2");
+        let pairs = params.iter().collect::<Vec<_>>();
+        assert_eq!(
+            pairs,
+            vec![
+                ("a", "1"),
+                ("b", "x y"),
+                ("c", ""),
+                ("a", "€"),
+                ("d", "%zz"),
+                ("e", "1=2"),
+            ]
+        );
+        assert_eq!(params.get_all("a"), vec!["1", "€"]);
+        assert!(UrlSearchParams::parse("").is_empty());
+    }
+
+    #[test]
+    fn test_serialize() {
+        let params = vec![("q", "a b&c=d"), ("€", "*-._~")]
+            .into_iter()
+            .collect::<UrlSearchParams>();
+        assert_eq!(params.to_string(), "q=a+b%26c%3Dd&%E2%82%AC=*-._%7E");
+        assert_eq!(UrlSearchParams::parse(&params.to_string()), params);
+    }
+
+    #[test]
+    fn test_modify() {
+        let mut params = UrlSearchParams::parse("b=1&a=2&b=3&c=4");
+        params.set("b", "5");
+        assert_eq!(params.to_string(), "b=5&a=2&c=4");
+        params.set("d", "6");
+        params.append("a", "7");
+        params.sort();
+        assert_eq!(params.to_string(), "a=2&a=7&b=5&c=4&d=6");
+        params.delete("a");
+        assert!(!params.has("a"));
+        assert_eq!(params.get("c"), Some("4"));
+        assert_eq!(params.len(), 3);
+    }
+}
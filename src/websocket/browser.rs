@@ -1,25 +1,69 @@
 use crate::{js_value_to_error, Result};
 use crate::websocket::{CloseCode, Message, Handler, Sender as WebSocketSender};
+use crate::clock;
+use crate::websocket::layer::Layers;
+use crate::websocket::rate_limit::Delayed;
+use crate::websocket::stats::Recorder;
 use js_sys::Uint8Array;
+use log::*;
 use std::borrow::Cow;
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::time::Duration;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 
 pub type Sender = Rc<RefCell<WebSocket>>;
 
-#[inline]
-pub(crate) fn connect<T: Handler + 'static>(url: &str) -> Result<()> {
+pub(crate) fn connect<T, F>(url: &str, new: F) -> Result<()>
+where
+    T: Handler + 'static,
+    F: FnOnce(WebSocketSender) -> T,
+{
     let socket = Rc::new(RefCell::new(WebSocket::new(url)?));
-    let sender = WebSocketSender::new(Rc::clone(&socket));
-    let handler = T::new(sender);
-    socket.borrow_mut().set_handler(handler);
+    let stats = Recorder::default();
+    let layers = Layers::default();
+    let sender = WebSocketSender::new(Rc::clone(&socket), stats.clone(), layers.clone());
+    let handler = new(sender);
+    socket.borrow_mut().set_handler(handler, stats, layers);
+    Ok(())
+}
+
+#[wasm_bindgen]
+extern "C" {
+    // The global function works in windows and workers.
+    #[wasm_bindgen(js_name = setTimeout)]
+    fn set_timeout(handler: &js_sys::Function, timeout: i32) -> JsValue;
+}
+
+/// Sends the message after `delay` and the waiting delayed messages.
+pub(crate) fn send_delayed(
+    socket: &Sender,
+    msg: Message,
+    delay: Duration,
+    stats: Recorder,
+) -> Result<()> {
+    let (seq, delay) = socket.borrow().delayed.borrow_mut().push(msg, delay);
+    let socket = Rc::clone(socket);
+    let callback: Closure<dyn FnMut()> = Closure::once(move || {
+        let socket = socket.borrow();
+        let msgs = socket.delayed.borrow_mut().pop_due(seq);
+        for msg in msgs {
+            stats.on_send(&msg);
+            if let Err(err) = socket.send(msg) {
+                error!("{:?}", err);
+            }
+        }
+    });
+    let millis = clock::millis(delay).ceil().min(i32::MAX as f64) as i32;
+    set_timeout(callback.as_ref().unchecked_ref(), millis);
+    callback.forget();
     Ok(())
 }
 
 pub struct WebSocket {
     socket: web_sys::WebSocket,
+    delayed: RefCell<Delayed>,
 }
 
 impl WebSocket {
@@ -28,7 +72,10 @@ impl WebSocket {
         let socket = web_sys::WebSocket::new(url)
             .map_err(js_value_to_error)?;
         socket.set_binary_type(web_sys::BinaryType::Arraybuffer);
-        Ok(WebSocket { socket })
+        Ok(WebSocket {
+            socket,
+            delayed: RefCell::default(),
+        })
     }
 
     #[inline]
@@ -42,6 +89,12 @@ impl WebSocket {
             .map_err(js_value_to_error)
     }
 
+    /// Returns true if delayed messages are waiting to be sent.
+    #[inline]
+    pub(crate) fn has_delayed(&self) -> bool {
+        !self.delayed.borrow().is_empty()
+    }
+
     #[inline]
     pub fn send(&self, msg: impl Into<Message>) -> Result<()> {
         match msg.into() {
@@ -61,22 +114,27 @@ impl WebSocket {
         Ok(())
     }
 
-    pub fn set_handler(&self, handler: impl Handler + 'static) {
+    pub fn set_handler(&self, handler: impl Handler + 'static, stats: Recorder, layers: Layers) {
         let handler = Rc::new(RefCell::new(Box::new(handler)));
 
         let handler2 = Rc::clone(&handler);
+        let layers2 = layers.clone();
+        let stats2 = stats.clone();
         let onopen: Closure<FnMut(JsValue)> =
             Closure::new(move |_event: JsValue| {
-                let result = handler2.borrow_mut().on_open();
+                stats2.on_open();
+                let result = layers2.on_open(&mut **handler2.borrow_mut());
                 match result {
                     Ok(()) => (),
-                    Err(err) => handler2.borrow_mut().on_error(err),
+                    Err(err) => layers2.on_error(&mut **handler2.borrow_mut(), err),
                 }
             });
         self.socket.set_onopen(Some(onopen.as_ref().unchecked_ref()));
         onopen.forget();
 
         let handler2 = Rc::clone(&handler);
+        let layers2 = layers.clone();
+        let stats2 = stats.clone();
         let onmessage: Closure<FnMut(JsValue)> =
             Closure::new(move |event: JsValue| {
                 let data = js_sys::Reflect::get(&event, &JsValue::from("data"))
@@ -89,16 +147,18 @@ impl WebSocket {
                     buffer.copy_to(&mut bin[..]);
                     Message::binary(bin)
                 };
-                let result = handler2.borrow_mut().on_message(message);
+                stats2.on_message(&message);
+                let result = layers2.on_message(&mut **handler2.borrow_mut(), message);
                 match result {
                     Ok(()) => (),
-                    Err(err) => handler2.borrow_mut().on_error(err),
+                    Err(err) => layers2.on_error(&mut **handler2.borrow_mut(), err),
                 }
             });
         self.socket.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
         onmessage.forget();
 
         let handler2 = Rc::clone(&handler);
+        let layers2 = layers.clone();
         let onclose: Closure<FnMut(JsValue)> =
             Closure::new(move |event: JsValue| {
                 let code = js_sys::Reflect::get(&event, &JsValue::from("code"))
@@ -110,7 +170,8 @@ impl WebSocket {
                     .unwrap()
                     .as_string()
                     .unwrap();
-                handler2.borrow_mut().on_close(code, &reason);
+                stats.on_close(code, &reason, false);
+                layers2.on_close(&mut **handler2.borrow_mut(), code, &reason);
             });
         self.socket.set_onclose(Some(onclose.as_ref().unchecked_ref()));
         onclose.forget();
@@ -118,7 +179,7 @@ impl WebSocket {
         let onerror: Closure<FnMut(JsValue)> =
             Closure::new(move |event: JsValue| {
                 let error = js_value_to_error(event);
-                handler.borrow_mut().on_error(error);
+                layers.on_error(&mut **handler.borrow_mut(), error);
             });
         self.socket.set_onerror(Some(onerror.as_ref().unchecked_ref()));
         onerror.forget();
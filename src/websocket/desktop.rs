@@ -1,16 +1,69 @@
 use crate::{Error, Result};
+use crate::websocket::layer::Layers;
 use crate::websocket::{CloseCode, Message, Handler, Sender as WebSocketSender};
+use crate::websocket::proxy::{self, Proxy};
+use crate::websocket::rate_limit::Delayed;
+use crate::websocket::stats::Recorder;
+use failure::{bail, format_err};
+use crate::clock;
 use std::borrow::Cow;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use url::Url;
 
-#[inline]
-pub(crate) fn connect<T: Handler + 'static>(url: &str) -> Result<()> {
+pub(crate) fn connect<T, F>(url: &str, proxy: Option<&Proxy>, new: F) -> Result<()>
+where
+    T: Handler + 'static,
+    F: FnOnce(WebSocketSender) -> T,
+{
+    let (url, host) = match proxy {
+        Some(proxy) => {
+            let mut url = Url::parse(url)?;
+            if url.scheme() != "ws" {
+                bail!("Only ws urls can be connected to through a proxy");
+            }
+            let host = url
+                .host_str()
+                .ok_or_else(|| format_err!("Url {} has no host", url))?
+                .to_string();
+            let port = url.port_or_known_default().unwrap_or(80);
+            let tunnel = proxy.tunnel(host.trim_start_matches('[').trim_end_matches(']'), port)?;
+            let local = proxy::relay(tunnel)?;
+            url.set_host(Some("127.0.0.1"))?;
+            url.set_port(Some(local.port()))
+                .map_err(|_| format_err!("Can't set port of {}", url))?;
+            (url.to_string(), Some(format!("{}:{}", host, port)))
+        }
+        None => (url.to_string(), None),
+    };
+    let mut new = Some(new);
     ws::connect(url, |out| {
-        WsHandler(T::new(WebSocketSender::new(Sender(out))))
+        let stats = Recorder::default();
+        let layers = Layers::default();
+        let delayed = Arc::new(Mutex::new(Delayed::default()));
+        let sender = Sender {
+            out: out.clone(),
+            delayed: Arc::clone(&delayed),
+        };
+        let sender = WebSocketSender::new(sender, stats.clone(), layers.clone());
+        // A client connection calls the factory once.
+        let new = new.take().expect("handler already created");
+        WsHandler {
+            handler: new(sender),
+            layers,
+            stats,
+            out,
+            delayed,
+            host: host.clone(),
+        }
     })?;
     Ok(())
 }
 
-pub struct Sender(ws::Sender);
+pub struct Sender {
+    out: ws::Sender,
+    delayed: Arc<Mutex<Delayed>>,
+}
 
 impl Sender {
     #[inline]
@@ -20,27 +73,73 @@ impl Sender {
         reason: impl Into<Cow<'static, str>>,
     ) -> Result<()> {
         let code: u16 = code.into();
-        self.0.close_with_reason(ws::CloseCode::from(code), reason)?;
+        self.out.close_with_reason(ws::CloseCode::from(code), reason)?;
         Ok(())
     }
 
     #[inline]
     pub fn send(&self, msg: impl Into<Message>) -> Result<()> {
-        let msg = match msg.into() {
-            Message::Text(txt) => ws::Message::Text(txt),
-            Message::Binary(bin) => ws::Message::Binary(bin),
-        };
-        self.0.send(msg)?;
+        self.out.send(to_ws_message(msg.into()))?;
+        Ok(())
+    }
+
+    /// Returns true if delayed messages are waiting to be sent.
+    pub(crate) fn has_delayed(&self) -> bool {
+        !self.delayed.lock().unwrap_or_else(|err| err.into_inner()).is_empty()
+    }
+
+    /// Sends the message after `delay` and the waiting delayed messages,
+    /// without blocking the event loop. The timeout is handled by
+    /// `WsHandler::on_timeout`, its token is the sequence number of the
+    /// message.
+    pub(crate) fn send_delayed(&self, msg: Message, delay: Duration) -> Result<()> {
+        let (seq, delay) = self
+            .delayed
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .push(msg, delay);
+        let millis = clock::millis(delay).ceil() as u64;
+        self.out.timeout(millis, ws::util::Token(seq))?;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn ping(&self) -> Result<()> {
+        self.out.ping(ping_payload())?;
         Ok(())
     }
 }
 
-struct WsHandler<T: Handler>(T);
+struct WsHandler<T: Handler> {
+    handler: T,
+    layers: Layers,
+    stats: Recorder,
+    out: ws::Sender,
+    delayed: Arc<Mutex<Delayed>>,
+    /// Overrides the `Host` header when connecting through a proxy.
+    host: Option<String>,
+}
 
 impl<T: Handler> ws::Handler for WsHandler<T> {
+    #[inline]
+    fn build_request(&mut self, url: &ws_url::Url) -> ws::Result<ws::Request> {
+        let mut request = ws::Request::from_url(url)?;
+        if let Some(host) = &self.host {
+            for (name, value) in request.headers_mut() {
+                if name.eq_ignore_ascii_case("host") {
+                    *value = host.clone().into_bytes();
+                }
+            }
+        }
+        Ok(request)
+    }
+
     #[inline]
     fn on_open(&mut self, _: ws::Handshake) -> ws::Result<()> {
-        self.0.on_open().map_err(|err| to_ws_error(err, "on_open error"))
+        self.stats.on_open();
+        self.layers
+            .on_open(&mut self.handler)
+            .map_err(|err| to_ws_error(err, "on_open error"))
     }
 
     #[inline]
@@ -49,18 +148,61 @@ impl<T: Handler> ws::Handler for WsHandler<T> {
             ws::Message::Text(txt) => Message::Text(txt),
             ws::Message::Binary(bin) => Message::Binary(bin),
         };
-        self.0.on_message(msg).map_err(|err| to_ws_error(err, "on_message error"))
+        self.stats.on_message(&msg);
+        self.layers
+            .on_message(&mut self.handler, msg)
+            .map_err(|err| to_ws_error(err, "on_message error"))
     }
 
     #[inline]
     fn on_close(&mut self, code: ws::CloseCode, reason: &str) {
         let code: u16 = code.into();
-        self.0.on_close(CloseCode::from(code), reason);
+        let code = CloseCode::from(code);
+        self.stats.on_close(code, reason, false);
+        self.layers.on_close(&mut self.handler, code, reason);
+    }
+
+    #[inline]
+    fn on_frame(&mut self, frame: ws::Frame) -> ws::Result<Option<ws::Frame>> {
+        if frame.opcode() == ws::OpCode::Pong {
+            if let Some(round_trip) = round_trip(frame.payload()) {
+                self.stats.on_round_trip(round_trip);
+            }
+        }
+        if frame.has_rsv1() || frame.has_rsv2() || frame.has_rsv3() {
+            Err(ws::Error::new(
+                ws::ErrorKind::Protocol,
+                "Encountered frame with reserved bits set.",
+            ))
+        } else {
+            Ok(Some(frame))
+        }
+    }
+
+    fn on_timeout(&mut self, token: ws::util::Token) -> ws::Result<()> {
+        let msgs = self
+            .delayed
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .pop_due(token.0);
+        for msg in msgs {
+            self.stats.on_send(&msg);
+            self.out.send(to_ws_message(msg))?;
+        }
+        Ok(())
     }
 
     #[inline]
     fn on_error(&mut self, error: ws::Error) {
-        self.0.on_error(error.into());
+        self.layers.on_error(&mut self.handler, error.into());
+    }
+}
+
+#[inline]
+fn to_ws_message(msg: Message) -> ws::Message {
+    match msg {
+        Message::Text(txt) => ws::Message::Text(txt),
+        Message::Binary(bin) => ws::Message::Binary(bin),
     }
 }
 
@@ -68,3 +210,244 @@ impl<T: Handler> ws::Handler for WsHandler<T> {
 fn to_ws_error(error: Error, msg: &'static str) -> ws::Error {
     ws::Error::new(ws::ErrorKind::Custom(Box::new(error.compat())), msg)
 }
+
+/// Encodes the current time as a ping payload.
+fn ping_payload() -> Vec<u8> {
+    clock::now().to_bits().to_be_bytes().to_vec()
+}
+
+/// Decodes a pong payload created by `ping_payload` into a round trip time.
+fn round_trip(payload: &[u8]) -> Option<Duration> {
+    if payload.len() != 8 {
+        return None;
+    }
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(payload);
+    let sent = f64::from_bits(u64::from_be_bytes(bytes));
+    let elapsed = clock::now() - sent;
+    if elapsed.is_finite() && elapsed >= 0.0 {
+        Some(clock::from_millis(elapsed))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::websocket::{
+        connect, connect_with, CloseCode, Handler, Layer, Layered, Message, Metrics, RateLimit,
+        Sender, Throttle,
+    };
+    use crate::Result;
+    use super::{ping_payload, round_trip};
+    use std::sync::Mutex;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    fn echo_server() -> u16 {
+        struct Echo(ws::Sender);
+
+        impl ws::Handler for Echo {
+            fn on_message(&mut self, msg: ws::Message) -> ws::Result<()> {
+                self.0.send(msg)
+            }
+        }
+
+        let server = ws::WebSocket::new(Echo).unwrap().bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        thread::spawn(move || server.run().unwrap());
+        port
+    }
+
+    /// The time `on_open` took, the number of messages sent when it returned
+    /// and the time until the last echo arrived.
+    static DELAYED: Mutex<Option<(Duration, u64, Duration)>> = Mutex::new(None);
+
+    struct Delayed {
+        sender: Sender,
+        opened: Option<Instant>,
+        open: Duration,
+        sent: u64,
+        received: u32,
+    }
+
+    impl Handler for Delayed {
+        fn new(mut sender: Sender) -> Self {
+            sender.set_rate_limit(Some(RateLimit {
+                burst: 1,
+                interval: Duration::from_millis(50),
+                throttle: Throttle::Delay,
+            }));
+            Delayed {
+                sender,
+                opened: None,
+                open: Duration::from_millis(0),
+                sent: 0,
+                received: 0,
+            }
+        }
+
+        fn on_open(&mut self) -> Result<()> {
+            let start = Instant::now();
+            for i in 0..3 {
+                self.sender.send(format!("{}", i))?;
+            }
+            self.open = start.elapsed();
+            self.sent = self.sender.stats().sent().messages;
+            self.opened = Some(start);
+            Ok(())
+        }
+
+        fn on_message(&mut self, msg: Message) -> Result<()> {
+            assert_eq!(msg, Message::text(format!("{}", self.received)));
+            self.received += 1;
+            if self.received == 3 {
+                let elapsed = self.opened.unwrap().elapsed();
+                *DELAYED.lock().unwrap() = Some((self.open, self.sent, elapsed));
+                self.sender.close(CloseCode::Normal)?;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_send_delayed() {
+        let port = echo_server();
+        connect::<Delayed>(format!("ws://127.0.0.1:{}", port)).unwrap();
+        let (open, sent, elapsed) = DELAYED.lock().unwrap().take().unwrap();
+        assert!(open < Duration::from_millis(50), "{:?}", open);
+        assert_eq!(sent, 1);
+        assert!(elapsed >= Duration::from_millis(95), "{:?}", elapsed);
+    }
+
+    static LOG: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    fn log(entry: String) {
+        LOG.lock().unwrap().push(entry);
+    }
+
+    /// A layer recording the events passing through it.
+    struct Record(&'static str);
+
+    impl Layer for Record {
+        fn on_open(&self) -> Result<()> {
+            log(format!("{} open", self.0));
+            Ok(())
+        }
+
+        fn on_message(&self, msg: Message) -> Result<Option<Message>> {
+            log(format!("{} message {:?}", self.0, msg));
+            Ok(Some(msg))
+        }
+
+        fn on_close(&self, _code: CloseCode, _reason: &str) {
+            log(format!("{} close", self.0));
+        }
+
+        fn on_send(&self, msg: Message) -> Result<Option<Message>> {
+            log(format!("{} send {:?}", self.0, msg));
+            match msg {
+                Message::Text(text) => Ok(Some(Message::text(format!("{} {}", text, self.0)))),
+                msg => Ok(Some(msg)),
+            }
+        }
+    }
+
+    #[derive(Default)]
+    struct Outer;
+
+    impl Layer for Outer {
+        fn on_open(&self) -> Result<()> {
+            Record("outer").on_open()
+        }
+
+        fn on_message(&self, msg: Message) -> Result<Option<Message>> {
+            Record("outer").on_message(msg)
+        }
+
+        fn on_send(&self, msg: Message) -> Result<Option<Message>> {
+            Record("outer").on_send(msg)
+        }
+    }
+
+    struct Ping(Sender);
+
+    impl Handler for Ping {
+        fn new(sender: Sender) -> Self {
+            Ping(sender)
+        }
+
+        fn on_open(&mut self) -> Result<()> {
+            log("handler open".into());
+            self.0.send("ping")
+        }
+
+        fn on_message(&mut self, msg: Message) -> Result<()> {
+            log(format!("handler message {:?}", msg));
+            self.0.close(CloseCode::Normal)
+        }
+
+        fn on_close(&mut self, _code: CloseCode, _reason: &str) {
+            log("handler close".into());
+        }
+    }
+
+    #[test]
+    fn test_layers() {
+        let port = echo_server();
+        connect_with(format!("ws://127.0.0.1:{}", port), |sender| {
+            let mut handler = Layered::<Outer, Ping>::new(sender);
+            handler.handler_mut().0.add_layer(Record("inner"));
+            handler
+        })
+        .unwrap();
+        assert_eq!(
+            *LOG.lock().unwrap(),
+            vec![
+                "outer open",
+                "inner open",
+                "handler open",
+                "inner send Text(\"ping\")",
+                "outer send Text(\"ping inner\")",
+                "outer message Text(\"ping inner outer\")",
+                "inner message Text(\"ping inner outer\")",
+                "handler message Text(\"ping inner outer\")",
+                "inner close",
+                "handler close",
+            ]
+        );
+    }
+
+    static SENT: Mutex<Option<u64>> = Mutex::new(None);
+
+    struct Dropping(Sender);
+
+    impl Handler for Dropping {
+        fn new(mut sender: Sender) -> Self {
+            sender.set_rate_limit(Some(RateLimit::per_second(1, Throttle::Drop)));
+            Dropping(sender)
+        }
+
+        fn on_open(&mut self) -> Result<()> {
+            for _ in 0..3 {
+                self.0.send("ping")?;
+            }
+            *SENT.lock().unwrap() = self.0.layer::<Metrics>().map(|m| m.messages_sent());
+            self.0.close(CloseCode::Normal)
+        }
+    }
+
+    #[test]
+    fn test_metrics_skip_dropped() {
+        let port = echo_server();
+        connect::<Layered<Metrics, Dropping>>(format!("ws://127.0.0.1:{}", port)).unwrap();
+        assert_eq!(SENT.lock().unwrap().take(), Some(1));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let payload = ping_payload();
+        assert!(round_trip(&payload).is_some());
+        assert!(round_trip(b"ping").is_none());
+    }
+}
@@ -0,0 +1,304 @@
+use crate::{Error, Result};
+use crate::websocket::{CloseCode, Handler, Message, Sender};
+use log::*;
+use std::any::Any;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A middleware intercepting the events of a WebSocket connection.
+///
+/// Layers are added with `Sender::add_layer`, or with the `Layered` handler
+/// for layers implementing `Default`. Incoming events pass through the
+/// layers in the order they were added before reaching the handler,
+/// outgoing messages pass through them in reverse order.
+pub trait Layer: Any + Send + Sync {
+    /// Called before the handler's `on_open`. Returning an error skips the
+    /// handler.
+    fn on_open(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called before the handler's `on_message`. Returning `None` swallows the
+    /// message.
+    fn on_message(&self, msg: Message) -> Result<Option<Message>> {
+        Ok(Some(msg))
+    }
+
+    /// Called before the handler's `on_close`.
+    fn on_close(&self, _code: CloseCode, _reason: &str) {}
+
+    /// Called before the handler's `on_error`. Returning `None` swallows the
+    /// error.
+    fn on_error(&self, err: Error) -> Option<Error> {
+        Some(err)
+    }
+
+    /// Called on outgoing messages that passed the rate limit, before they
+    /// are sent. Returning `None` swallows the message.
+    fn on_send(&self, msg: Message) -> Result<Option<Message>> {
+        Ok(Some(msg))
+    }
+}
+
+/// The layers of a connection, shared by its `Sender` and the backend
+/// dispatching the events.
+///
+/// Events are dispatched to a snapshot of the layers, so layers can send
+/// messages or add layers without deadlocking.
+#[derive(Clone, Default)]
+pub(crate) struct Layers(Arc<Mutex<Snapshot>>);
+
+type Snapshot = Arc<Vec<Arc<dyn Layer>>>;
+
+impl Layers {
+    fn snapshot(&self) -> Snapshot {
+        Arc::clone(&self.0.lock().unwrap_or_else(|err| err.into_inner()))
+    }
+
+    pub(crate) fn push(&self, layer: Arc<dyn Layer>) {
+        let mut layers = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        let mut next = Vec::clone(&layers);
+        next.push(layer);
+        *layers = Arc::new(next);
+    }
+
+    pub(crate) fn get<L: Layer>(&self) -> Option<Arc<L>> {
+        self.snapshot().iter().find_map(|layer| {
+            let layer: Arc<dyn Any + Send + Sync> = layer.clone();
+            layer.downcast::<L>().ok()
+        })
+    }
+
+    pub(crate) fn on_open(&self, handler: &mut impl Handler) -> Result<()> {
+        for layer in self.snapshot().iter() {
+            layer.on_open()?;
+        }
+        handler.on_open()
+    }
+
+    pub(crate) fn on_message(&self, handler: &mut impl Handler, mut msg: Message) -> Result<()> {
+        for layer in self.snapshot().iter() {
+            match layer.on_message(msg)? {
+                Some(next) => msg = next,
+                None => return Ok(()),
+            }
+        }
+        handler.on_message(msg)
+    }
+
+    pub(crate) fn on_close(&self, handler: &mut impl Handler, code: CloseCode, reason: &str) {
+        for layer in self.snapshot().iter() {
+            layer.on_close(code, reason);
+        }
+        handler.on_close(code, reason);
+    }
+
+    pub(crate) fn on_error(&self, handler: &mut impl Handler, mut err: Error) {
+        for layer in self.snapshot().iter() {
+            match layer.on_error(err) {
+                Some(next) => err = next,
+                None => return,
+            }
+        }
+        handler.on_error(err);
+    }
+
+    pub(crate) fn on_send(&self, mut msg: Message) -> Result<Option<Message>> {
+        for layer in self.snapshot().iter().rev() {
+            match layer.on_send(msg)? {
+                Some(next) => msg = next,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(msg))
+    }
+}
+
+/// A handler wrapping the handler `H` with the layer `L`, created with
+/// `L::default()`.
+///
+/// Layers that need configuration, like an authentication layer holding a
+/// token, are added with `Sender::add_layer` instead, for example in a
+/// handler created by `connect_with`.
+///
+/// ```ignore
+/// connect::<Layered<Logging, Layered<Metrics, MyHandler>>>(url)?;
+/// ```
+pub struct Layered<L: Layer, H: Handler> {
+    layer: Arc<L>,
+    handler: H,
+}
+
+impl<L: Layer, H: Handler> Layered<L, H> {
+    /// Returns a reference to the layer.
+    #[inline]
+    pub fn layer(&self) -> &L {
+        &self.layer
+    }
+
+    /// Returns a reference to the wrapped handler.
+    #[inline]
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    /// Returns a mutable reference to the wrapped handler.
+    #[inline]
+    pub fn handler_mut(&mut self) -> &mut H {
+        &mut self.handler
+    }
+}
+
+/// The layer is added to the connection, which dispatches the events to it,
+/// so the callbacks only forward to the wrapped handler.
+impl<L: Layer + Default, H: Handler> Handler for Layered<L, H> {
+    fn new(sender: Sender) -> Self {
+        let layer = Arc::new(L::default());
+        sender.layers.push(layer.clone());
+        Layered {
+            layer,
+            handler: H::new(sender),
+        }
+    }
+
+    fn on_open(&mut self) -> Result<()> {
+        self.handler.on_open()
+    }
+
+    fn on_message(&mut self, msg: Message) -> Result<()> {
+        self.handler.on_message(msg)
+    }
+
+    fn on_close(&mut self, code: CloseCode, reason: &str) {
+        self.handler.on_close(code, reason);
+    }
+
+    fn on_error(&mut self, err: Error) {
+        self.handler.on_error(err);
+    }
+}
+
+/// A layer logging all events using the `log` crate.
+///
+/// Errors are logged at debug level and passed on, since the handler's
+/// `on_error` logs them as errors by default.
+#[derive(Debug, Default)]
+pub struct Logging;
+
+impl Layer for Logging {
+    fn on_open(&self) -> Result<()> {
+        info!("Connection open");
+        Ok(())
+    }
+
+    fn on_message(&self, msg: Message) -> Result<Option<Message>> {
+        debug!("Received message {:?}", msg);
+        Ok(Some(msg))
+    }
+
+    fn on_close(&self, code: CloseCode, reason: &str) {
+        info!("Connection closing due to ({:?}) {}", code, reason);
+    }
+
+    fn on_error(&self, err: Error) -> Option<Error> {
+        debug!("Connection error {:?}", err);
+        Some(err)
+    }
+
+    fn on_send(&self, msg: Message) -> Result<Option<Message>> {
+        debug!("Sending message {:?}", msg);
+        Ok(Some(msg))
+    }
+}
+
+/// A layer counting messages, bytes and errors.
+///
+/// Handlers can access the counters using `Sender::layer::<Metrics>()`.
+/// Messages dropped or rejected by the rate limiter are not counted.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    messages_sent: AtomicU64,
+    messages_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    errors: AtomicU64,
+}
+
+impl Metrics {
+    /// Number of messages sent.
+    #[inline]
+    pub fn messages_sent(&self) -> u64 {
+        self.messages_sent.load(Ordering::Relaxed)
+    }
+
+    /// Number of messages received.
+    #[inline]
+    pub fn messages_received(&self) -> u64 {
+        self.messages_received.load(Ordering::Relaxed)
+    }
+
+    /// Number of payload bytes sent.
+    #[inline]
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent.load(Ordering::Relaxed)
+    }
+
+    /// Number of payload bytes received.
+    #[inline]
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received.load(Ordering::Relaxed)
+    }
+
+    /// Number of errors.
+    #[inline]
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+}
+
+impl Layer for Metrics {
+    fn on_message(&self, msg: Message) -> Result<Option<Message>> {
+        self.messages_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received.fetch_add(msg.len() as u64, Ordering::Relaxed);
+        Ok(Some(msg))
+    }
+
+    fn on_error(&self, err: Error) -> Option<Error> {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        Some(err)
+    }
+
+    fn on_send(&self, msg: Message) -> Result<Option<Message>> {
+        self.messages_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(msg.len() as u64, Ordering::Relaxed);
+        Ok(Some(msg))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use failure::format_err;
+
+    #[test]
+    fn test_metrics() {
+        let metrics = Metrics::default();
+        metrics.on_message(Message::text("ping")).unwrap();
+        metrics.on_send(Message::binary(&b"pong!"[..])).unwrap();
+        metrics.on_send(Message::text("")).unwrap();
+        metrics.on_error(format_err!("error"));
+        assert_eq!(metrics.messages_received(), 1);
+        assert_eq!(metrics.bytes_received(), 4);
+        assert_eq!(metrics.messages_sent(), 2);
+        assert_eq!(metrics.bytes_sent(), 5);
+        assert_eq!(metrics.errors(), 1);
+    }
+
+    #[test]
+    fn test_logging_passes_through() {
+        let logging = Logging;
+        let msg = logging.on_message(Message::text("ping")).unwrap();
+        assert_eq!(msg, Some(Message::text("ping")));
+        assert!(logging.on_error(format_err!("error")).is_some());
+    }
+}
@@ -1,13 +1,31 @@
 //! The WebSocket API
+use crate::url::IntoUrl;
 use crate::{Error, Result};
+use failure::{bail, Fail};
 use log::*;
 use self::CloseCode::*;
 use std::borrow::Cow;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[cfg(target_arch = "wasm32")]
 mod browser;
 #[cfg(not(target_arch = "wasm32"))]
 mod desktop;
+mod layer;
+#[cfg(not(target_arch = "wasm32"))]
+mod proxy;
+mod rate_limit;
+mod stats;
+
+pub use self::layer::{Layer, Layered, Logging, Metrics};
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::proxy::{Proxy, ProxyKind};
+pub use self::rate_limit::{RateLimit, RateLimitExceeded, RateLimiter, Throttle};
+pub use self::stats::{Close, Counter, Stats, StatsSink};
+use self::layer::Layers;
+use self::stats::Recorder;
 
 #[cfg(target_arch = "wasm32")]
 type InnerSender = browser::Sender;
@@ -15,28 +33,153 @@ type InnerSender = browser::Sender;
 type InnerSender = desktop::Sender;
 
 /// Create a new WebSocket connection to url.
-pub fn connect<T: Handler + 'static>(url: &str) -> Result<()> {
+///
+/// Fails without connecting if url is invalid or its scheme is not `ws` or
+/// `wss`. On desktop the proxy configured in the environment is used for `ws`
+/// urls, see `Proxy::from_env`. `wss` urls are connected to directly since
+/// TLS through a proxy tunnel is not supported, and an invalid proxy variable
+/// is logged and ignored.
+pub fn connect<T: Handler + 'static>(url: impl IntoUrl) -> Result<()> {
+    connect_with(url, T::new)
+}
+
+/// Create a new WebSocket connection to url with the handler returned by
+/// `new` instead of `Handler::new`.
+///
+/// The closure can capture configuration, for example to add layers with
+/// `Sender::add_layer`.
+///
+/// ```ignore
+/// connect_with(url, move |mut sender| {
+///     sender.add_layer(Auth::new(token));
+///     MyHandler::new(sender)
+/// })?;
+/// ```
+pub fn connect_with<T, F>(url: impl IntoUrl, new: F) -> Result<()>
+where
+    T: Handler + 'static,
+    F: FnOnce(Sender) -> T + 'static,
+{
+    let url = websocket_url(url)?;
     #[cfg(target_arch = "wasm32")]
-    browser::connect::<T>(url)?;
+    browser::connect(&url, new)?;
     #[cfg(not(target_arch = "wasm32"))]
-    desktop::connect::<T>(url)?;
+    desktop::connect(&url, env_proxy(&url, |name| std::env::var(name).ok()).as_ref(), new)?;
     Ok(())
 }
 
+/// Returns the proxy from the environment variables looked up with `lookup`
+/// to connect to `url` through, if it can be used.
+#[cfg(not(target_arch = "wasm32"))]
+fn env_proxy(url: &str, lookup: impl Fn(&str) -> Option<String>) -> Option<Proxy> {
+    match Proxy::from_env_with(url, lookup) {
+        Ok(Some(_)) if url.starts_with("wss:") => {
+            warn!("Connecting to {} without proxy, TLS through a proxy is not supported", url);
+            None
+        }
+        Ok(proxy) => proxy,
+        Err(e) => {
+            warn!("Ignoring invalid proxy configuration: {}", e);
+            None
+        }
+    }
+}
+
+/// Create a new WebSocket connection to url through a proxy, or directly if
+/// `proxy` is `None`.
+///
+/// Only available on desktop, browsers handle proxies transparently. Fails
+/// for `wss` urls with a proxy, since TLS through the tunnel is not supported.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn connect_with_proxy<T: Handler + 'static>(
+    url: impl IntoUrl,
+    proxy: Option<&Proxy>,
+) -> Result<()> {
+    desktop::connect(&websocket_url(url)?, proxy, T::new)
+}
+
+fn websocket_url(url: impl IntoUrl) -> Result<String> {
+    let url = url.into_url()?;
+    match url.protocol().as_str() {
+        "ws:" | "wss:" => Ok(url.href()),
+        _ => bail!("Invalid WebSocket URL '{}': the scheme must be ws or wss", url),
+    }
+}
+
 /// A representation of the output of the WebSocket connection. Use this to send
 /// messages to the other endpoint.
 pub struct Sender {
     inner: InnerSender,
+    limiter: Option<RateLimiter>,
+    layers: Layers,
+    stats: Recorder,
 }
 
 impl Sender {
     #[inline]
-    pub(crate) fn new(inner: InnerSender) -> Self {
+    pub(crate) fn new(inner: InnerSender, stats: Recorder, layers: Layers) -> Self {
         Sender {
             inner,
+            limiter: None,
+            layers,
+            stats,
         }
     }
 
+    /// Returns a snapshot of the connection statistics.
+    pub fn stats(&self) -> Stats {
+        self.stats.stats()
+    }
+
+    /// Sets a sink that receives the connection statistics every time they
+    /// change.
+    ///
+    /// The sink may read the statistics, but must not send messages or
+    /// change the sink, which would deadlock.
+    pub fn set_stats_sink(&self, sink: impl StatsSink + 'static) {
+        self.stats.set_sink(Some(Box::new(sink)));
+    }
+
+    /// Removes the statistics sink.
+    pub fn clear_stats_sink(&self) {
+        self.stats.set_sink(None);
+    }
+
+    /// Records a round trip time measured by the application, for example
+    /// using application level ping messages in the browser.
+    pub fn record_round_trip(&self, round_trip: Duration) {
+        self.stats.on_round_trip(round_trip);
+    }
+
+    /// Sends a ping frame. The round trip time is recorded in the statistics
+    /// when the pong arrives.
+    ///
+    /// Browsers don't expose ping frames, so this returns an error in the
+    /// browser.
+    pub fn ping(&self) -> Result<()> {
+        #[cfg(target_arch = "wasm32")]
+        failure::bail!("Ping frames are not supported in the browser");
+        #[cfg(not(target_arch = "wasm32"))]
+        self.inner.ping()
+    }
+
+    /// Returns the layer of type `L` if it was added to the connection.
+    pub fn layer<L: Layer>(&self) -> Option<Arc<L>> {
+        self.layers.get::<L>()
+    }
+
+    /// Adds a layer to the connection. It receives the events after the
+    /// layers added before it and the outgoing messages before them.
+    pub fn add_layer(&mut self, layer: impl Layer) {
+        self.layers.push(Arc::new(layer));
+    }
+
+    /// Limits the rate of outgoing messages. Passing `None` removes the
+    /// rate limit.
+    pub fn set_rate_limit(&mut self, limit: Option<RateLimit>) {
+        self.limiter = limit.map(RateLimiter::new);
+    }
+
     /// Send a close code to the other endpoint.
     #[inline]
     pub fn close(&self, code: CloseCode) -> Result<()> {
@@ -44,12 +187,19 @@ impl Sender {
     }
 
     /// Send a close code and provide a descriptive reason for closing.
+    ///
+    /// Only `CloseCode::Normal` and codes in the range 3000-4999 may be sent
+    /// and the reason may be at most 123 bytes long, otherwise an
+    /// `InvalidClose` error is returned.
     #[inline]
     pub fn close_with_reason(
         &self,
         code: CloseCode,
         reason: impl Into<Cow<'static, str>>,
     ) -> Result<()> {
+        let reason = reason.into();
+        validate_close(code, &reason)?;
+        self.stats.on_close(code, &reason, true);
         #[cfg(target_arch = "wasm32")]
         self.inner.borrow().close_with_reason(code, reason)?;
         #[cfg(not(target_arch = "wasm32"))]
@@ -58,14 +208,61 @@ impl Sender {
     }
 
     /// Send a message over the connection.
-    #[inline]
+    ///
+    /// If a rate limit is set, messages exceeding it are delayed, dropped or
+    /// rejected with a `RateLimitExceeded` error. Messages that aren't
+    /// dropped or rejected pass through the `on_send` of all layers. Delayed
+    /// messages keep their order, later messages wait until they are sent.
     pub fn send(&self, msg: impl Into<Message>) -> Result<()> {
+        let mut delay = Duration::from_millis(0);
+        if let Some(limiter) = &self.limiter {
+            match limiter.limit().throttle {
+                Throttle::Delay => delay = limiter.reserve(),
+                Throttle::Drop => {
+                    if !limiter.try_acquire() {
+                        debug!("Rate limit exceeded, dropping message");
+                        return Ok(());
+                    }
+                }
+                Throttle::Error => {
+                    if !limiter.try_acquire() {
+                        return Err(RateLimitExceeded.into());
+                    }
+                }
+            }
+        }
+        let msg = match self.layers.on_send(msg.into())? {
+            Some(msg) => msg,
+            None => return Ok(()),
+        };
+        // Messages can't overtake delayed ones.
+        if delay > Duration::from_millis(0) || self.has_delayed() {
+            return self.send_delayed(msg, delay);
+        }
+        self.stats.on_send(&msg);
         #[cfg(target_arch = "wasm32")]
         self.inner.borrow().send(msg)?;
         #[cfg(not(target_arch = "wasm32"))]
         self.inner.send(msg)?;
         Ok(())
     }
+
+    fn has_delayed(&self) -> bool {
+        #[cfg(target_arch = "wasm32")]
+        return self.inner.borrow().has_delayed();
+        #[cfg(not(target_arch = "wasm32"))]
+        return self.inner.has_delayed();
+    }
+
+    fn send_delayed(&self, msg: Message, delay: Duration) -> Result<()> {
+        debug!("Delaying message by at least {:?}", delay);
+        // The statistics are recorded when the message is actually sent.
+        #[cfg(target_arch = "wasm32")]
+        browser::send_delayed(&self.inner, msg, delay, self.stats.clone())?;
+        #[cfg(not(target_arch = "wasm32"))]
+        self.inner.send_delayed(msg, delay)?;
+        Ok(())
+    }
 }
 
 /// Implementing this trait provides the business logic of the WebSocket
@@ -100,13 +297,13 @@ pub trait Handler {
         error!("{:?}", err);
         if !log_enabled!(Level::Error) {
             println!("Encountered an error: {}", err);
-            println!("Enable a logger to see more information.");
+            println!("Enable a logger like `libweb::console::Logger` to see more information.");
         }
     }
 }
 
 /// An enum representing the various forms of a WebSocket message.
-#[derive(Debug)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub enum Message {
     /// A text WebSocket message
     Text(String),
@@ -130,6 +327,19 @@ impl Message {
     {
         Message::Binary(bin.into())
     }
+
+    /// Returns the length of the payload in bytes.
+    pub fn len(&self) -> usize {
+        match self {
+            Message::Text(txt) => txt.len(),
+            Message::Binary(bin) => bin.len(),
+        }
+    }
+
+    /// Returns true if the payload is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
 impl From<String> for Message {
@@ -217,6 +427,9 @@ pub enum CloseCode {
     /// to a different IP (when multiple targets exist), or reconnect to the same IP
     /// when a user has performed an action.
     Again,
+    /// A private close code in the range 4000-4999, which can be used by
+    /// applications. Use `CloseCode::application` to create it.
+    Application(u16),
     #[doc(hidden)]
     Tls,
     #[doc(hidden)]
@@ -225,9 +438,9 @@ pub enum CloseCode {
     Other(u16),
 }
 
-impl Into<u16> for CloseCode {
-    fn into(self) -> u16 {
-        match self {
+impl From<CloseCode> for u16 {
+    fn from(code: CloseCode) -> u16 {
+        match code {
             Normal => 1000,
             Away => 1001,
             Protocol => 1002,
@@ -243,6 +456,7 @@ impl Into<u16> for CloseCode {
             Again => 1013,
             Tls => 1015,
             Empty => 0,
+            Application(code) => code,
             Other(code) => code,
         }
     }
@@ -266,7 +480,144 @@ impl From<u16> for CloseCode {
             1013 => Again,
             1015 => Tls,
             0 => Empty,
+            4000..=4999 => Application(code),
             _ => Other(code),
         }
     }
 }
+
+impl CloseCode {
+    /// Creates an application close code. `code` must be in the range
+    /// 4000-4999.
+    pub fn application(code: u16) -> Result<CloseCode> {
+        match code {
+            4000..=4999 => Ok(Application(code)),
+            _ => Err(InvalidClose::Code(code).into()),
+        }
+    }
+
+    /// Returns true if the close code may be sent by an endpoint.
+    ///
+    /// Browsers only allow sending `Normal` and codes in the range 3000-4999.
+    pub fn is_sendable(self) -> bool {
+        matches!(u16::from(self), 1000 | 3000..=4999)
+    }
+}
+
+/// The maximum length of a close reason in bytes.
+pub const MAX_CLOSE_REASON_LEN: usize = 123;
+
+/// Error returned when trying to close a connection with an invalid close
+/// code or reason.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum InvalidClose {
+    /// The close code may not be sent by an endpoint.
+    Code(u16),
+    /// The close reason is longer than `MAX_CLOSE_REASON_LEN` bytes.
+    ReasonTooLong(usize),
+}
+
+impl fmt::Display for InvalidClose {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvalidClose::Code(code) => write!(
+                f,
+                "Close code {} is not allowed, use 1000 or 3000-4999",
+                code,
+            ),
+            InvalidClose::ReasonTooLong(len) => write!(
+                f,
+                "Close reason is {} bytes long, the limit is {} bytes",
+                len,
+                MAX_CLOSE_REASON_LEN,
+            ),
+        }
+    }
+}
+
+impl Fail for InvalidClose {}
+
+fn validate_close(code: CloseCode, reason: &str) -> Result<()> {
+    if !code.is_sendable() {
+        return Err(InvalidClose::Code(code.into()).into());
+    }
+    if reason.len() > MAX_CLOSE_REASON_LEN {
+        return Err(InvalidClose::ReasonTooLong(reason.len()).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid_close(code: CloseCode, reason: &str) -> Option<InvalidClose> {
+        validate_close(code, reason)
+            .err()
+            .map(|err| err.downcast::<InvalidClose>().unwrap())
+    }
+
+    #[test]
+    fn test_close_codes() {
+        assert_eq!(invalid_close(Normal, ""), None);
+        assert_eq!(invalid_close(Other(3000), ""), None);
+        assert_eq!(invalid_close(CloseCode::application(4999).unwrap(), ""), None);
+        for code in &[Away, Policy, Status, Abnormal, Tls, Empty, Other(2999), Other(5000)] {
+            let raw: u16 = (*code).into();
+            assert_eq!(invalid_close(*code, ""), Some(InvalidClose::Code(raw)));
+        }
+    }
+
+    #[test]
+    fn test_websocket_url() {
+        assert_eq!(websocket_url("WS://localhost:3012").unwrap(), "ws://localhost:3012/");
+        let url = crate::url::Url::parse("wss://example.com/socket").unwrap();
+        assert_eq!(websocket_url(&url).unwrap(), "wss://example.com/socket");
+        assert!(websocket_url("http://example.com/").is_err());
+        assert!(websocket_url("localhost:3012").is_err());
+    }
+
+    #[test]
+    fn test_application_close_code() {
+        assert_eq!(CloseCode::application(4001).unwrap(), Application(4001));
+        assert_eq!(CloseCode::from(4001), Application(4001));
+        assert_eq!(u16::from(Application(4001)), 4001);
+        assert!(CloseCode::application(3999).is_err());
+        assert!(CloseCode::application(5000).is_err());
+    }
+
+    #[test]
+    fn test_close_reason_length() {
+        let reason = "a".repeat(MAX_CLOSE_REASON_LEN);
+        assert_eq!(invalid_close(Normal, &reason), None);
+        // Multi byte characters count as multiple bytes.
+        let reason = "ä".repeat(62);
+        assert_eq!(invalid_close(Normal, &reason), Some(InvalidClose::ReasonTooLong(124)));
+    }
+
+    #[test]
+    #[cfg(not(target_arch = "wasm32"))]
+    fn test_env_proxy() {
+        let lookup = |name: &str| match name {
+            "https_proxy" => Some("http://127.0.0.1:9".to_string()),
+            "HTTP_PROXY" => Some("ftp://proxy".to_string()),
+            "ALL_PROXY" => Some("socks5://127.0.0.1:1080".to_string()),
+            "NO_PROXY" => Some("localhost".to_string()),
+            _ => None,
+        };
+        // TLS through a proxy is not supported.
+        assert!(env_proxy("wss://example.com/", lookup).is_none());
+        // The invalid proxy is ignored rather than falling back to ALL_PROXY.
+        assert!(env_proxy("ws://example.com/", lookup).is_none());
+        assert!(env_proxy("ws://localhost/", |name| match name {
+            "all_proxy" => Some("socks5://127.0.0.1:1080".to_string()),
+            _ => lookup(name),
+        })
+        .is_none());
+        let proxy = env_proxy("ws://example.com/", |name| match name {
+            "HTTP_PROXY" => None,
+            _ => lookup(name),
+        });
+        assert_eq!(proxy.unwrap().kind, ProxyKind::Socks5);
+    }
+}
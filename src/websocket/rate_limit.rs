@@ -0,0 +1,249 @@
+use crate::clock::{from_millis, millis, now};
+use crate::websocket::Message;
+use failure::Fail;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// What to do with a message that exceeds the rate limit.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Throttle {
+    /// Delay the message until the rate limit allows sending it.
+    ///
+    /// `send` returns immediately and the message is sent later by the event
+    /// loop on desktop or by `setTimeout` in the browser.
+    Delay,
+    /// Silently drop the message.
+    Drop,
+    /// Return a `RateLimitExceeded` error.
+    Error,
+}
+
+/// Configuration of a token bucket rate limiter.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct RateLimit {
+    /// Maximum number of messages that can be sent in a burst.
+    pub burst: u32,
+    /// Time it takes to replenish a single message.
+    pub interval: Duration,
+    /// What to do with messages exceeding the limit.
+    pub throttle: Throttle,
+}
+
+impl RateLimit {
+    /// Allows `messages` messages per second with a burst of `messages`.
+    ///
+    /// Panics if `messages` is zero.
+    pub fn per_second(messages: u32, throttle: Throttle) -> Self {
+        assert!(messages > 0, "rate limit must allow at least one message");
+        RateLimit {
+            burst: messages,
+            interval: Duration::from_secs(1) / messages,
+            throttle,
+        }
+    }
+}
+
+/// Error returned when a message exceeds the rate limit and the throttle is
+/// `Throttle::Error`.
+#[derive(Debug)]
+pub struct RateLimitExceeded;
+
+impl fmt::Display for RateLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Rate limit exceeded")
+    }
+}
+
+impl Fail for RateLimitExceeded {}
+
+/// A token bucket rate limiter.
+///
+/// Besides limiting outgoing messages of a `Sender` it can be used to limit
+/// incoming messages, for example in a desktop server.
+#[derive(Debug)]
+pub struct RateLimiter {
+    limit: RateLimit,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last: f64,
+}
+
+impl RateLimiter {
+    /// Creates a new rate limiter with a full bucket.
+    pub fn new(limit: RateLimit) -> Self {
+        RateLimiter {
+            limit,
+            bucket: Mutex::new(Bucket {
+                tokens: limit.burst as f64,
+                last: now(),
+            }),
+        }
+    }
+
+    /// Returns the rate limit configuration.
+    #[inline]
+    pub fn limit(&self) -> &RateLimit {
+        &self.limit
+    }
+
+    /// Takes a token if one is available.
+    #[inline]
+    pub fn try_acquire(&self) -> bool {
+        self.try_acquire_at(now())
+    }
+
+    /// Takes a token, going into debt if none is available. Returns how long
+    /// to wait before the message may be sent.
+    #[inline]
+    pub fn reserve(&self) -> Duration {
+        self.reserve_at(now())
+    }
+
+    fn try_acquire_at(&self, now: f64) -> bool {
+        let mut bucket = self.bucket.lock().unwrap();
+        self.refill(&mut bucket, now);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn reserve_at(&self, now: f64) -> Duration {
+        let mut bucket = self.bucket.lock().unwrap();
+        self.refill(&mut bucket, now);
+        bucket.tokens -= 1.0;
+        if bucket.tokens >= 0.0 {
+            Duration::from_millis(0)
+        } else {
+            from_millis(-bucket.tokens * millis(self.limit.interval))
+        }
+    }
+
+    fn refill(&self, bucket: &mut Bucket, now: f64) {
+        let elapsed = (now - bucket.last).max(0.0);
+        let tokens = bucket.tokens + elapsed / millis(self.limit.interval);
+        bucket.tokens = tokens.min(self.limit.burst as f64);
+        bucket.last = now;
+    }
+}
+
+/// Messages delayed by `Throttle::Delay`, in the order they were sent.
+///
+/// Every message gets its own timer, identified by the sequence number
+/// returned by `push`. A timer sends its message together with the ones
+/// queued before it and any others that are due, so timers firing out of
+/// order can't reorder the messages.
+#[derive(Debug, Default)]
+pub(crate) struct Delayed {
+    next: usize,
+    queue: VecDeque<(usize, f64, Message)>,
+}
+
+impl Delayed {
+    /// Returns true if no message is waiting.
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Queues the message behind the waiting ones. Returns the sequence
+    /// number of the message and when its timer should fire.
+    #[inline]
+    pub(crate) fn push(&mut self, msg: Message, delay: Duration) -> (usize, Duration) {
+        self.push_at(now(), msg, delay)
+    }
+
+    /// Removes the messages to send when the timer of message `seq` fires.
+    #[inline]
+    pub(crate) fn pop_due(&mut self, seq: usize) -> Vec<Message> {
+        self.pop_due_at(now(), seq)
+    }
+
+    fn push_at(&mut self, now: f64, msg: Message, delay: Duration) -> (usize, Duration) {
+        let mut due = now + millis(delay);
+        if let Some(&(_, last, _)) = self.queue.back() {
+            due = due.max(last);
+        }
+        let seq = self.next;
+        self.next = self.next.wrapping_add(1);
+        self.queue.push_back((seq, due, msg));
+        (seq, from_millis(due - now))
+    }
+
+    fn pop_due_at(&mut self, now: f64, seq: usize) -> Vec<Message> {
+        let mut msgs = Vec::new();
+        while let Some(&(next, due, _)) = self.queue.front() {
+            if next > seq && due > now {
+                break;
+            }
+            msgs.extend(self.queue.pop_front().map(|(_, _, msg)| msg));
+        }
+        msgs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(burst: u32, interval: u64) -> RateLimiter {
+        let limiter = RateLimiter::new(RateLimit {
+            burst,
+            interval: Duration::from_millis(interval),
+            throttle: Throttle::Error,
+        });
+        limiter.bucket.lock().unwrap().last = 0.0;
+        limiter
+    }
+
+    #[test]
+    fn test_try_acquire() {
+        let limiter = limiter(2, 100);
+        assert!(limiter.try_acquire_at(0.0));
+        assert!(limiter.try_acquire_at(0.0));
+        assert!(!limiter.try_acquire_at(50.0));
+        assert!(limiter.try_acquire_at(100.0));
+        assert!(!limiter.try_acquire_at(100.0));
+    }
+
+    #[test]
+    fn test_burst_is_capped() {
+        let limiter = limiter(2, 100);
+        assert!(limiter.try_acquire_at(10_000.0));
+        assert!(limiter.try_acquire_at(10_000.0));
+        assert!(!limiter.try_acquire_at(10_000.0));
+    }
+
+    #[test]
+    fn test_reserve() {
+        let limiter = limiter(1, 100);
+        assert_eq!(limiter.reserve_at(0.0), Duration::from_millis(0));
+        assert_eq!(limiter.reserve_at(0.0), Duration::from_millis(100));
+        assert_eq!(limiter.reserve_at(50.0), Duration::from_millis(150));
+    }
+
+    #[test]
+    fn test_delayed_keeps_order() {
+        let mut delayed = Delayed::default();
+        let text = |txt: &str| Message::text(txt.to_string());
+        let delay = Duration::from_millis;
+        assert_eq!(delayed.push_at(0.0, text("a"), delay(100)), (0, delay(100)));
+        // A message that could be sent right away waits for the earlier one.
+        assert_eq!(delayed.push_at(10.0, text("b"), delay(0)), (1, delay(90)));
+        assert_eq!(delayed.push_at(20.0, text("c"), delay(200)), (2, delay(200)));
+        // The timer of `b` fires first and sends `a` as well.
+        assert_eq!(delayed.pop_due_at(100.0, 1), vec![text("a"), text("b")]);
+        assert_eq!(delayed.pop_due_at(100.0, 0), vec![]);
+        assert!(!delayed.is_empty());
+        assert_eq!(delayed.pop_due_at(220.0, 2), vec![text("c")]);
+        assert!(delayed.is_empty());
+    }
+}
@@ -0,0 +1,222 @@
+use crate::clock::system_time;
+use crate::websocket::{CloseCode, Message};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// Number of messages and payload bytes.
+#[derive(Debug, Default, Eq, PartialEq, Clone, Copy)]
+pub struct Counter {
+    /// Number of messages.
+    pub messages: u64,
+    /// Number of payload bytes.
+    pub bytes: u64,
+}
+
+impl Counter {
+    #[inline]
+    fn add(&mut self, bytes: usize) {
+        self.messages += 1;
+        self.bytes += bytes as u64;
+    }
+}
+
+/// A close frame sent or received on the connection.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Close {
+    /// The close code.
+    pub code: CloseCode,
+    /// The reason for closing.
+    pub reason: String,
+    /// True if the close frame was sent by this endpoint.
+    pub local: bool,
+    /// When the close frame was sent or received.
+    pub time: SystemTime,
+}
+
+/// Statistics of a WebSocket connection.
+#[derive(Debug, Default, Clone)]
+pub struct Stats {
+    /// Text messages sent.
+    pub text_sent: Counter,
+    /// Binary messages sent.
+    pub binary_sent: Counter,
+    /// Text messages received.
+    pub text_received: Counter,
+    /// Binary messages received.
+    pub binary_received: Counter,
+    /// When the connection was opened.
+    pub connected_at: Option<SystemTime>,
+    /// When the last message was sent or received.
+    pub last_activity: Option<SystemTime>,
+    /// The most recently measured round trip time.
+    pub round_trip: Option<Duration>,
+    /// Close frames sent and received.
+    pub closes: Vec<Close>,
+}
+
+impl Stats {
+    /// Total number of messages and bytes sent.
+    pub fn sent(&self) -> Counter {
+        Counter {
+            messages: self.text_sent.messages + self.binary_sent.messages,
+            bytes: self.text_sent.bytes + self.binary_sent.bytes,
+        }
+    }
+
+    /// Total number of messages and bytes received.
+    pub fn received(&self) -> Counter {
+        Counter {
+            messages: self.text_received.messages + self.binary_received.messages,
+            bytes: self.text_received.bytes + self.binary_received.bytes,
+        }
+    }
+}
+
+/// Receives the statistics of a connection every time they change.
+pub trait StatsSink: Send {
+    /// Exports the statistics.
+    fn export(&mut self, stats: &Stats);
+}
+
+impl<F: FnMut(&Stats) + Send> StatsSink for F {
+    fn export(&mut self, stats: &Stats) {
+        self(stats)
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    stats: Stats,
+    /// Incremented on every update, so a sink never receives older
+    /// statistics after newer ones.
+    version: u64,
+}
+
+#[derive(Default)]
+struct Sink {
+    sink: Option<Box<dyn StatsSink>>,
+    version: u64,
+}
+
+/// Records the statistics of a connection. Shared between the `Sender` and
+/// the backend.
+///
+/// The sink has its own lock and is called after the statistics are
+/// unlocked, so it can read them and a slow sink doesn't hold up updates.
+#[derive(Clone, Default)]
+pub(crate) struct Recorder {
+    inner: Arc<Mutex<Inner>>,
+    sink: Arc<Mutex<Sink>>,
+}
+
+impl Recorder {
+    pub fn stats(&self) -> Stats {
+        self.inner.lock().unwrap().stats.clone()
+    }
+
+    pub fn set_sink(&self, sink: Option<Box<dyn StatsSink>>) {
+        self.sink.lock().unwrap().sink = sink;
+    }
+
+    pub fn on_open(&self) {
+        self.update(|stats| stats.connected_at = Some(system_time()));
+    }
+
+    pub fn on_send(&self, msg: &Message) {
+        self.update(|stats| {
+            match msg {
+                Message::Text(_) => stats.text_sent.add(msg.len()),
+                Message::Binary(_) => stats.binary_sent.add(msg.len()),
+            }
+            stats.last_activity = Some(system_time());
+        });
+    }
+
+    pub fn on_message(&self, msg: &Message) {
+        self.update(|stats| {
+            match msg {
+                Message::Text(_) => stats.text_received.add(msg.len()),
+                Message::Binary(_) => stats.binary_received.add(msg.len()),
+            }
+            stats.last_activity = Some(system_time());
+        });
+    }
+
+    pub fn on_close(&self, code: CloseCode, reason: &str, local: bool) {
+        self.update(|stats| {
+            stats.closes.push(Close {
+                code,
+                reason: reason.to_string(),
+                local,
+                time: system_time(),
+            })
+        });
+    }
+
+    pub fn on_round_trip(&self, round_trip: Duration) {
+        self.update(|stats| stats.round_trip = Some(round_trip));
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Stats)) {
+        let (stats, version) = {
+            let mut inner = self.inner.lock().unwrap();
+            f(&mut inner.stats);
+            inner.version += 1;
+            (inner.stats.clone(), inner.version)
+        };
+        let mut sink = self.sink.lock().unwrap();
+        if version <= sink.version {
+            return;
+        }
+        sink.version = version;
+        if let Some(sink) = &mut sink.sink {
+            sink.export(&stats);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recorder() {
+        let recorder = Recorder::default();
+        let exported = Arc::new(Mutex::new(0));
+        let exported2 = exported.clone();
+        recorder.set_sink(Some(Box::new(move |_: &Stats| {
+            *exported2.lock().unwrap() += 1;
+        })));
+        recorder.on_open();
+        recorder.on_send(&Message::text("ping"));
+        recorder.on_message(&Message::binary(&b"pong!"[..]));
+        recorder.on_close(CloseCode::Normal, "bye", true);
+        let stats = recorder.stats();
+        assert!(stats.connected_at.is_some());
+        assert!(stats.last_activity.is_some());
+        assert_eq!(stats.text_sent, Counter { messages: 1, bytes: 4 });
+        assert_eq!(stats.binary_received, Counter { messages: 1, bytes: 5 });
+        assert_eq!(stats.sent(), Counter { messages: 1, bytes: 4 });
+        assert_eq!(stats.received(), Counter { messages: 1, bytes: 5 });
+        assert_eq!(stats.closes.len(), 1);
+        assert_eq!(stats.closes[0].code, CloseCode::Normal);
+        assert!(stats.closes[0].local);
+        assert_eq!(*exported.lock().unwrap(), 4);
+    }
+
+    #[test]
+    fn test_sink_reads_stats() {
+        let recorder = Recorder::default();
+        let recorder2 = recorder.clone();
+        let exported = Arc::new(Mutex::new(Vec::new()));
+        let exported2 = exported.clone();
+        recorder.set_sink(Some(Box::new(move |stats: &Stats| {
+            // Reading the statistics from the sink must not deadlock.
+            let current = recorder2.stats();
+            exported2.lock().unwrap().push((stats.sent().messages, current.sent().messages));
+        })));
+        recorder.on_send(&Message::text("a"));
+        recorder.on_send(&Message::text("b"));
+        assert_eq!(*exported.lock().unwrap(), vec![(1, 1), (2, 2)]);
+    }
+}